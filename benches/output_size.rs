@@ -0,0 +1,79 @@
+//! Counts the bytes a frame takes, with the optimised output versus
+//! the naive one (full move, both colors and a reset for every changed cell),
+//! `cargo bench` to see the table.
+//! Bytes are what matters over a slow connection, so there is no timing here
+
+use std::io::Write;
+use std::time::Instant;
+
+use entropic::draw::*;
+use entropic::gui::*;
+use entropic::input::*;
+use entropic::state::*;
+
+const SCREEN: Dimension = Dimension { width: 160, height: 50 };
+
+fn cells(gui: &Gui) -> Vec<CharCell> {
+    SCREEN.into_iter().map(|pos| gui.buffer.get(pos).clone()).collect()
+}
+
+fn naive(before: Option<&[CharCell]>, after: &[CharCell]) -> usize {
+    let mut out = Vec::new();
+    for (pos, cell) in SCREEN.into_iter().zip(after) {
+        if before.is_some_and(|before| before[SCREEN.offset(pos)] == *cell) {
+            continue;
+        }
+        pos.draw(&mut out).unwrap();
+        cell.draw(&mut out).unwrap();
+    }
+    out.len()
+}
+
+fn frame(gui: &mut Gui, name: &str, input: &[u8], total: &mut (usize, usize)) {
+    let before = cells(gui);
+    let now = Instant::now();
+    for event in EventParser::default().parse(input).collect::<Vec<_>>() {
+        gui.handle(event, now).unwrap();
+    }
+    let mut out = Vec::new();
+    gui.draw(&mut out).unwrap();
+    let naive = naive(Some(&before), &cells(gui));
+    report(name, naive, out.len(), total);
+}
+
+fn report(name: &str, naive: usize, optimised: usize, total: &mut (usize, usize)) {
+    total.0 += naive;
+    total.1 += optimised;
+    let ratio = if naive == 0 { 1.0 } else { optimised as f64 / naive as f64 };
+    let _ = writeln!(std::io::stdout(), "{:<24}{:>10}{:>12}{:>9.1}%", name, naive, optimised, ratio * 100.0);
+}
+
+fn main() {
+    let size = Dimension { width: 64, height: 64 };
+    let mut gui = Gui::new(SCREEN, Picture {
+        size,
+        layers: vec![
+            Layer { name: "Background".into(), pixels: vec![Pixel { r: 0x3f, g: 0x3f, b: 0x3f, a: 0xff }; size.number()].into_boxed_slice() },
+            Layer { name: "Layer 1".into(), pixels: vec![Pixel::default(); size.number()].into_boxed_slice() },
+        ],
+    });
+    let mut total = (0, 0);
+    let _ = writeln!(std::io::stdout(), "{:<24}{:>10}{:>12}{:>10}", "frame", "naive", "optimised", "ratio");
+
+    let mut out = Vec::new();
+    gui.draw(&mut out).unwrap();
+    report("first frame", naive(None, &cells(&gui)), out.len(), &mut total);
+
+    frame(&mut gui, "hover", b"\x1b[<35;20;20M", &mut total);
+    frame(&mut gui, "stroke", b"\x1b[<0;20;20M\x1b[<32;24;21M\x1b[<32;30;22M\x1b[<0;30;22m", &mut total);
+    frame(&mut gui, "color picker", b"\x1b[<0;1;1M\x1b[<0;1;1m\x1b[<0;1;1M\x1b[<0;1;1m", &mut total);
+    frame(&mut gui, "close picker", b"\x1b", &mut total);
+    frame(&mut gui, "scroll", b"\x1b[<65;20;20M", &mut total);
+    frame(&mut gui, "half blocks", b"z", &mut total);
+
+    let mut out = Vec::new();
+    gui.redraw(&mut out).unwrap();
+    report("full repaint", naive(None, &cells(&gui)), out.len(), &mut total);
+
+    report("total", total.0, total.1, &mut (0, 0));
+}
@@ -0,0 +1,220 @@
+use std::{
+    fs,
+    path::Path,
+};
+
+use crate::{
+    draw::Color,
+    gui::Action,
+    scale::Scaler,
+    transform::{Target, Transform},
+    state::{Anchor, Dimension, Pixel, Position},
+};
+
+/// The actions that take nothing, by the names they have on the command line.
+/// These are the same ones the keys are bound to
+const ACTIONS: &[(&str, Action)] = &[
+    ("quit", Action::Quit),
+    ("clear-screen", Action::ClearScreen),
+    ("redraw", Action::Redraw),
+    ("swap-colors", Action::SwapColors),
+    ("next-canvas-mode", Action::NextCanvasMode),
+    ("toggle-dither", Action::ToggleDither),
+    ("toggle-preview", Action::TogglePreview),
+    ("next-tool", Action::NextTool),
+    ("bigger-brush", Action::BiggerBrush),
+    ("smaller-brush", Action::SmallerBrush),
+    ("trim", Action::Trim),
+    ("undo", Action::Undo),
+    ("redo", Action::Redo),
+];
+
+/// The commands that take arguments, with how they're used.
+/// Without the file or the name, the ones that take those open a dialog to ask for it
+const COMMANDS: &[(&str, &str)] = &[
+    ("save", "[<file.entropic>]"),
+    ("resize", "<width> <height> [<anchor>]"),
+    ("crop", "<x> <y> <width> <height>"),
+    ("scale", "<factor> [nearest|scale2x|scale3x]"),
+    ("flip", "horizontal|vertical [picture]"),
+    ("rotate", "<degrees> [picture]"),
+    ("shear", "<x> <y> [picture]"),
+    ("layer", "new [<name>]"),
+    ("export", "[<file.png> [--scale <n>] [--scaler <name>]]"),
+    ("palette", "load [<file.hex>]"),
+    ("fill", "<#rrggbb[aa]>"),
+];
+
+/// Parses a line typed into the command line, without the `:`, into the action to perform
+pub fn parse(line: &str) -> Result<Action, String> {
+    let mut words = line.split_whitespace();
+    let name = words.next().ok_or("no command given")?;
+    let args: Vec<&str> = words.collect();
+    if let Some((_, action)) = ACTIONS.iter().find(|(action, _)| *action == name) {
+        return match args.is_empty() {
+            true => Ok(action.clone()),
+            false => Err(format!("{} takes no arguments", name)),
+        };
+    }
+    Ok(match (name, args.as_slice()) {
+        ("save", []) => Action::Save,
+        ("save", [path]) => Action::SaveAs(path.into()),
+        ("resize", [width, height]) => Action::Resize(Dimension { width: size(width)?, height: size(height)? }, Anchor::TopLeft),
+        ("resize", [width, height, name]) => Action::Resize(Dimension { width: size(width)?, height: size(height)? }, anchor(name)?),
+        ("crop", [x, y, width, height]) => {
+            Action::Crop(Position { x: coordinate(x)?, y: coordinate(y)? }, Dimension { width: size(width)?, height: size(height)? })
+        }
+        ("layer", ["new"]) => Action::NewLayerDialog,
+        ("layer", ["new", layer @ ..]) => Action::NewLayer(layer.join(" ")),
+        ("export", []) => Action::ExportDialog,
+        ("export", args) => export(args)?,
+        ("palette", ["load"]) => Action::PaletteDialog,
+        ("palette", ["load", path]) => Action::LoadPalette(path.into()),
+        ("fill", [color]) => Action::Fill(pixel(color)?),
+        ("flip" | "rotate" | "shear", [args @ .., "picture"]) => transform(name, args, Target::Picture)?,
+        ("flip" | "rotate" | "shear", args) => transform(name, args, Target::Layer)?,
+        ("scale", [factor]) => Action::Scale(self::factor(factor)?, Scaler::Nearest),
+        ("scale", [factor, name]) => Action::Scale(self::factor(factor)?, scaler(name)?),
+        _ => match COMMANDS.iter().any(|(command, _)| *command == name) {
+            true => return Err(usage(name)),
+            false => return Err(format!("unknown command: {}", name)),
+        },
+    })
+}
+
+/// The lines the given one could be completed to, sorted. The commands are completed
+/// with a space after the ones that take arguments, and the files with a slash after the directories
+pub fn complete(line: &str) -> Vec<String> {
+    let (head, word) = match line.rfind(' ') {
+        Some(space) => line.split_at(space + 1),
+        None => ("", line),
+    };
+    let words: Vec<&str> = head.split_whitespace().collect();
+    let mut candidates: Vec<String> = match words.as_slice() {
+        [] => {
+            let actions = ACTIONS.iter().map(|(name, _)| name.to_string());
+            let commands = COMMANDS.iter().map(|(name, _)| format!("{} ", name));
+            actions.chain(commands).filter(|name| name.starts_with(word)).collect()
+        }
+        ["resize", _, _] => Anchor::ALL.iter().map(|anchor| anchor.name().to_string()).filter(|name| name.starts_with(word)).collect(),
+        ["flip"] => vec!["horizontal ".to_string(), "vertical ".to_string()].into_iter().filter(|axis| axis.starts_with(word)).collect(),
+        ["flip", _] | ["rotate", _] | ["shear", _, _] => vec!["picture".to_string()].into_iter().filter(|target| target.starts_with(word)).collect(),
+        ["layer"] => vec!["new ".to_string()].into_iter().filter(|new| new.starts_with(word)).collect(),
+        ["palette"] => vec!["load ".to_string()].into_iter().filter(|load| load.starts_with(word)).collect(),
+        ["scale", _] | ["export", .., "--scaler"] => Scaler::ALL.iter().map(|scaler| scaler.name().to_string()).filter(|name| name.starts_with(word)).collect(),
+        ["export", .., "--scale"] => vec![],
+        ["export", ..] if word.starts_with('-') => {
+            vec!["--scale ".to_string(), "--scaler ".to_string()].into_iter().filter(|option| option.starts_with(word)).collect()
+        }
+        ["save"] | ["export", ..] | ["palette", "load"] => files(word),
+        _ => vec![],
+    };
+    candidates.sort();
+    candidates.into_iter().map(|candidate| format!("{}{}", head, candidate)).collect()
+}
+
+/// The files and the directories the path could be completed to
+fn files(path: &str) -> Vec<String> {
+    let (dir, prefix) = match path.rfind('/') {
+        Some(slash) => path.split_at(slash + 1),
+        None => ("", path),
+    };
+    let entries = match fs::read_dir(if dir.is_empty() { Path::new(".") } else { Path::new(dir) }) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    entries.filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            // hidden files only when asked for
+            if !name.starts_with(prefix) || name.starts_with('.') && !prefix.starts_with('.') {
+                return None;
+            }
+            let slash = if entry.file_type().ok()?.is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", dir, name, slash))
+        })
+        .collect()
+}
+
+fn size(text: &str) -> Result<u16, String> {
+    text.parse().ok().filter(|&size| size > 0).ok_or_else(|| format!("not a size: {}", text))
+}
+
+/// A whole number, a fraction or either of those with an `x` after it, like `2x` or `0.5`
+fn factor(text: &str) -> Result<f64, String> {
+    text.strip_suffix('x').unwrap_or(text).parse().ok()
+        .filter(|factor: &f64| factor.is_finite() && *factor > 0.0)
+        .ok_or_else(|| format!("not a factor: {}", text))
+}
+
+fn scaler(text: &str) -> Result<Scaler, String> {
+    Scaler::ALL.iter().copied().find(|scaler| scaler.name() == text).ok_or_else(|| format!("not a scaler: {}", text))
+}
+
+/// The transforms, without the target at the end
+fn transform(name: &str, args: &[&str], target: Target) -> Result<Action, String> {
+    let number = |text: &str| text.parse::<f64>().ok().filter(|number| number.is_finite()).ok_or_else(|| format!("not a number: {}", text));
+    let transform = match (name, args) {
+        ("flip", ["horizontal"]) => Transform::FlipHorizontal,
+        ("flip", ["vertical"]) => Transform::FlipVertical,
+        ("rotate", [degrees]) => Transform::Rotate(number(degrees)?),
+        ("shear", [x, y]) => Transform::Shear(number(x)?, number(y)?),
+        _ => return Err(usage(name)),
+    };
+    Ok(Action::Transform(transform, target))
+}
+
+/// The file and the options after it, in any order
+fn export(args: &[&str]) -> Result<Action, String> {
+    let mut path = None;
+    let (mut scale, mut with) = (1, Scaler::Nearest);
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        match arg {
+            "--scale" => scale = size(args.next().ok_or_else(|| usage("export"))?)?,
+            "--scaler" => with = scaler(args.next().ok_or_else(|| usage("export"))?)?,
+            _ if !arg.starts_with('-') && path.is_none() => path = Some(arg),
+            _ => return Err(usage("export")),
+        }
+    }
+    Ok(Action::Export(path.ok_or_else(|| usage("export"))?.into(), scale, with))
+}
+
+fn usage(command: &str) -> String {
+    let (_, usage) = COMMANDS.iter().find(|(name, _)| *name == command).expect("not a command with arguments");
+    format!("usage: {} {}", command, usage)
+}
+
+fn coordinate(text: &str) -> Result<u16, String> {
+    text.parse().map_err(|_| format!("not a coordinate: {}", text))
+}
+
+fn anchor(text: &str) -> Result<Anchor, String> {
+    Anchor::ALL.iter().copied().find(|anchor| anchor.name() == text).ok_or_else(|| format!("not an anchor: {}", text))
+}
+
+/// `#rrggbb` or `#rrggbbaa`, the `#` can be left out
+fn pixel(text: &str) -> Result<Pixel, String> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    let channel = |i: usize| hex.get(i * 2..i * 2 + 2).and_then(|channel| u8::from_str_radix(channel, 16).ok());
+    match (hex.len(), channel(0), channel(1), channel(2)) {
+        (6, Some(r), Some(g), Some(b)) => Ok(Pixel { r, g, b, a: 255 }),
+        (8, Some(r), Some(g), Some(b)) => match channel(3) {
+            Some(a) => Ok(Pixel { r, g, b, a }),
+            None => Err(format!("not a color: {}", text)),
+        },
+        _ => Err(format!("not a color: {}", text)),
+    }
+}
+
+/// Parses a palette in the `.hex` format, one `rrggbb` color per line
+pub fn parse_palette(text: &str) -> Result<Vec<Color>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| match pixel(line.trim()) {
+            Ok(pixel) if pixel.a == 255 => Ok(Color::from(pixel)),
+            _ => Err(format!("line {}: not a color: {}", i + 1, line.trim())),
+        })
+        .collect()
+}
@@ -0,0 +1,478 @@
+use std::{
+    fs,
+    io,
+    path::PathBuf,
+};
+
+use unicode_width::UnicodeWidthChar;
+
+use crate::{
+    draw::*,
+    gesture::Gesture,
+    gui::*,
+    input::*,
+    state::{Dimension, Position},
+    surface::*,
+};
+
+fn error_color() -> CellColor {
+    CellColor::default().fg(Color { r: 255, g: 85, b: 85 })
+}
+
+/// A line of text being typed in, with a cursor that moves along it
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TextInput {
+    text: String,
+    /// In chars, not bytes
+    cursor: usize,
+}
+
+impl TextInput {
+    /// With the cursor at the end
+    pub fn new(text: impl Into<String>) -> TextInput {
+        let text = text.into();
+        TextInput { cursor: text.chars().count(), text }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    pub fn set(&mut self, text: impl Into<String>) {
+        *self = TextInput::new(text);
+    }
+
+    pub fn take(&mut self) -> String {
+        std::mem::take(self).text
+    }
+
+    fn byte(&self, cursor: usize) -> usize {
+        self.text.char_indices().nth(cursor).map_or(self.text.len(), |(i, _)| i)
+    }
+
+    fn insert(&mut self, ch: char) {
+        let at = self.byte(self.cursor);
+        self.text.insert(at, ch);
+        self.cursor += 1;
+    }
+
+    /// Handles the keys for typing and moving around, returns whether it was one of those
+    pub fn on_key(&mut self, event: &Event) -> bool {
+        let len = self.text.chars().count();
+        match *event {
+            Event::Press(ch, Modifiers::None) => self.insert(ch),
+            Event::Press(ch, Modifiers::Shift) => self.insert(ch.to_ascii_uppercase()),
+            Event::Space => self.insert(' '),
+            Event::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                let at = self.byte(self.cursor);
+                self.text.remove(at);
+            }
+            Event::Delete if self.cursor < len => {
+                let at = self.byte(self.cursor);
+                self.text.remove(at);
+            }
+            Event::Arrow(Arrow::Left, Modifiers::None) => self.cursor = self.cursor.saturating_sub(1),
+            Event::Arrow(Arrow::Right, Modifiers::None) => self.cursor = (self.cursor + 1).min(len),
+            Event::Home => self.cursor = 0,
+            Event::End => self.cursor = len,
+            Event::Backspace | Event::Delete => {}
+            _ => return false,
+        }
+        true
+    }
+
+    /// Draws the text into the row of the surface, scrolled so that the cursor is in view,
+    /// which is only shown when it's `focused`
+    pub fn draw(&self, surface: &mut Surface, y: u16, color: CellColor, focused: bool) {
+        let width = surface.size().width as usize;
+        let chars: Vec<char> = self.text.chars().collect();
+        let columns = |chars: &[char]| chars.iter().map(|ch| ch.width().unwrap_or(0)).sum::<usize>();
+        // the cursor takes a cell of its own at the end
+        let mut start = 0;
+        while start < self.cursor && columns(&chars[start..self.cursor]) + 1 > width {
+            start += 1;
+        }
+        let text: String = chars[start..].iter().collect();
+        surface.text(Position { x: 0, y }, color.clone(), text);
+        if focused {
+            let x = columns(&chars[start..self.cursor]) as u16;
+            let under = chars.get(self.cursor).copied().unwrap_or(' ');
+            surface.put(Position { x, y }, CharCell::new(under).color(color).attrs(Attributes::default().reverse()));
+        }
+    }
+}
+
+/// Asks whether to go on with an action, the cancel button being the default one.
+/// The question can have more than one line
+pub struct Confirm {
+    title: String,
+    question: String,
+    action: Option<Action>,
+    otherwise: Option<Action>,
+    ok: bool,
+    done: bool,
+}
+
+impl Confirm {
+    const BUTTONS: &'static str = "[ Cancel ]  [ OK ]";
+
+    pub fn new(title: impl Into<String>, question: impl Into<String>, action: Action) -> Confirm {
+        Confirm { title: title.into(), question: question.into(), action: Some(action), otherwise: None, ok: false, done: false }
+    }
+
+    /// The action for the answer being no, or there being none
+    pub fn otherwise(mut self, action: Action) -> Confirm {
+        self.otherwise = Some(action);
+        self
+    }
+
+    /// The row with the buttons, with an empty one between them and the question
+    fn buttons_row(&self) -> u16 {
+        self.question.lines().count() as u16 + 1
+    }
+
+    fn answer(&mut self, gui: &mut GuiState, ok: bool) {
+        gui.requested = if ok { self.action.take() } else { self.otherwise.take() };
+        self.done = true;
+    }
+}
+
+impl Widget for Confirm {
+    fn draw(&self, _: &GuiState, surface: &mut Surface) -> io::Result<()> {
+        for (y, line) in self.question.lines().enumerate() {
+            surface.text(Position { x: 0, y: y as u16 }, CellColor::default(), line);
+        }
+        let x = surface.size().width.saturating_sub(text_width(Self::BUTTONS) as u16);
+        let y = self.buttons_row();
+        let button = |surface: &mut Surface, x: u16, text: &str, selected: bool| {
+            let attrs = if selected { Attributes::default().reverse() } else { Attributes::default() };
+            surface.text(Position { x, y }, CellColor::default().attrs(attrs), text);
+        };
+        button(surface, x, "[ Cancel ]", !self.ok);
+        button(surface, x + 12, "[ OK ]", self.ok);
+        Ok(())
+    }
+
+    fn size_hint(&self, _: &GuiState) -> Dimension {
+        let width = self.question.lines().map(text_width).chain(Some(text_width(Self::BUTTONS))).max().unwrap_or(0);
+        Dimension { width: width as u16, height: self.buttons_row() + 1 }
+    }
+
+    fn on_mouse_input(&mut self, gui: &mut GuiState, action: MouseAction, button: MouseButton, pos: Position, _: Modifiers) -> io::Result<Handled> {
+        if (action, button, pos.y) != (MouseAction::Release, MouseButton::Left, self.buttons_row()) {
+            return Ok(Handled::Consumed);
+        }
+        let buttons = self.size_hint(gui).width - text_width(Self::BUTTONS) as u16;
+        match pos.x.checked_sub(buttons) {
+            Some(0..=9) => self.answer(gui, false),
+            Some(12..=17) => self.answer(gui, true),
+            _ => {}
+        }
+        Ok(Handled::Consumed)
+    }
+
+    fn on_key(&mut self, gui: &mut GuiState, event: &Event) -> io::Result<Handled> {
+        match *event {
+            Event::Arrow(Arrow::Left, _) | Event::Arrow(Arrow::Right, _) | Event::Tab | Event::BackTab => self.ok = !self.ok,
+            Event::Enter | Event::Space => self.answer(gui, self.ok),
+            Event::Press('y', Modifiers::None) => self.answer(gui, true),
+            Event::Press('n', Modifiers::None) => self.answer(gui, false),
+            _ => {}
+        }
+        Ok(Handled::Consumed)
+    }
+}
+
+impl Modal for Confirm {
+    fn title(&self) -> String {
+        self.title.clone()
+    }
+
+    fn done(&self) -> bool {
+        self.done
+    }
+
+    fn cancel(&mut self, gui: &mut GuiState) {
+        self.answer(gui, false);
+    }
+}
+
+/// Asks for a line of text, to make an action out of it. What's typed in
+/// is checked, and the dialog stays open telling what's wrong with it if anything
+pub struct Prompt {
+    title: String,
+    input: TextInput,
+    action: Box<dyn Fn(String) -> Result<Action, String>>,
+    error: Option<String>,
+    done: bool,
+}
+
+impl Prompt {
+    const WIDTH: u16 = 40;
+
+    pub fn new<F>(title: impl Into<String>, text: impl Into<String>, action: F) -> Prompt
+        where F: Fn(String) -> Result<Action, String> + 'static {
+        Prompt { title: title.into(), input: TextInput::new(text), action: Box::new(action), error: None, done: false }
+    }
+}
+
+impl Widget for Prompt {
+    fn draw(&self, _: &GuiState, surface: &mut Surface) -> io::Result<()> {
+        let field = CellColor::default().bg(Color::gray(40)).fg(Color::gray(230));
+        surface.sub(Position::default(), Dimension { width: Self::WIDTH, height: 1 }).fill(CharCell::new(' ').color(field.clone()));
+        self.input.draw(surface, 0, field, true);
+        if let Some(error) = &self.error {
+            surface.text(Position { x: 0, y: 1 }, error_color(), error);
+        }
+        Ok(())
+    }
+
+    fn size_hint(&self, _: &GuiState) -> Dimension {
+        Dimension { width: Self::WIDTH, height: 2 }
+    }
+
+    fn on_key(&mut self, gui: &mut GuiState, event: &Event) -> io::Result<Handled> {
+        if *event == Event::Enter {
+            match (self.action)(self.input.text().to_string()) {
+                Ok(action) => {
+                    gui.requested = Some(action);
+                    self.done = true;
+                }
+                Err(error) => self.error = Some(error),
+            }
+        } else if self.input.on_key(event) {
+            self.error = None;
+        }
+        Ok(Handled::Consumed)
+    }
+}
+
+impl Modal for Prompt {
+    fn title(&self) -> String {
+        self.title.clone()
+    }
+
+    fn done(&self) -> bool {
+        self.done
+    }
+}
+
+/// Browses the directories for a file to open or to save into, listing only the files
+/// with the extensions it's for. The arrows go through the list, picking the name of the file,
+/// which can also be typed in. Enter with no name goes into the directory, and so does a double click
+pub struct FileDialog {
+    title: String,
+    dir: PathBuf,
+    extensions: &'static [&'static str],
+    /// Only the files that are already there can be picked
+    existing: bool,
+    /// The names in the directory, with whether they're directories themselves
+    entries: Vec<(String, bool)>,
+    selected: usize,
+    /// First entry shown
+    scroll: usize,
+    name: TextInput,
+    action: Box<dyn Fn(PathBuf) -> Action>,
+    error: Option<String>,
+    done: bool,
+}
+
+impl FileDialog {
+    const WIDTH: u16 = 48;
+    const ROWS: u16 = 12;
+
+    /// `extensions` without the dots, the first one is added to the names typed in without any
+    pub fn new<F>(title: impl Into<String>, dir: PathBuf, extensions: &'static [&'static str], existing: bool, action: F) -> FileDialog
+        where F: Fn(PathBuf) -> Action + 'static {
+        let mut dialog = FileDialog {
+            title: title.into(),
+            dir,
+            extensions,
+            existing,
+            entries: vec![],
+            selected: 0,
+            scroll: 0,
+            name: TextInput::default(),
+            action: Box::new(action),
+            error: None,
+            done: false,
+        };
+        dialog.list();
+        dialog
+    }
+
+    fn list(&mut self) {
+        self.entries.clear();
+        self.selected = 0;
+        self.scroll = 0;
+        if self.dir.parent().is_some() {
+            self.entries.push(("..".to_string(), true));
+        }
+        let read = match fs::read_dir(&self.dir) {
+            Ok(read) => read,
+            Err(e) => {
+                self.error = Some(e.to_string());
+                return;
+            }
+        };
+        let mut entries: Vec<(String, bool)> = read.filter_map(Result::ok)
+            .filter_map(|entry| Some((entry.file_name().into_string().ok()?, entry.path().is_dir())))
+            .filter(|(name, dir)| !name.starts_with('.') && (*dir || self.wanted(name)))
+            .collect();
+        // directories first
+        entries.sort_by(|(a, a_dir), (b, b_dir)| b_dir.cmp(a_dir).then_with(|| a.cmp(b)));
+        self.entries.extend(entries);
+    }
+
+    fn wanted(&self, name: &str) -> bool {
+        let extension = name.rsplit_once('.').map(|(_, extension)| extension.to_ascii_lowercase());
+        extension.is_some_and(|extension| self.extensions.contains(&extension.as_str()))
+    }
+
+    fn select(&mut self, selected: usize) {
+        if selected >= self.entries.len() {
+            return;
+        }
+        self.selected = selected;
+        let rows = Self::ROWS as usize;
+        self.scroll = self.scroll.min(selected).max((selected + 1).saturating_sub(rows));
+        let (name, dir) = &self.entries[selected];
+        self.name.set(if *dir { "" } else { name.as_str() });
+        self.error = None;
+    }
+
+    fn enter(&mut self, gui: &mut GuiState) {
+        if !self.name.is_empty() {
+            return self.pick(gui);
+        }
+        let (name, dir) = match self.entries.get(self.selected) {
+            Some(entry) => entry.clone(),
+            None => return,
+        };
+        if !dir {
+            return;
+        }
+        self.error = None;
+        if name == ".." {
+            self.dir.pop();
+        } else {
+            self.dir.push(name);
+        }
+        self.list();
+    }
+
+    fn pick(&mut self, gui: &mut GuiState) {
+        let mut path = self.dir.join(self.name.text());
+        if !self.wanted(self.name.text()) {
+            path.set_extension(self.extensions[0]);
+        }
+        if self.existing && !path.is_file() {
+            self.error = Some(format!("no such file: {}", path.display()));
+            return;
+        }
+        gui.requested = Some((self.action)(path));
+        self.done = true;
+    }
+}
+
+impl Widget for FileDialog {
+    fn draw(&self, _: &GuiState, surface: &mut Surface) -> io::Result<()> {
+        let width = surface.size().width as usize;
+        // the end of the path is the interesting part
+        let dir = self.dir.display().to_string();
+        let mut shown = dir.as_str();
+        while text_width(shown) > width {
+            let mut chars = shown.chars();
+            chars.next();
+            shown = chars.as_str();
+        }
+        surface.text(Position::default(), CellColor::default().attrs(Attributes::default().dim()), shown);
+
+        for (row, (name, dir)) in self.entries.iter().enumerate().skip(self.scroll).take(Self::ROWS as usize) {
+            let y = (row - self.scroll) as u16 + 1;
+            let text = format!(" {}{}", name, if *dir { "/" } else { "" });
+            let color = match row == self.selected {
+                true => CellColor::default().bg(Color::gray(90)).fg(Color::gray(230)),
+                false => CellColor::default(),
+            };
+            surface.text(Position { x: 0, y }, color, format!("{:<1$}", text, width));
+        }
+
+        let y = Self::ROWS + 1;
+        surface.text(Position { x: 0, y }, CellColor::default(), "Name ");
+        let field = CellColor::default().bg(Color::gray(40)).fg(Color::gray(230));
+        let mut input = surface.sub(Position { x: 5, y }, Dimension { width: Self::WIDTH - 5, height: 1 });
+        input.fill(CharCell::new(' ').color(field.clone()));
+        self.name.draw(&mut input, 0, field, true);
+        if let Some(error) = &self.error {
+            surface.text(Position { x: 0, y: y + 1 }, error_color(), error);
+        }
+        Ok(())
+    }
+
+    fn size_hint(&self, _: &GuiState) -> Dimension {
+        Dimension { width: Self::WIDTH, height: Self::ROWS + 3 }
+    }
+
+    fn on_mouse_input(&mut self, _: &mut GuiState, action: MouseAction, button: MouseButton, pos: Position, _: Modifiers) -> io::Result<Handled> {
+        if (action, button) == (MouseAction::Press, MouseButton::Left) && pos.y >= 1 && pos.y <= Self::ROWS {
+            self.select(self.scroll + pos.y as usize - 1);
+        }
+        Ok(Handled::Consumed)
+    }
+
+    fn on_mouse_wheel(&mut self, _: &mut GuiState, direction: MouseWheelDirection, _: Position, _: Modifiers) -> io::Result<Handled> {
+        match direction {
+            MouseWheelDirection::Up => self.select(self.selected.saturating_sub(1)),
+            MouseWheelDirection::Down => self.select(self.selected + 1),
+            _ => {}
+        }
+        Ok(Handled::Consumed)
+    }
+
+    fn on_gesture(&mut self, gui: &mut GuiState, gesture: Gesture, pos: Position) -> io::Result<Handled> {
+        if let Gesture::DoubleClick(MouseButton::Left, _, _) = gesture {
+            if pos.y >= 1 && pos.y <= Self::ROWS && self.scroll + pos.y as usize - 1 == self.selected {
+                self.enter(gui);
+            }
+        }
+        Ok(Handled::Consumed)
+    }
+
+    fn on_key(&mut self, gui: &mut GuiState, event: &Event) -> io::Result<Handled> {
+        match *event {
+            Event::Arrow(Arrow::Up, Modifiers::None) => self.select(self.selected.saturating_sub(1)),
+            Event::Arrow(Arrow::Down, Modifiers::None) => self.select(self.selected + 1),
+            Event::PgUp => self.select(self.selected.saturating_sub(Self::ROWS as usize)),
+            Event::PgDown => self.select((self.selected + Self::ROWS as usize).min(self.entries.len().saturating_sub(1))),
+            Event::Enter => self.enter(gui),
+            // backspacing with no name goes up a directory
+            Event::Backspace if self.name.is_empty() && self.dir.pop() => self.list(),
+            _ => {
+                if self.name.on_key(event) {
+                    self.error = None;
+                }
+            }
+        }
+        Ok(Handled::Consumed)
+    }
+}
+
+impl Modal for FileDialog {
+    fn title(&self) -> String {
+        self.title.clone()
+    }
+
+    fn done(&self) -> bool {
+        self.done
+    }
+}
@@ -0,0 +1,176 @@
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::state::{Dimension, Layer, Picture, Pixel};
+
+const MAGIC: &[u8] = b"entropic picture v1\n";
+
+/// Longer layer names are taken for a broken file, nobody types that much
+const MAX_NAME: u32 = 64 * 1024;
+
+/// The extension of the files in the native format
+pub const EXTENSION: &str = "entropic";
+
+/// Writes the picture in the native format: the size, then every layer with its name
+/// and its pixels as RGBA, all of the numbers little endian
+pub fn write<W: Write>(picture: &Picture, mut output: W) -> io::Result<()> {
+    output.write_all(MAGIC)?;
+    output.write_all(&picture.size.width.to_le_bytes())?;
+    output.write_all(&picture.size.height.to_le_bytes())?;
+    output.write_all(&(picture.layers.len() as u32).to_le_bytes())?;
+    for layer in &picture.layers {
+        output.write_all(&(layer.name.len() as u32).to_le_bytes())?;
+        output.write_all(layer.name.as_bytes())?;
+        for pixel in layer.pixels.iter() {
+            output.write_all(&[pixel.r, pixel.g, pixel.b, pixel.a])?;
+        }
+    }
+    output.flush()
+}
+
+/// Reads a picture written by `write`. Unlike the recordings, a truncated one is an error,
+/// half of a picture is no good. Nothing is allocated before it's actually read, so a broken
+/// length can't ask for more memory than the file has
+pub fn read<R: Read>(mut input: R) -> io::Result<Picture> {
+    let mut magic = [0; MAGIC.len()];
+    input.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(invalid("not an entropic picture"));
+    }
+    let size = Dimension { width: read_u16(&mut input)?, height: read_u16(&mut input)? };
+    if size.number() == 0 {
+        return Err(invalid("a picture with no pixels"));
+    }
+    Picture::check_size(size).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+    let count = read_u32(&mut input)?;
+    if count == 0 {
+        return Err(invalid("a picture with no layers"));
+    }
+    let mut layers = vec![];
+    for _ in 0..count {
+        let length = read_u32(&mut input)?;
+        if length > MAX_NAME {
+            return Err(invalid("a layer name that's too long"));
+        }
+        let name = read_bytes(&mut input, length as usize)?;
+        let name = String::from_utf8(name).map_err(|_| invalid("a layer name that isn't UTF-8"))?;
+        let bytes = read_bytes(&mut input, size.number() * 4)?;
+        let pixels = bytes.chunks(4).map(|rgba| Pixel { r: rgba[0], g: rgba[1], b: rgba[2], a: rgba[3] }).collect();
+        layers.push(Layer { name, pixels });
+    }
+    Ok(Picture { size, layers })
+}
+
+fn read_u16<R: Read>(input: &mut R) -> io::Result<u16> {
+    let mut bytes = [0; 2];
+    input.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32<R: Read>(input: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// Exactly that many bytes, taken in as they're read rather than allocated all at once
+fn read_bytes<R: Read>(input: &mut R, length: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![];
+    input.take(length as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < length {
+        return Err(io::Error::new(ErrorKind::UnexpectedEof, "the picture ends too early"));
+    }
+    Ok(bytes)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+pub fn load(path: &Path) -> io::Result<Picture> {
+    read(BufReader::new(File::open(path)?))
+}
+
+/// Saves the picture so that the file is either the old one or the whole new one,
+/// never something in between: it's written next to it first, then renamed over it
+pub fn save(picture: &Picture, path: &Path) -> io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    let written = File::create(&temp).and_then(|file| {
+        let mut output = BufWriter::new(file);
+        write(picture, &mut output)?;
+        output.into_inner().map_err(|e| e.into_error())?.sync_all()
+    });
+    match written {
+        Ok(()) => fs::rename(&temp, path),
+        Err(e) => {
+            let _ = fs::remove_file(&temp);
+            Err(e)
+        }
+    }
+}
+
+/// Where the autosaves go, `$XDG_STATE_HOME/entropic/recovery` or its default under the home
+pub fn recovery_dir() -> PathBuf {
+    let state = env::var_os("XDG_STATE_HOME").filter(|dir| !dir.is_empty()).map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("state")))
+        .unwrap_or_else(env::temp_dir);
+    state.join("entropic").join("recovery")
+}
+
+/// The autosave of the file in the directory, named after its whole path the way vim
+/// names its swap files, so that two files with the same name don't share one
+pub fn recovery_path(dir: &Path, file: Option<&Path>) -> PathBuf {
+    let name = match file {
+        Some(file) => {
+            let file = env::current_dir().map(|cwd| cwd.join(file)).unwrap_or_else(|_| file.to_path_buf());
+            file.to_string_lossy().replace('%', "%%").replace('/', "%")
+        }
+        None => "untitled".to_string(),
+    };
+    dir.join(format!("{}.{}", name, EXTENSION))
+}
+
+/// Keeps an autosave of the picture in the recovery directory while it has unsaved changes
+pub struct Autosave {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl Autosave {
+    pub fn new(dir: PathBuf, file: Option<&Path>) -> Autosave {
+        Autosave { path: recovery_path(&dir, file), dir }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes the picture if it's modified, and removes the autosave once it's saved.
+    /// The file is there for the picture being saved as a different one
+    pub fn update(&mut self, picture: &Picture, modified: bool, file: Option<&Path>) -> io::Result<()> {
+        let path = recovery_path(&self.dir, file);
+        if path != self.path {
+            self.discard()?;
+            self.path = path;
+        }
+        if !modified {
+            return self.discard();
+        }
+        fs::create_dir_all(&self.dir)?;
+        save(picture, &self.path)
+    }
+
+    /// Removes the autosave, be it ours or a leftover that wasn't recovered
+    pub fn discard(&mut self) -> io::Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
@@ -1,11 +1,16 @@
-use std::io::{StdoutLock, Write};
+use std::io::Write;
 use std::io;
+use std::str::FromStr;
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 use crate::state::{Dimension, Pixel, Position};
+use crate::surface::Surface;
 
 pub trait Drawable {
 
-    fn draw(&self, handle: &mut StdoutLock) -> io::Result<()>;
+    fn draw<W: Write + ?Sized>(&self, handle: &mut W) -> io::Result<()>;
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -23,12 +28,199 @@ impl Color {
     pub fn gray(gray: u8) -> Color {
         Color { r: gray, g: gray, b: gray }
     }
+
+    fn distance(&self, other: &Color) -> u32 {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+        // roughly how much each channel matters to the eye
+        2 * d(self.r, other.r) + 4 * d(self.g, other.g) + 3 * d(self.b, other.b)
+    }
+
+    /// Index of the closest color in the xterm 256-color palette, leaving out
+    /// the first 16, since those are different in every terminal
+    pub fn to_256(&self) -> u8 {
+        let level = |c: u8| CUBE_LEVELS.iter().enumerate()
+            .min_by_key(|(_, &level)| (level as i32 - c as i32).abs())
+            .map_or(0, |(i, _)| i as u8);
+        let cube = 16 + 36 * level(self.r) + 6 * level(self.g) + level(self.b);
+        let average = (self.r as u32 + self.g as u32 + self.b as u32) / 3;
+        let gray = 232 + (average.saturating_sub(3) / 10).min(23) as u8;
+        if xterm_color(gray).distance(self) < xterm_color(cube).distance(self) { gray } else { cube }
+    }
+
+    /// Index of the closest of the 16 ANSI colors, as xterm shows them by default
+    pub fn to_16(&self) -> u8 {
+        (0..16).min_by_key(|&i| xterm_color(i).distance(self)).unwrap_or(0)
+    }
+
+    /// Whether the color is closer to white than to black, which is all that's left on a mono terminal
+    pub fn is_light(&self) -> bool {
+        self.distance(&Color::gray(0)) > self.distance(&Color::gray(255))
+    }
+
+    /// The closest color the terminal can show
+    pub fn quantize(&self, depth: ColorDepth) -> Color {
+        match depth {
+            ColorDepth::TrueColor => self.clone(),
+            ColorDepth::Indexed256 => xterm_color(self.to_256()),
+            ColorDepth::Ansi16 => xterm_color(self.to_16()),
+            ColorDepth::Mono => Color::gray(if self.is_light() { 255 } else { 0 }),
+        }
+    }
+
+    /// Quantizes the color with ordered dithering, which makes a pattern
+    /// of the neighbouring palette colors instead of a flat area of the closest one.
+    /// `pos` is where the color is, for the pattern to line up
+    pub fn dither(&self, depth: ColorDepth, pos: Position) -> Color {
+        const BAYER: [[i32; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+        // about the distance between the palette colors
+        let spread = match depth {
+            ColorDepth::TrueColor => return self.clone(),
+            ColorDepth::Indexed256 => 40,
+            ColorDepth::Ansi16 => 128,
+            ColorDepth::Mono => 255,
+        };
+        let threshold = BAYER[pos.y as usize % 4][pos.x as usize % 4] * 2 - 15;
+        let offset = |c: u8| (c as i32 + threshold * spread / 32).clamp(0, 255) as u8;
+        Color::new(offset(self.r), offset(self.g), offset(self.b)).quantize(depth)
+    }
+}
+
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// The color xterm shows for the given palette index by default
+pub fn xterm_color(index: u8) -> Color {
+    const ANSI: [(u8, u8, u8); 16] = [
+        (0, 0, 0), (205, 0, 0), (0, 205, 0), (205, 205, 0), (0, 0, 238), (205, 0, 205), (0, 205, 205), (229, 229, 229),
+        (127, 127, 127), (255, 0, 0), (0, 255, 0), (255, 255, 0), (92, 92, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255),
+    ];
+    match index {
+        0..=15 => {
+            let (r, g, b) = ANSI[index as usize];
+            Color { r, g, b }
+        }
+        16..=231 => {
+            let i = index - 16;
+            Color::new(CUBE_LEVELS[i as usize / 36], CUBE_LEVELS[i as usize / 6 % 6], CUBE_LEVELS[i as usize % 6])
+        }
+        _ => Color::gray(8 + 10 * (index - 232)),
+    }
+}
+
+/// How many colors the terminal can show
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ColorDepth {
+    TrueColor,
+    /// The xterm 256-color palette
+    Indexed256,
+    /// The 16 ANSI colors
+    Ansi16,
+    /// Only the reverse video
+    Mono,
+}
+
+impl ColorDepth {
+    /// Guesses the color depth from the values of `COLORTERM` and `TERM`
+    pub fn detect(colorterm: Option<&str>, term: Option<&str>) -> ColorDepth {
+        if let Some("truecolor") | Some("24bit") = colorterm {
+            return ColorDepth::TrueColor;
+        }
+        match term {
+            None | Some("") | Some("dumb") => ColorDepth::Mono,
+            Some(term) if term.ends_with("-direct") || term.contains("truecolor") => ColorDepth::TrueColor,
+            Some(term) if term.contains("256color") => ColorDepth::Indexed256,
+            Some(term) if term.starts_with("vt") => ColorDepth::Mono,
+            _ => ColorDepth::Ansi16,
+        }
+    }
+
+    /// Detects the color depth of the terminal we run in, `NO_COLOR` makes it mono
+    pub fn from_env() -> ColorDepth {
+        if std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+            return ColorDepth::Mono;
+        }
+        let colorterm = std::env::var("COLORTERM").ok();
+        let term = std::env::var("TERM").ok();
+        ColorDepth::detect(colorterm.as_deref(), term.as_deref())
+    }
+
+    /// SGR parameters setting the given (already quantized) foreground or background,
+    /// empty when there is nothing to set. Mono has no colors, the background is
+    /// turned into the reverse video before it gets here
+    fn sgr(self, color: &Ink, background: bool) -> String {
+        let (base, bright, extended) = if background { (40, 100, 48) } else { (30, 90, 38) };
+        match (self, color.rgb()) {
+            (ColorDepth::Mono, _) => String::new(),
+            (_, None) => format!("{}", base + 9),
+            (ColorDepth::TrueColor, Some(Color { r, g, b })) => format!("{};2;{};{};{}", extended, r, g, b),
+            (ColorDepth::Indexed256, Some(color)) => format!("{};5;{}", extended, color.to_256()),
+            (ColorDepth::Ansi16, Some(color)) => match color.to_16() {
+                i @ 0..=7 => format!("{}", base + i),
+                i => format!("{}", bright + i - 8),
+            },
+        }
+    }
+}
+
+impl FromStr for ColorDepth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "truecolor" | "24bit" => Ok(ColorDepth::TrueColor),
+            "256" => Ok(ColorDepth::Indexed256),
+            "16" => Ok(ColorDepth::Ansi16),
+            "mono" => Ok(ColorDepth::Mono),
+            _ => Err(format!("unknown color depth {}, expected truecolor, 256, 16 or mono", s)),
+        }
+    }
+}
+
+/// The foreground or the background of a cell
+#[derive(Default, Debug, Clone, PartialEq)]
+pub enum Ink {
+    /// Whatever the terminal shows when there's no color set, SGR 39 or 49
+    #[default]
+    Default,
+    /// No color at all, the cell drawn over keeps the one it had.
+    /// It's only for drawing, the cells themselves never end up with it
+    Transparent,
+    Rgb(Color),
+}
+
+impl Ink {
+    pub fn rgb(&self) -> Option<&Color> {
+        match self {
+            Ink::Rgb(color) => Some(color),
+            _ => None,
+        }
+    }
+
+    /// What's left of the ink drawn over the other one
+    fn over(self, under: &Ink) -> Ink {
+        match self {
+            Ink::Transparent => under.clone(),
+            ink => ink,
+        }
+    }
+
+    fn quantize(&self, depth: ColorDepth) -> Ink {
+        match self {
+            Ink::Rgb(color) => Ink::Rgb(color.quantize(depth)),
+            ink => ink.clone(),
+        }
+    }
+}
+
+impl From<Option<Color>> for Ink {
+    fn from(color: Option<Color>) -> Self {
+        color.map_or(Ink::Default, Ink::Rgb)
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq)]
 pub struct CellColor {
-    pub bg: Option<Color>,
-    pub fg: Option<Color>,
+    pub bg: Ink,
+    pub fg: Ink,
 }
 
 impl CellColor {
@@ -36,28 +228,182 @@ impl CellColor {
         Self::default()
     }
 
+    /// Leaves both of the colors of the cells it's drawn over as they are
+    pub fn transparent() -> CellColor {
+        CellColor { bg: Ink::Transparent, fg: Ink::Transparent }
+    }
+
     pub fn bg(mut self, rgb: Color) -> CellColor {
-        self.bg = Some(rgb);
+        self.bg = Ink::Rgb(rgb);
         self
     }
 
     pub fn fg(mut self, rgb: Color) -> CellColor {
-        self.fg = Some(rgb);
+        self.fg = Ink::Rgb(rgb);
         self
     }
 
     pub fn clear(&mut self) {
-        self.bg = None;
-        self.fg = None;
+        self.bg = Ink::Default;
+        self.fg = Ink::Default;
+    }
+
+    /// The colors drawn over the ones of the cell, the transparent ones keeping those
+    pub fn over(self, under: &CellColor) -> CellColor {
+        CellColor { bg: self.bg.over(&under.bg), fg: self.fg.over(&under.fg) }
+    }
+
+    pub fn quantize(&self, depth: ColorDepth) -> CellColor {
+        // there is no foreground in mono, only the reverse video
+        let fg = if depth == ColorDepth::Mono { Ink::Default } else { self.fg.quantize(depth) };
+        CellColor { bg: self.bg.quantize(depth), fg }
+    }
+}
+
+/// The ways to underline, everything but `Single` is an extension
+/// (kitty, VTE, iTerm2 and others), the terminals that don't know it show a plain underline
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Underline {
+    Single,
+    Double,
+    Curly,
+    Dotted,
+    Dashed,
+}
+
+impl Underline {
+    fn sgr(self) -> &'static str {
+        match self {
+            Underline::Single => "4",
+            Underline::Double => "4:2",
+            Underline::Curly => "4:3",
+            Underline::Dotted => "4:4",
+            Underline::Dashed => "4:5",
+        }
+    }
+}
+
+/// Text attributes of a cell
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Attributes {
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: Option<Underline>,
+    /// `None` is the color of the text
+    pub underline_color: Option<Color>,
+    pub reverse: bool,
+}
+
+impl Attributes {
+    pub fn bold(mut self) -> Attributes {
+        self.bold = true;
+        self
+    }
+
+    pub fn dim(mut self) -> Attributes {
+        self.dim = true;
+        self
+    }
+
+    pub fn italic(mut self) -> Attributes {
+        self.italic = true;
+        self
+    }
+
+    pub fn underline(mut self, underline: Underline) -> Attributes {
+        self.underline = Some(underline);
+        self
+    }
+
+    pub fn underline_color(mut self, rgb: Color) -> Attributes {
+        self.underline_color = Some(rgb);
+        self
+    }
+
+    pub fn reverse(mut self) -> Attributes {
+        self.reverse = true;
+        self
+    }
+
+    /// SGR parameters going from `self` to `other`
+    fn sgr(&self, other: &Attributes, depth: ColorDepth) -> Vec<String> {
+        let mut params = vec![];
+        // there's only the one way to turn off both bold and dim
+        if self.bold && !other.bold || self.dim && !other.dim {
+            params.push("22".into());
+            params.extend(other.bold.then(|| "1".into()));
+            params.extend(other.dim.then(|| "2".into()));
+        } else {
+            params.extend((other.bold && !self.bold).then(|| "1".into()));
+            params.extend((other.dim && !self.dim).then(|| "2".into()));
+        }
+        if self.italic != other.italic {
+            params.push(if other.italic { "3" } else { "23" }.into());
+        }
+        if self.underline != other.underline {
+            params.push(other.underline.map_or("24", Underline::sgr).into());
+        }
+        if self.underline_color != other.underline_color {
+            params.push(match (depth, &other.underline_color) {
+                (_, None) => "59".into(),
+                (ColorDepth::TrueColor, Some(Color { r, g, b })) => format!("58;2;{};{};{}", r, g, b),
+                (_, Some(color)) => format!("58;5;{}", color.to_256()),
+            });
+        }
+        if self.reverse != other.reverse {
+            params.push(if other.reverse { "7" } else { "27" }.into());
+        }
+        params
+    }
+}
+
+/// The colors and the attributes, all of what a cell looks like but the glyph
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Style {
+    pub color: CellColor,
+    pub attrs: Attributes,
+}
+
+impl From<CellColor> for Style {
+    fn from(color: CellColor) -> Self {
+        Style { color, attrs: Attributes::default() }
+    }
+}
+
+impl Style {
+    /// The colors and attributes as the terminal can show them.
+    /// A mono terminal shows the light background as the reverse video
+    fn quantize(&self, depth: ColorDepth) -> Style {
+        let mut color = self.color.quantize(depth);
+        let mut attrs = self.attrs.clone();
+        match depth {
+            ColorDepth::TrueColor => {}
+            ColorDepth::Indexed256 => attrs.underline_color = attrs.underline_color.map(|c| c.quantize(depth)),
+            ColorDepth::Ansi16 => attrs.underline_color = None,
+            ColorDepth::Mono => {
+                attrs.underline_color = None;
+                if std::mem::take(&mut color.bg).rgb().is_some_and(Color::is_light) {
+                    attrs.reverse = !attrs.reverse;
+                }
+            }
+        }
+        Style { color, attrs }
+    }
+}
+
+impl CellColor {
+    pub fn attrs(self, attrs: Attributes) -> Style {
+        Style { color: self, attrs }
     }
 }
 
 impl Drawable for CellColor {
-    fn draw(&self, handle: &mut StdoutLock) -> io::Result<()> {
-        if let Some(Color { r, g, b }) = self.bg {
+    fn draw<W: Write + ?Sized>(&self, handle: &mut W) -> io::Result<()> {
+        if let Ink::Rgb(Color { r, g, b }) = self.bg {
             write!(handle, "\x1b[48;2;{};{};{}m", r, g, b)?;
         }
-        if let Some(Color { r, g, b }) = self.fg {
+        if let Ink::Rgb(Color { r, g, b }) = self.fg {
             write!(handle, "\x1b[38;2;{};{};{}m", r, g, b)?;
         }
         Ok(())
@@ -70,10 +416,45 @@ impl From<Pixel> for Color {
     }
 }
 
+/// How many columns the glyph in a cell takes
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CellWidth {
+    Single,
+    /// CJK, most emoji and such, the cell on the right is its `Continuation`
+    Double,
+    /// The right half of the double width glyph on the left, there is nothing to draw in it
+    Continuation,
+}
+
+impl CellWidth {
+    fn of(grapheme: &str) -> CellWidth {
+        // zero width ones (lone combining marks, controls) still take a cell when they're on their own
+        if grapheme.width() > 1 { CellWidth::Double } else { CellWidth::Single }
+    }
+
+    pub fn columns(self) -> u16 {
+        match self {
+            CellWidth::Single => 1,
+            CellWidth::Double => 2,
+            CellWidth::Continuation => 0,
+        }
+    }
+}
+
+/// How many columns the text takes on the screen, the same way `put_text` places it
+pub fn text_width(text: &str) -> usize {
+    text.graphemes(true).map(|g| CellWidth::of(g).columns() as usize).sum()
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct CharCell {
     pub color: CellColor,
+    pub attrs: Attributes,
     pub char: char,
+    /// The rest of the grapheme cluster, combining accents, variation selectors,
+    /// joined emoji and so on. Empty almost always, so it doesn't allocate
+    pub marks: String,
+    pub width: CellWidth,
 }
 
 impl Default for CharCell {
@@ -84,7 +465,21 @@ impl Default for CharCell {
 
 impl CharCell {
     pub fn new(char: char) -> CharCell {
-        CharCell { color: CellColor::default(), char }
+        let width = if char.width() == Some(2) { CellWidth::Double } else { CellWidth::Single };
+        CharCell { color: CellColor::default(), attrs: Attributes::default(), char, marks: String::new(), width }
+    }
+
+    /// A cell showing a whole grapheme cluster, only the first one if there are more
+    pub fn grapheme(grapheme: &str) -> CharCell {
+        let grapheme = grapheme.graphemes(true).next().unwrap_or(" ");
+        let mut chars = grapheme.chars();
+        let char = chars.next().unwrap_or(' ');
+        let width = CellWidth::of(grapheme);
+        CharCell { color: CellColor::default(), attrs: Attributes::default(), char, marks: chars.collect(), width }
+    }
+
+    fn continuation(color: CellColor, attrs: Attributes) -> CharCell {
+        CharCell { color, attrs, char: ' ', marks: String::new(), width: CellWidth::Continuation }
     }
 
     pub fn color(mut self, color: CellColor) -> CharCell {
@@ -92,6 +487,15 @@ impl CharCell {
         self
     }
 
+    pub fn attrs(mut self, attrs: Attributes) -> CharCell {
+        self.attrs = attrs;
+        self
+    }
+
+    pub fn style(self, style: Style) -> CharCell {
+        self.color(style.color).attrs(style.attrs)
+    }
+
     pub fn bg(mut self, rgb: Color) -> CharCell {
         self.color = self.color.bg(rgb);
         self
@@ -103,15 +507,147 @@ impl CharCell {
     }
 
     pub fn clear(&mut self) {
-        self.char = ' ';
+        self.blank();
         self.color.clear();
+        self.attrs = Attributes::default();
+    }
+
+    /// Makes it a space, keeping the colors
+    fn blank(&mut self) {
+        self.char = ' ';
+        self.marks.clear();
+        self.width = CellWidth::Single;
+    }
+
+    fn is_blank(&self) -> bool {
+        self.char == ' ' && self.width == CellWidth::Single && self.marks.is_empty()
     }
 }
 
 impl Drawable for CharCell {
-    fn draw(&self, handle: &mut StdoutLock) -> io::Result<()> {
+    fn draw<W: Write + ?Sized>(&self, handle: &mut W) -> io::Result<()> {
         self.color.draw(handle)?;
-        write!(handle, "{}\x1b[0m", self.char)
+        let attrs = Attributes::default().sgr(&self.attrs, ColorDepth::TrueColor);
+        if !attrs.is_empty() {
+            write!(handle, "\x1b[{}m", attrs.join(";"))?;
+        }
+        write!(handle, "{}{}\x1b[0m", self.char, self.marks)
+    }
+}
+
+/// Block glyphs that split a cell into a grid of pixels, each of which
+/// is either the foreground or the background color
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Glyphs {
+    /// 2x2, `▚` and friends
+    Quadrants,
+    /// 2x3, from Unicode 13, so not every font has them
+    Sextants,
+    /// 2x4 braille dots, these don't cover the cell so the foreground looks dimmer
+    Braille,
+}
+
+impl Glyphs {
+    /// How many pixels fit into a cell
+    pub fn block(self) -> Dimension {
+        match self {
+            Glyphs::Quadrants => Dimension { width: 2, height: 2 },
+            Glyphs::Sextants => Dimension { width: 2, height: 3 },
+            Glyphs::Braille => Dimension { width: 2, height: 4 },
+        }
+    }
+
+    /// The glyph with the foreground where the bits of the mask are set,
+    /// the bits going over the block pixels row by row
+    pub fn glyph(self, mask: u8) -> char {
+        const QUADRANTS: [char; 16] = [' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█'];
+        match self {
+            Glyphs::Quadrants => QUADRANTS[mask as usize & 15],
+            Glyphs::Sextants => match mask & 63 {
+                0 => ' ',
+                21 => '▌',
+                42 => '▐',
+                63 => '█',
+                // the sextants block leaves out the ones above
+                m => std::char::from_u32(0x1fb00 + m as u32 - 1 - (m > 21) as u32 - (m > 42) as u32).unwrap(),
+            },
+            Glyphs::Braille => {
+                // braille dots go down the left column first, and the bottom row is an afterthought
+                const DOTS: [u8; 8] = [0, 3, 1, 4, 2, 5, 6, 7];
+                let bits = (0..8).filter(|&i| mask & (1 << i) != 0).fold(0, |bits, i| bits | 1 << DOTS[i]);
+                std::char::from_u32(0x2800 + bits as u32).unwrap()
+            }
+        }
+    }
+}
+
+/// Picks the glyph and the two colors that show the given block of pixels best,
+/// trying every way to split them in two and keeping the one with the least error.
+/// Pixels are row by row, `None` is where there's nothing to show, which
+/// has to be the (default) background, so the rest can only have the foreground
+pub fn fit_cell(glyphs: Glyphs, pixels: &[Option<Color>]) -> CharCell {
+    let count = glyphs.block().number().min(pixels.len());
+    let average = |mask: u8, fg: bool| {
+        let (mut r, mut g, mut b, mut n) = (0, 0, 0, 0);
+        for (i, pixel) in pixels[..count].iter().enumerate() {
+            if let Some(pixel) = pixel.as_ref().filter(|_| (mask & (1 << i) != 0) == fg) {
+                r += pixel.r as u32;
+                g += pixel.g as u32;
+                b += pixel.b as u32;
+                n += 1;
+            }
+        }
+        let (r, g, b) = (r.checked_div(n)?, g.checked_div(n)?, b.checked_div(n)?);
+        Some(Color::new(r as u8, g as u8, b as u8))
+    };
+    let present = pixels[..count].iter().enumerate()
+        .filter(|(_, p)| p.is_some())
+        .fold(0u8, |mask, (i, _)| mask | 1 << i);
+    let full = present.count_ones() as usize == count;
+
+    let mut best = (u32::MAX, 0, None, None);
+    for mask in 0..1u16 << count {
+        let mask = mask as u8;
+        // with something missing there's only one way to split them
+        if !full && mask != present {
+            continue;
+        }
+        let fg = average(mask, true);
+        let bg = average(mask, false);
+        let error = pixels[..count].iter().enumerate()
+            .filter_map(|(i, pixel)| {
+                let side = if mask & (1 << i) != 0 { &fg } else { &bg };
+                Some(pixel.as_ref()?.distance(side.as_ref()?))
+            })
+            .sum();
+        if error < best.0 {
+            best = (error, mask, fg, bg);
+        }
+        if error == 0 {
+            break;
+        }
+    }
+    let (_, mask, fg, bg) = best;
+    CharCell::new(glyphs.glyph(mask)).color(CellColor { bg: bg.into(), fg: if mask == 0 { Ink::Default } else { fg.into() } })
+}
+
+/// Something shown over the cells that the cells know nothing about, like an image
+#[derive(Debug, Clone, PartialEq)]
+pub struct Overlay {
+    pub pos: Position,
+    /// The cells under it, those are left alone when drawing
+    pub size: Dimension,
+    /// Written with the cursor at `pos`
+    pub bytes: Vec<u8>,
+    /// Written with the cursor at `pos` when the overlay goes away,
+    /// in case drawing the cells over it is not enough
+    pub clear: Vec<u8>,
+}
+
+impl Overlay {
+    fn covers(&self, pos: Position) -> bool {
+        pos.x >= self.pos.x && pos.y >= self.pos.y
+            && pos.x < self.pos.x + self.size.width && pos.y < self.pos.y + self.size.height
     }
 }
 
@@ -119,12 +655,54 @@ pub struct TerminalState {
     size: Dimension,
     drawn: Option<Box<[CharCell]>>,
     buffer: Box<[CharCell]>,
+    overlays: Vec<Overlay>,
+    drawn_overlays: Vec<Overlay>,
+    /// Whether the frames are wrapped in the synchronized update mode (2026),
+    /// so that the terminal shows them all at once
+    synchronized: bool,
+    depth: ColorDepth,
+    /// Frames are put together here and then written out at once
+    frame: Vec<u8>,
 }
 
 impl TerminalState {
     pub fn new(size: Dimension) -> TerminalState {
         let buffer = vec![CharCell::default(); size.number()].into_boxed_slice();
-        TerminalState { size, buffer, drawn: None }
+        TerminalState {
+            size,
+            buffer,
+            drawn: None,
+            overlays: vec![],
+            drawn_overlays: vec![],
+            synchronized: false,
+            depth: ColorDepth::TrueColor,
+            frame: vec![],
+        }
+    }
+
+    pub fn size(&self) -> Dimension {
+        self.size
+    }
+
+    /// All of the screen, to draw into with the clipping and the local coordinates
+    pub fn surface(&mut self) -> Surface<'_> {
+        let size = self.size;
+        Surface::new(self, Position::default(), size)
+    }
+
+    pub fn color_depth(&self) -> ColorDepth {
+        self.depth
+    }
+
+    /// The colors are quantized to the depth when drawn, so the whole screen should be redrawn after this
+    pub fn set_color_depth(&mut self, depth: ColorDepth) {
+        self.depth = depth;
+    }
+
+    /// Should only be turned on when the terminal said it knows the mode,
+    /// the others might just print the garbage otherwise
+    pub fn set_synchronized(&mut self, synchronized: bool) {
+        self.synchronized = synchronized;
     }
 
     #[inline]
@@ -132,66 +710,325 @@ impl TerminalState {
         self.size.offset(pos)
     }
 
+    /// Clears the cells, along with the overlays that start within them
     pub fn clear(&mut self, rect: Dimension) {
-        for pos in rect {
-            self.buffer[self.size.offset(pos)].clear();
+        for pos in rect.min(self.size) {
+            self.split(pos);
+            let offset = self.offset(pos);
+            self.buffer[offset].clear();
+        }
+        self.overlays.retain(|o| o.pos.x >= rect.width || o.pos.y >= rect.height);
+    }
+
+    /// Darkens the cells, for what's behind a dialog. The overlays over them are taken away,
+    /// as there's no darkening an image
+    pub fn dim(&mut self, (origin, size): (Position, Dimension)) {
+        let darker = |color: &mut Ink| {
+            if let Ink::Rgb(color) = color {
+                *color = Color { r: color.r / 2, g: color.g / 2, b: color.b / 2 };
+            }
+        };
+        for pos in size {
+            let pos = origin + pos;
+            if pos.x >= self.size.width || pos.y >= self.size.height {
+                continue;
+            }
+            let offset = self.offset(pos);
+            let cell = &mut self.buffer[offset];
+            darker(&mut cell.color.bg);
+            darker(&mut cell.color.fg);
+            // the default one could be anything, but it can be made fainter
+            if cell.color.fg == Ink::Default {
+                cell.attrs.dim = true;
+            }
         }
+        self.overlays.retain(|o| {
+            o.pos.x >= origin.x + size.width || o.pos.y >= origin.y + size.height
+                || o.pos.x + o.size.width <= origin.x || o.pos.y + o.size.height <= origin.y
+        });
     }
 
-    pub fn put(&mut self, pos: Position, cell: CharCell) {
-        self.buffer[self.offset(pos)] = cell.clone();
+    /// Adds an overlay for this frame, it is only written out again when it changes
+    pub fn overlay(&mut self, overlay: Overlay) {
+        self.overlays.push(overlay);
+    }
+
+    /// Puts the cell there, along with its continuation if it's a wide one.
+    /// Cells off the screen are left out, and so are the continuations,
+    /// as those come along with the glyph on their left
+    pub fn put(&mut self, pos: Position, mut cell: CharCell) {
+        if pos.x >= self.size.width || pos.y >= self.size.height || cell.width == CellWidth::Continuation {
+            return;
+        }
+        cell.color = cell.color.over(&self.get(pos).color);
+        self.split(pos);
+        if cell.width == CellWidth::Double {
+            let next = Position { x: pos.x + 1, y: pos.y };
+            if next.x < self.size.width {
+                self.split(next);
+                let offset = self.offset(next);
+                self.buffer[offset] = CharCell::continuation(cell.color.clone(), cell.attrs.clone());
+            } else {
+                // half a glyph can't be shown
+                cell.blank();
+            }
+        }
+        let offset = self.offset(pos);
+        self.buffer[offset] = cell;
     }
 
-    pub fn put_text(&mut self, pos: Position, color: CellColor, text: impl AsRef<str>) {
-        let pos = self.offset(pos);
-        let mut offset = 0;
-        for ch in text.as_ref().chars() {
-            let cell = CharCell::new(ch).color(color.clone());
-            self.buffer[pos + offset] = cell;
-            offset += 1;
+    /// Breaks up the wide glyph the cell is a half of, leaving a blank for the other half
+    fn split(&mut self, pos: Position) {
+        let offset = self.offset(pos);
+        match self.buffer[offset].width {
+            CellWidth::Single => {}
+            CellWidth::Double => self.buffer[offset + 1].blank(),
+            CellWidth::Continuation => self.buffer[offset - 1].blank(),
         }
     }
 
-    pub fn redraw(&mut self, part: Dimension) -> io::Result<()> {
-        let Dimension { width, height } = part.min(self.size);
-        let stdout = std::io::stdout();
-        let mut handle = stdout.lock();
-        write!(handle, "\x1b[3J")?;
-        for y in 0..height {
-            write!(handle, "\x1b[{};1H", y + 1)?;
-            for x in 0..width {
-                self.buffer[self.size.offset(Position { x, y })].draw(&mut handle)?;
+    /// Puts the text in a row, one grapheme per cell or two for the wide ones,
+    /// whatever goes past the edge of the screen is cut off
+    pub fn put_text(&mut self, pos: Position, style: impl Into<Style>, text: impl AsRef<str>) {
+        self.put_text_clipped(pos, (Position::default(), self.size), style, text)
+    }
+
+    /// Like `put_text`, but only within the `clip` rectangle.
+    /// A wide glyph cut in half by its edge leaves a blank for the half inside
+    pub fn put_text_clipped(&mut self, pos: Position, clip: (Position, Dimension), style: impl Into<Style>, text: impl AsRef<str>) {
+        let style = style.into();
+        let (origin, size) = clip;
+        let end = (origin.x as usize + size.width as usize).min(self.size.width as usize);
+        if pos.y < origin.y || pos.y as usize >= origin.y as usize + size.height as usize {
+            return;
+        }
+        let mut x = pos.x as usize;
+        for grapheme in text.as_ref().graphemes(true) {
+            if x >= end {
+                break;
             }
+            let cell = CharCell::grapheme(grapheme).style(style.clone());
+            let columns = cell.width.columns() as usize;
+            if x >= origin.x as usize && x + columns <= end {
+                self.put(Position { x: x as u16, y: pos.y }, cell);
+            } else {
+                for x in (x..x + columns).filter(|&x| x >= origin.x as usize && x < end) {
+                    self.put(Position { x: x as u16, y: pos.y }, CharCell::new(' ').style(style.clone()));
+                }
+            }
+            x += columns;
         }
-        write!(handle, "\x1b[0m\x1b[1;1H")?;
-        handle.flush()?;
+    }
+
+    pub fn get(&self, pos: Position) -> &CharCell {
+        &self.buffer[self.offset(pos)]
+    }
+
+    /// Paints the whole `part` of the screen, regardless of what is drawn already
+    pub fn redraw(&mut self, part: Dimension, handle: &mut impl Write) -> io::Result<()> {
+        let mut frame = std::mem::take(&mut self.frame);
+        write!(frame, "\x1b[3J")?;
+        self.paint(part, None, &mut frame)?;
+        self.paint_overlays(true, &mut frame)?;
         self.drawn = Some(self.buffer.clone());
-        Ok(())
+        self.frame = frame;
+        self.flush_frame(handle)
     }
 
-    pub fn draw(&mut self, part: Dimension) -> io::Result<()> {
-        match &mut self.drawn {
-            None => self.redraw(part),
-            Some(drawn) => {
-                let stdout = std::io::stdout();
-                let mut handle = stdout.lock();
+    /// Paints only the cells that changed since the last time
+    pub fn draw(&mut self, part: Dimension, handle: &mut impl Write) -> io::Result<()> {
+        let mut drawn = match self.drawn.take() {
+            None => return self.redraw(part, handle),
+            Some(drawn) => drawn,
+        };
+        let mut frame = std::mem::take(&mut self.frame);
+        self.paint(part, Some(&drawn), &mut frame)?;
+        self.paint_overlays(false, &mut frame)?;
+        for pos in part.min(self.size) {
+            let offset = self.offset(pos);
+            drawn[offset] = self.buffer[offset].clone();
+        }
+        self.drawn = Some(drawn);
+        self.frame = frame;
+        self.flush_frame(handle)
+    }
 
-                for pos in part.min(self.size) {
-                    let offset = self.size.offset(pos);
+    /// Writes the frame out in one go, nothing is written if nothing changed
+    fn flush_frame(&mut self, handle: &mut impl Write) -> io::Result<()> {
+        if self.frame.is_empty() {
+            return Ok(());
+        }
+        if self.synchronized {
+            self.frame.splice(0..0, b"\x1b[?2026h".iter().copied());
+            self.frame.extend_from_slice(b"\x1b[?2026l");
+        }
+        let res = handle.write_all(&self.frame).and_then(|_| handle.flush());
+        self.frame.clear();
+        res
+    }
 
-                    let old = &mut drawn[offset];
-                    let new = &self.buffer[offset];
+    /// Clears the overlays that are gone and writes the new ones,
+    /// or all of them if it's a full repaint
+    fn paint_overlays(&mut self, full: bool, handle: &mut Vec<u8>) -> io::Result<()> {
+        for overlay in &self.drawn_overlays {
+            if !self.overlays.contains(overlay) && !overlay.clear.is_empty() {
+                overlay.pos.draw(handle)?;
+                handle.extend_from_slice(&overlay.clear);
+            }
+        }
+        for overlay in &self.overlays {
+            if full || !self.drawn_overlays.contains(overlay) {
+                overlay.pos.draw(handle)?;
+                handle.extend_from_slice(&overlay.bytes);
+            }
+        }
+        self.drawn_overlays = self.overlays.clone();
+        Ok(())
+    }
 
-                    if old != new {
-                        pos.draw(&mut handle)?;
-                        new.draw(&mut handle)?;
-                        *old = new.clone();
-                    }
+    fn paint(&self, part: Dimension, drawn: Option<&[CharCell]>, handle: &mut Vec<u8>) -> io::Result<()> {
+        let part = part.min(self.size);
+        let mut pen = Pen { style: Style::default(), cursor: None, width: part.width, depth: self.depth };
+        let covered = |pos: Position| self.overlays.iter().any(|o| o.covers(pos));
+        // cells that were under an overlay have to be drawn again, whatever they are
+        let changed = |pos: Position| {
+            let uncovered = self.drawn_overlays.iter().any(|o| o.covers(pos));
+            let offset = self.offset(pos);
+            !covered(pos) && (uncovered || drawn.is_none_or(|drawn| drawn[offset] != self.buffer[offset]))
+        };
+        for y in 0..part.height {
+            let row = &self.buffer[self.offset(Position { x: 0, y })..][..part.width as usize];
+            let mut x = 0;
+            while x < part.width {
+                let cell = &row[x as usize];
+                let pos = Position { x, y };
+                let next = Position { x: x + 1, y };
+                let paint = match cell.width {
+                    CellWidth::Single => changed(pos),
+                    // the other half has to be drawn again along with it
+                    CellWidth::Double => changed(pos) || next.x < part.width && changed(next),
+                    // goes out with the glyph on the left, unless that one is under an overlay
+                    CellWidth::Continuation => x > 0 && covered(Position { x: x - 1, y }) && changed(pos),
+                };
+                if !paint {
+                    x += 1;
+                    continue;
+                }
+                let style = Style { color: cell.color.clone(), attrs: cell.attrs.clone() }.quantize(self.depth);
+                // erased cells are neither reversed nor underlined
+                let erasable = |style: &Style| !style.attrs.reverse && style.attrs.underline.is_none();
+                let blanks = match cell.is_blank() && erasable(&style) {
+                    true => row[x as usize..].iter()
+                        .zip(x..)
+                        .take_while(|(c, x)| c.is_blank() && !covered(Position { x: *x, y }) && {
+                            let other = Style { color: c.color.clone(), attrs: c.attrs.clone() }.quantize(self.depth);
+                            other.color.bg == style.color.bg && erasable(&other)
+                        })
+                        .count() as u16,
+                    false => 0,
+                };
+                pen.move_to(handle, pos)?;
+                // erasing is only worth it when it's shorter than the spaces,
+                // it doesn't move the cursor so the next cell might need a move
+                if x + blanks == part.width && blanks > 3 {
+                    pen.set_bg(handle, &style.color.bg)?;
+                    write!(handle, "\x1b[K")?;
+                    x = part.width;
+                } else if blanks > 6 {
+                    pen.set_bg(handle, &style.color.bg)?;
+                    write!(handle, "\x1b[{}X", blanks)?;
+                    x += blanks;
+                } else {
+                    pen.set_style(handle, &style)?;
+                    let columns = match cell.width {
+                        CellWidth::Double if next.x < part.width => {
+                            write!(handle, "{}{}", cell.char, cell.marks)?;
+                            2
+                        }
+                        CellWidth::Single => {
+                            write!(handle, "{}{}", cell.char, cell.marks)?;
+                            1
+                        }
+                        // only one half of it can be seen
+                        _ => {
+                            write!(handle, " ")?;
+                            1
+                        }
+                    };
+                    pen.advance(columns);
+                    x += columns;
                 }
-                handle.flush()
             }
         }
+        if pen.style != Style::default() {
+            write!(handle, "\x1b[0m")?;
+        }
+        Ok(())
     }
 }
 
+/// What the terminal is known to be at while a frame is written out,
+/// so that only the differences to it get emitted.
+///
+/// Erasing relies on the terminal filling the erased cells with the current
+/// background (bce), which is what pretty much every terminal out there does
+struct Pen {
+    style: Style,
+    /// `None` when we can't be sure, which is at the start and after the last column
+    cursor: Option<Position>,
+    width: u16,
+    depth: ColorDepth,
+}
+
+impl Pen {
+    fn move_to(&mut self, handle: &mut impl Write, pos: Position) -> io::Result<()> {
+        match self.cursor {
+            Some(cursor) if cursor == pos => {}
+            Some(cursor) if cursor.y == pos.y && cursor.x + 1 == pos.x => write!(handle, "\x1b[C")?,
+            Some(cursor) if cursor.y == pos.y && cursor.x < pos.x => write!(handle, "\x1b[{}C", pos.x - cursor.x)?,
+            _ => pos.draw(handle)?,
+        }
+        self.cursor = Some(pos);
+        Ok(())
+    }
+
+    /// After a glyph taking the given number of columns is written at the cursor
+    fn advance(&mut self, columns: u16) {
+        self.cursor = self.cursor
+            .map(|Position { x, y }| Position { x: x + columns, y })
+            .filter(|pos| pos.x < self.width);
+    }
 
+    /// Sets up for erasing, the attributes that would show on the erased cells are turned off
+    fn set_bg(&mut self, handle: &mut impl Write, bg: &Ink) -> io::Result<()> {
+        let mut style = self.style.clone();
+        style.color.bg = bg.clone();
+        style.attrs.reverse = false;
+        style.attrs.underline = None;
+        self.set_style(handle, &style)
+    }
+
+    fn set_style(&mut self, handle: &mut impl Write, style: &Style) -> io::Result<()> {
+        if self.style == *style {
+            return Ok(());
+        }
+        if *style == Style::default() {
+            write!(handle, "\x1b[0m")?;
+        } else {
+            let mut params = vec![];
+            if self.style.color.bg != style.color.bg {
+                params.push(self.depth.sgr(&style.color.bg, true));
+            }
+            if self.style.color.fg != style.color.fg {
+                params.push(self.depth.sgr(&style.color.fg, false));
+            }
+            params.extend(self.style.attrs.sgr(&style.attrs, self.depth));
+            params.retain(|p| !p.is_empty());
+            if !params.is_empty() {
+                write!(handle, "\x1b[{}m", params.join(";"))?;
+            }
+        }
+        self.style = style.clone();
+        Ok(())
+    }
+}
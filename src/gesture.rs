@@ -0,0 +1,149 @@
+use std::time::{Duration, Instant};
+
+use crate::input::{Event, Modifiers, MouseAction, MouseButton};
+use crate::state::Position;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Gesture {
+    Click(MouseButton, Position, Modifiers),
+    DoubleClick(MouseButton, Position, Modifiers),
+    TripleClick(MouseButton, Position, Modifiers),
+    LongPress(MouseButton, Position, Modifiers),
+    /// Reported with the position where the button was pressed
+    DragStart(MouseButton, Position, Modifiers),
+    DragEnd(MouseButton, Position, Modifiers),
+}
+
+impl Gesture {
+    pub fn position(&self) -> Position {
+        match *self {
+            Gesture::Click(_, pos, _) |
+            Gesture::DoubleClick(_, pos, _) |
+            Gesture::TripleClick(_, pos, _) |
+            Gesture::LongPress(_, pos, _) |
+            Gesture::DragStart(_, pos, _) |
+            Gesture::DragEnd(_, pos, _) => pos,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct GestureConfig {
+    /// Max time between the clicks of a double or a triple click
+    pub multi_click: Duration,
+    /// How long the button has to be held still for a long press
+    pub long_press: Duration,
+    /// How many cells the mouse can move while pressed before it becomes a drag
+    pub drag_distance: u16,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        GestureConfig {
+            multi_click: Duration::from_millis(400),
+            long_press: Duration::from_millis(600),
+            drag_distance: 0,
+        }
+    }
+}
+
+struct Press {
+    button: MouseButton,
+    pos: Position,
+    mods: Modifiers,
+    at: Instant,
+    dragging: bool,
+    long_pressed: bool,
+}
+
+struct LastClick {
+    button: MouseButton,
+    pos: Position,
+    at: Instant,
+    count: u8,
+}
+
+/// Turns the raw mouse events into higher level gestures.
+///
+/// It is a plain state machine that is fed with the raw events
+/// (which the caller still handles as usual) and the time they were received at,
+/// so it does not care whether those come from the terminal or from a replay.
+/// Long presses happen without any event, so the caller has to wait
+/// until the `deadline` and then `tick` the recognizer.
+pub struct GestureRecognizer {
+    config: GestureConfig,
+    press: Option<Press>,
+    last_click: Option<LastClick>,
+}
+
+fn distance(a: Position, b: Position) -> u16 {
+    let dx = (a.x as i32 - b.x as i32).unsigned_abs();
+    let dy = (a.y as i32 - b.y as i32).unsigned_abs();
+    dx.max(dy) as u16
+}
+
+impl GestureRecognizer {
+    pub fn new(config: GestureConfig) -> GestureRecognizer {
+        GestureRecognizer { config, press: None, last_click: None }
+    }
+
+    pub fn feed(&mut self, event: &Event, now: Instant) -> Option<Gesture> {
+        match *event {
+            Event::Mouse(MouseAction::Press, button, pos, mods) => {
+                self.press = Some(Press { button, pos, mods, at: now, dragging: false, long_pressed: false });
+                None
+            }
+            Event::Mouse(MouseAction::Drag, _, pos, _) | Event::MouseMotion(pos, _) => {
+                let press = self.press.as_mut()?;
+                if press.dragging || distance(press.pos, pos) <= self.config.drag_distance {
+                    return None;
+                }
+                press.dragging = true;
+                self.last_click = None;
+                Some(Gesture::DragStart(press.button, press.pos, press.mods))
+            }
+            Event::Mouse(MouseAction::Release, _, pos, mods) => {
+                let press = self.press.take()?;
+                if press.dragging {
+                    return Some(Gesture::DragEnd(press.button, pos, mods));
+                }
+                if press.long_pressed {
+                    self.last_click = None;
+                    return None;
+                }
+                let count = match &self.last_click {
+                    Some(last) if last.button == press.button
+                        && last.count < 3
+                        && now.duration_since(last.at) <= self.config.multi_click
+                        && distance(last.pos, press.pos) <= self.config.drag_distance => last.count + 1,
+                    _ => 1,
+                };
+                self.last_click = Some(LastClick { button: press.button, pos: press.pos, at: now, count });
+                Some(match count {
+                    1 => Gesture::Click(press.button, press.pos, press.mods),
+                    2 => Gesture::DoubleClick(press.button, press.pos, press.mods),
+                    _ => Gesture::TripleClick(press.button, press.pos, press.mods),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// When the current press would become a long one, if there is any
+    pub fn deadline(&self) -> Option<Instant> {
+        match &self.press {
+            Some(press) if !press.dragging && !press.long_pressed => Some(press.at + self.config.long_press),
+            _ => None,
+        }
+    }
+
+    pub fn tick(&mut self, now: Instant) -> Option<Gesture> {
+        let deadline = self.deadline()?;
+        if now < deadline {
+            return None;
+        }
+        let press = self.press.as_mut()?;
+        press.long_pressed = true;
+        Some(Gesture::LongPress(press.button, press.pos, press.mods))
+    }
+}
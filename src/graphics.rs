@@ -0,0 +1,135 @@
+use std::io::Write;
+
+use crate::draw::Color;
+use crate::state::{Dimension, Pixel};
+
+/// How the terminal can show actual images
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Graphics {
+    /// No images at all, so they're made out of half-block cells
+    HalfBlocks,
+    /// https://sw.kovidgoyal.net/kitty/graphics-protocol/
+    Kitty,
+    Sixel,
+}
+
+/// Id of the image we ask the terminal about to see if it knows the kitty protocol
+pub const KITTY_QUERY_ID: u32 = 31;
+
+/// Asks whether the kitty graphics protocol is there and then for the device attributes,
+/// which tell about sixel. Since every terminal answers the latter, when that comes in
+/// without the kitty answer before it, there is no kitty protocol
+pub fn capability_query() -> Vec<u8> {
+    format!("\x1b_Gi={},s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\\x1b[c", KITTY_QUERY_ID).into_bytes()
+}
+
+/// Whether the device attributes (the answer to DA1) have sixel in them
+pub fn has_sixel(attributes: &[u16]) -> bool {
+    attributes.iter().skip(1).any(|&a| a == 4)
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut res = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                res.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                res.push('=');
+            }
+        }
+    }
+    res
+}
+
+/// Transmits and shows an RGBA image with the given id at the cursor, scaled to fit
+/// the given number of cells. An image with the same id is replaced.
+/// The cursor is not moved and the terminal is told not to answer
+pub fn kitty_image(id: u32, pixels: &[Pixel], size: Dimension, cells: Dimension) -> Vec<u8> {
+    let rgba: Vec<u8> = pixels.iter().flat_map(|p| [p.r, p.g, p.b, p.a]).collect();
+    let data = base64(&rgba);
+    let mut out = kitty_delete(id);
+    // the payload has to be sent in chunks of at most 4096 bytes
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(4096).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = (i + 1 < chunks.len()) as u8;
+        if i == 0 {
+            let _ = write!(out, "\x1b_Ga=T,f=32,i={},s={},v={},c={},r={},C=1,q=2,m={};",
+                           id, size.width, size.height, cells.width, cells.height, more);
+        } else {
+            let _ = write!(out, "\x1b_Gm={};", more);
+        }
+        out.extend_from_slice(chunk);
+        out.extend_from_slice(b"\x1b\\");
+    }
+    out
+}
+
+/// Deletes the image with the given id, along with its data
+pub fn kitty_delete(id: u32) -> Vec<u8> {
+    format!("\x1b_Ga=d,d=I,i={},q=2\x1b\\", id).into_bytes()
+}
+
+/// Encodes the image as sixels at the cursor, every pixel repeated `scale` times.
+/// The colors are reduced to the 256-color palette, since that's about
+/// as many color registers as the terminals have, and the transparent pixels are left out
+pub fn sixel_image(pixels: &[Pixel], size: Dimension, scale: u16) -> Vec<u8> {
+    let scale = scale.max(1) as usize;
+    let (width, height) = (size.width as usize * scale, size.height as usize * scale);
+    // palette index, or none for the transparent ones
+    let index = |x: usize, y: usize| {
+        let pixel = pixels[(y / scale) * size.width as usize + x / scale];
+        Some(pixel).filter(|p| p.a >= 128).map(|p| Color::new(p.r, p.g, p.b).to_256())
+    };
+
+    let mut out = Vec::new();
+    // P2 = 1 leaves the zero bits alone, which is what makes the transparency
+    let _ = write!(out, "\x1bP0;1;0q\"1;1;{};{}", width, height);
+    let mut used = [false; 256];
+    for y in 0..height {
+        for x in 0..width {
+            if let Some(i) = index(x, y) {
+                used[i as usize] = true;
+            }
+        }
+    }
+    for (i, _) in used.iter().enumerate().filter(|(_, &used)| used) {
+        let Color { r, g, b } = crate::draw::xterm_color(i as u8);
+        let percent = |c: u8| c as u32 * 100 / 255;
+        let _ = write!(out, "#{};2;{};{};{}", i, percent(r), percent(g), percent(b));
+    }
+    for band in (0..height).step_by(6) {
+        let rows = (height - band).min(6);
+        let mut first = true;
+        for color in (0..256).filter(|&i| used[i]) {
+            let sixels: Vec<u8> = (0..width)
+                .map(|x| (0..rows).filter(|&dy| index(x, band + dy) == Some(color as u8)).fold(0, |bits, dy| bits | 1 << dy))
+                .collect();
+            if sixels.iter().all(|&s| s == 0) {
+                continue;
+            }
+            if !first {
+                out.push(b'$');
+            }
+            first = false;
+            let _ = write!(out, "#{}", color);
+            // run-length encoded
+            let mut x = 0;
+            while x < width {
+                let run = sixels[x..].iter().take_while(|&&s| s == sixels[x]).count();
+                let ch = 63 + sixels[x];
+                if run > 3 {
+                    let _ = write!(out, "!{}{}", run, ch as char);
+                } else {
+                    out.extend(std::iter::repeat_n(ch, run));
+                }
+                x += run;
+            }
+        }
+        out.push(b'-');
+    }
+    out.extend_from_slice(b"\x1b\\");
+    out
+}
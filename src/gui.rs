@@ -0,0 +1,1819 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs,
+    io,
+    io::Write,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use crate::{
+    command,
+    dialog::*,
+    document::{self, Autosave},
+    history::History,
+    draw::*,
+    gesture::*,
+    graphics::*,
+    input::*,
+    png,
+    scale::Scaler,
+    state::*,
+    transform::{Target, Transform},
+    layout::*,
+    surface::*,
+};
+
+/// How the panels have their title bars, a lighter one for the focused panel
+fn title_style(focused: bool) -> Style {
+    let bg = if focused { Color::gray(100) } else { Color::gray(60) };
+    CellColor::default().bg(bg).fg(Color::gray(200)).attrs(Attributes::default().bold())
+}
+
+/// What a widget did with an event it was given
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Handled {
+    /// The widget took it, nothing else gets to see it
+    Consumed,
+    /// Passed on, keys to the other widgets and then the key bindings,
+    /// mouse presses to the button bindings
+    Bubble,
+}
+
+/// Widgets get the mouse events within their part of the screen, with the positions
+/// relative to it. A press captures the mouse, so that the drags and the release
+/// go to the same widget wherever they happen, clamped to its top left corner.
+/// Keys go to the focused widget first, then to the others in the order they were added
+pub trait Widget {
+    /// Draws into the part of the screen the layout gave to the widget
+    fn draw(&self, gui: &GuiState, surface: &mut Surface) -> io::Result<()>;
+
+    /// How much room the widget would like, for the `Size::Auto` ones in the layout
+    fn size_hint(&self, _: &GuiState) -> Dimension {
+        Dimension { width: 0, height: 0 }
+    }
+
+    fn on_mouse_input(&mut self, _: &mut GuiState, _: MouseAction, _: MouseButton, _: Position, _: Modifiers) -> io::Result<Handled> {
+        Ok(Handled::Bubble)
+    }
+
+    fn on_mouse_wheel(&mut self, _: &mut GuiState, _: MouseWheelDirection, _: Position, _: Modifiers) -> io::Result<Handled> {
+        Ok(Handled::Bubble)
+    }
+
+    fn on_gesture(&mut self, _: &mut GuiState, _: Gesture, _: Position) -> io::Result<Handled> {
+        Ok(Handled::Bubble)
+    }
+
+    fn on_key(&mut self, _: &mut GuiState, _: &Event) -> io::Result<Handled> {
+        Ok(Handled::Bubble)
+    }
+
+    /// The mouse came over the widget, or left it
+    fn on_hover(&mut self, _: &mut GuiState, _: bool) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Whether Tab can move the keyboard focus to the widget
+    fn focusable(&self, _: &GuiState) -> bool {
+        false
+    }
+
+    /// The widget got the keyboard focus, or lost it
+    fn on_focus(&mut self, _: &mut GuiState, _: bool) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A dialog over everything else, which gets all of the input while it's open.
+/// The size hint is for the inside, the frame with the title goes around it
+pub trait Modal: Widget {
+    fn title(&self) -> String;
+
+    /// Whether it's done with and can be closed, the escape closes it anyway
+    fn done(&self) -> bool;
+
+    /// Called when the escape closes it
+    fn cancel(&mut self, _: &mut GuiState) {}
+}
+
+/// Where the dialog goes, in the middle of the screen, with the frame
+fn modal_rect(terminal: Dimension, inside: Dimension) -> Rect {
+    let width = (inside.width + 4).min(terminal.width);
+    let height = (inside.height + 2).min(terminal.height);
+    let pos = Position { x: (terminal.width - width) / 2, y: (terminal.height - height) / 2 };
+    (pos, Dimension { width, height })
+}
+
+/// What's inside of the frame of the dialog
+fn modal_inside((pos, size): Rect) -> Rect {
+    let size = Dimension { width: size.width.saturating_sub(4), height: size.height.saturating_sub(2) };
+    (pos + Position { x: 2, y: 1 }, size)
+}
+
+fn is_key(event: &Event) -> bool {
+    matches!(event, Event::Press(..) | Event::Enter | Event::Space | Event::Tab | Event::BackTab | Event::Escape | Event::Backspace
+        | Event::Home | Event::End | Event::Insert | Event::Delete | Event::PgUp | Event::PgDown | Event::Arrow(..))
+}
+
+/// How the picture pixels are laid out onto the terminal cells
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CanvasMode {
+    /// Each pixel takes two cells in a row, so that they look square
+    Wide,
+    /// Each cell holds two pixels, one above the other, drawn with the upper half-block
+    HalfBlocks,
+    /// The denser ones are only good for previews, since the two colors
+    /// per cell are fitted to the pixels, losing the rest
+    Glyphs(Glyphs),
+}
+
+impl CanvasMode {
+    pub fn next(self) -> CanvasMode {
+        match self {
+            CanvasMode::Wide => CanvasMode::HalfBlocks,
+            CanvasMode::HalfBlocks => CanvasMode::Glyphs(Glyphs::Quadrants),
+            CanvasMode::Glyphs(Glyphs::Quadrants) => CanvasMode::Glyphs(Glyphs::Sextants),
+            CanvasMode::Glyphs(Glyphs::Sextants) => CanvasMode::Glyphs(Glyphs::Braille),
+            CanvasMode::Glyphs(Glyphs::Braille) => CanvasMode::Wide,
+        }
+    }
+
+    /// How it's called in the status bar
+    pub fn name(self) -> &'static str {
+        match self {
+            CanvasMode::Wide => "wide",
+            CanvasMode::HalfBlocks => "half blocks",
+            CanvasMode::Glyphs(Glyphs::Quadrants) => "quadrants",
+            CanvasMode::Glyphs(Glyphs::Sextants) => "sextants",
+            CanvasMode::Glyphs(Glyphs::Braille) => "braille",
+        }
+    }
+
+    /// How many pixels a cell holds, `None` in the wide mode where it's the other way around
+    pub fn block(self) -> Option<Dimension> {
+        match self {
+            CanvasMode::Wide => None,
+            CanvasMode::HalfBlocks => Some(Dimension { width: 1, height: 2 }),
+            CanvasMode::Glyphs(glyphs) => Some(glyphs.block()),
+        }
+    }
+}
+
+/// What the left button does on the canvas, the other ones always erase
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Tool {
+    Pencil,
+    Eraser,
+}
+
+impl Tool {
+    pub fn next(self) -> Tool {
+        match self {
+            Tool::Pencil => Tool::Eraser,
+            Tool::Eraser => Tool::Pencil,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tool::Pencil => "pencil",
+            Tool::Eraser => "eraser",
+        }
+    }
+}
+
+/// The picture itself, where the painting happens
+pub struct TheField {
+    /// Whether the stroke going on painted anything yet, it's only recorded for undoing once it does
+    painted: bool,
+}
+
+impl TheField {
+    /// Color of the picture pixel, as it should be drawn
+    fn ink(gui: &GuiState, depth: ColorDepth, pos: Position) -> Color {
+        let color: Color = gui.picture.composite(pos).into();
+        if gui.dither {
+            // dithering to the picture pixels, so the pattern scrolls along with them
+            color.dither(depth, pos)
+        } else {
+            color
+        }
+    }
+}
+
+impl Widget for TheField {
+    fn draw(&self, gui: &GuiState, surface: &mut Surface) -> io::Result<()> {
+        let depth = surface.color_depth();
+        let ink = |pos: Position| Self::ink(gui, depth, gui.scroll + pos);
+        let view = gui.view();
+        let Dimension { width, height } = view;
+
+        match gui.canvas {
+            CanvasMode::Wide => {
+                for pos in view {
+                    let color = CellColor::default().bg(ink(pos));
+                    surface.text(Position { x: pos.x * 2, y: pos.y }, color, "  ")
+                }
+            }
+            CanvasMode::HalfBlocks => {
+                let rows = Dimension { width, height: height.div_ceil(2) };
+                for Position { x, y } in rows {
+                    let upper = Position { x, y: y * 2 };
+                    let mut color = CellColor::default().fg(ink(upper));
+                    if y * 2 + 1 < height {
+                        color = color.bg(ink(upper + Position { x: 0, y: 1 }));
+                    }
+                    surface.put(Position { x, y }, CharCell::new('▀').color(color));
+                }
+            }
+            CanvasMode::Glyphs(glyphs) => {
+                let block = glyphs.block();
+                let cells = Dimension { width: width.div_ceil(block.width), height: height.div_ceil(block.height) };
+                let mut pixels = Vec::with_capacity(block.number());
+                for cell in cells {
+                    pixels.clear();
+                    for Position { x, y } in block {
+                        let pos = Position { x: cell.x * block.width + x, y: cell.y * block.height + y };
+                        pixels.push(Some(pos).filter(|pos| pos.x < width && pos.y < height).map(ink));
+                    }
+                    surface.put(cell, fit_cell(glyphs, &pixels));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn on_mouse_input(&mut self, gui: &mut GuiState, action: MouseAction, button: MouseButton, _: Position, _: Modifiers) -> io::Result<Handled> {
+        let pixel = match (button, gui.tool) {
+            (MouseButton::Left, Tool::Pencil) => Pixel { r: gui.primary.r, g: gui.primary.g, b: gui.primary.b, a: 255 },
+            (MouseButton::Left, Tool::Eraser) | (MouseButton::Middle, _) | (MouseButton::Right, _) => Pixel { r: 0, g: 0, b: 0, a: 0 },
+            _ => return Ok(Handled::Bubble),
+        };
+        if action == MouseAction::Press {
+            self.painted = false;
+        }
+        // the stroke goes on while the mouse is away, painting again once it's back over the canvas
+        if let Some(center) = gui.pixel_at(gui.mouse) {
+            // a whole stroke is undone at once
+            if !self.painted {
+                gui.change();
+                self.painted = true;
+            }
+            let size = gui.picture.size;
+            // the brush is a square, centered on the pixel, or a bit up and left of it when even
+            let corner = Position { x: center.x.saturating_sub((gui.brush - 1) / 2), y: center.y.saturating_sub((gui.brush - 1) / 2) };
+            for offset in (Dimension { width: gui.brush, height: gui.brush }) {
+                let pos = corner + offset;
+                if pos.x < size.width && pos.y < size.height {
+                    gui.picture.layers[gui.current_layer].pixels[size.offset(pos)] = pixel;
+                }
+            }
+        }
+        Ok(Handled::Consumed)
+    }
+
+    fn on_mouse_wheel(&mut self, gui: &mut GuiState, direction: MouseWheelDirection, _: Position, _: Modifiers) -> io::Result<Handled> {
+        // keep the rows of cells aligned to the same rows of pixels
+        let dy = gui.canvas.block().map_or(1, |block| block.height as i32);
+        match direction {
+            MouseWheelDirection::Up => gui.scroll_by(0, -dy),
+            MouseWheelDirection::Down => gui.scroll_by(0, dy),
+            MouseWheelDirection::Left => gui.scroll_by(-1, 0),
+            MouseWheelDirection::Right => gui.scroll_by(1, 0),
+        }
+        Ok(Handled::Consumed)
+    }
+
+    fn on_hover(&mut self, gui: &mut GuiState, entered: bool) -> io::Result<()> {
+        gui.canvas_hovered = entered;
+        Ok(())
+    }
+}
+
+/// The pixel numbers along the top or the left side of the canvas,
+/// with the ones under the mouse highlighted while it's over the canvas
+pub struct Ruler {
+    vertical: bool,
+}
+
+impl Widget for Ruler {
+    fn draw(&self, gui: &GuiState, surface: &mut Surface) -> io::Result<()> {
+        let Dimension { width, height } = gui.view();
+        let hovered = gui.pixel_under(gui.mouse).unwrap_or(gui.scroll) - gui.scroll;
+
+        // a label is two cells wide and a row is a single one, so when cells
+        // hold more than one pixel the rulers only label every few pixels
+        let block = gui.canvas.block().unwrap_or(Dimension { width: 1, height: 1 });
+        let (step_x, step_y) = match gui.canvas {
+            CanvasMode::Wide => (1, 1),
+            _ => (block.width * 2, block.height),
+        };
+        let (length, step, hovered, scroll) = match self.vertical {
+            false => (width, step_x, hovered.x.min(width.max(1) - 1), gui.scroll.x),
+            true => (height, step_y, hovered.y.min(height.max(1) - 1), gui.scroll.y),
+        };
+
+        for i in (0..length).step_by(step as usize) {
+            let label = i / step;
+            let bg = if gui.focused && gui.canvas_hovered && label == hovered / step {
+                Color::gray(40)
+            } else {
+                Color::gray(120 - ((label % 2) as u8 * 20))
+            };
+            let pos = match (self.vertical, gui.canvas) {
+                (true, _) => Position { x: 0, y: label },
+                (false, CanvasMode::Wide) => Position { x: i * 2, y: 0 },
+                (false, _) => Position { x: i / block.width, y: 0 },
+            };
+            surface.text(pos, CellColor::default().bg(bg), format!("{:0>2}", (scroll + i) % 100));
+        }
+        Ok(())
+    }
+}
+
+/// The primary and the secondary color, double click to pick them
+pub struct Swatches;
+
+impl Widget for Swatches {
+    fn draw(&self, gui: &GuiState, surface: &mut Surface) -> io::Result<()> {
+        surface.put(Position { x: 0, y: 0 }, CharCell::new(' ').bg(gui.primary.clone()));
+        surface.put(Position { x: 1, y: 0 }, CharCell::new(' ').bg(gui.secondary.clone()));
+        Ok(())
+    }
+
+    fn on_gesture(&mut self, gui: &mut GuiState, gesture: Gesture, pos: Position) -> io::Result<Handled> {
+        if let Gesture::DoubleClick(MouseButton::Left, _, _) = gesture {
+            gui.picker = match pos {
+                Position { x: 0, y: 0 } => Some(Swatch::Primary),
+                Position { x: 1, y: 0 } => Some(Swatch::Secondary),
+                _ => return Ok(Handled::Bubble),
+            };
+            return Ok(Handled::Consumed);
+        }
+        Ok(Handled::Bubble)
+    }
+}
+
+/// Lists the layers, top one first, to pick the current one or rename it.
+/// When focused, the arrows move the current layer up and down
+pub struct LayersPanel {
+    renaming: Option<Renaming>,
+    focused: bool,
+}
+
+/// A layer being renamed
+struct Renaming {
+    layer: usize,
+    /// The name typed in so far
+    name: String,
+    /// How many layers there were when it started. It's called off once that changes,
+    /// as the bindings still work and an undo can take the layer away
+    layers: usize,
+}
+
+impl LayersPanel {
+    const WIDTH: u16 = 20;
+
+    fn layer_at(&self, gui: &GuiState, pos: Position) -> Option<usize> {
+        let count = gui.picture.layers.len();
+        let row = pos.y as usize;
+        if row >= 1 && row <= count {
+            Some(count - row)
+        } else {
+            None
+        }
+    }
+}
+
+impl Widget for LayersPanel {
+    fn draw(&self, gui: &GuiState, surface: &mut Surface) -> io::Result<()> {
+        let width = Self::WIDTH as usize;
+        surface.text(Position::default(), title_style(self.focused), format!("{:<1$}", " Layers", width));
+
+        let count = gui.picture.layers.len();
+        for (row, layer) in gui.picture.layers.iter().rev().enumerate() {
+            let idx = count - 1 - row;
+            let text = match &self.renaming {
+                Some(renaming) if renaming.layer == idx && renaming.layers == count => format!(" {}_", renaming.name),
+                _ => format!(" {}", layer.name),
+            };
+            let bg = if idx == gui.current_layer { Color::gray(90) } else { Color::gray(30) };
+            let pos = Position { x: 0, y: row as u16 + 1 };
+            surface.text(pos, CellColor::default().bg(bg).fg(Color::gray(220)), format!("{:<1$}", text, width));
+        }
+        Ok(())
+    }
+
+    fn size_hint(&self, gui: &GuiState) -> Dimension {
+        Dimension { width: Self::WIDTH, height: gui.picture.layers.len() as u16 + 1 }
+    }
+
+    fn on_mouse_input(&mut self, gui: &mut GuiState, action: MouseAction, button: MouseButton, pos: Position, _: Modifiers) -> io::Result<Handled> {
+        match (action, button, self.layer_at(gui, pos)) {
+            (MouseAction::Press, MouseButton::Left, Some(idx)) => gui.current_layer = idx,
+            (_, MouseButton::Left, _) => {}
+            _ => return Ok(Handled::Bubble),
+        }
+        Ok(Handled::Consumed)
+    }
+
+    fn on_gesture(&mut self, gui: &mut GuiState, gesture: Gesture, pos: Position) -> io::Result<Handled> {
+        if let (Gesture::DoubleClick(MouseButton::Left, _, _), Some(idx)) = (gesture, self.layer_at(gui, pos)) {
+            self.renaming = Some(Renaming { layer: idx, name: gui.picture.layers[idx].name.clone(), layers: gui.picture.layers.len() });
+            return Ok(Handled::Consumed);
+        }
+        Ok(Handled::Bubble)
+    }
+
+    fn on_key(&mut self, gui: &mut GuiState, event: &Event) -> io::Result<Handled> {
+        if self.renaming.as_ref().is_some_and(|renaming| renaming.layers != gui.picture.layers.len()) {
+            self.renaming = None;
+        }
+        let Renaming { layer: idx, name, .. } = match &mut self.renaming {
+            Some(renaming) => renaming,
+            None if self.focused => {
+                // the list has the top layer first
+                match *event {
+                    Event::Arrow(Arrow::Up, Modifiers::None) => {
+                        gui.current_layer = (gui.current_layer + 1).min(gui.picture.layers.len() - 1);
+                    }
+                    Event::Arrow(Arrow::Down, Modifiers::None) => gui.current_layer = gui.current_layer.saturating_sub(1),
+                    _ => return Ok(Handled::Bubble),
+                }
+                return Ok(Handled::Consumed);
+            }
+            None => return Ok(Handled::Bubble),
+        };
+        match *event {
+            Event::Press(ch, Modifiers::None) => name.push(ch),
+            Event::Press(ch, Modifiers::Shift) => name.push(ch.to_ascii_uppercase()),
+            Event::Space => name.push(' '),
+            Event::Backspace => {
+                name.pop();
+            }
+            Event::Enter => {
+                let name = std::mem::take(name);
+                if gui.picture.layers[*idx].name != name {
+                    gui.change();
+                    gui.picture.layers[*idx].name = name;
+                }
+                self.renaming = None;
+            }
+            Event::Escape => self.renaming = None,
+            // only the keys the editing needs, the bindings still work while renaming
+            _ => return Ok(Handled::Bubble),
+        }
+        Ok(Handled::Consumed)
+    }
+
+    fn focusable(&self, _: &GuiState) -> bool {
+        true
+    }
+
+    fn on_focus(&mut self, _: &mut GuiState, focused: bool) -> io::Result<()> {
+        self.focused = focused;
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Swatch {
+    Primary,
+    Secondary,
+}
+
+/// RGB sliders for one of the swatches, shown when `GuiState::picker` is set
+pub struct ColorPicker;
+
+impl ColorPicker {
+    const STEPS: u16 = 32;
+    const WIDTH: u16 = Self::STEPS + 2;
+
+    fn channel(color: &mut Color, idx: u16) -> &mut u8 {
+        match idx {
+            0 => &mut color.r,
+            1 => &mut color.g,
+            _ => &mut color.b,
+        }
+    }
+}
+
+impl Widget for ColorPicker {
+    fn draw(&self, gui: &GuiState, surface: &mut Surface) -> io::Result<()> {
+        let swatch = match gui.picker {
+            Some(swatch) => swatch,
+            None => return Ok(()),
+        };
+        let color = gui.swatch(swatch).clone();
+
+        let title = title_style(false);
+        let name = if swatch == Swatch::Primary { "Primary" } else { "Secondary" };
+        let text = format!(" {} #{:02x}{:02x}{:02x}", name, color.r, color.g, color.b);
+        surface.text(Position::default(), title, format!("{:<1$}", text, Self::WIDTH as usize));
+
+        for (row, label) in "RGB".chars().enumerate() {
+            let row = row as u16;
+            let y = row + 1;
+            surface.put(Position { x: 0, y }, CharCell::new(label).bg(Color::gray(30)).fg(Color::gray(200)));
+            surface.put(Position { x: 1, y }, CharCell::new(' ').bg(Color::gray(30)));
+            let current = *Self::channel(&mut color.clone(), row) as u16 * (Self::STEPS - 1) / 255;
+            for i in 0..Self::STEPS {
+                let mut step = color.clone();
+                *Self::channel(&mut step, row) = (i * 255 / (Self::STEPS - 1)) as u8;
+                let marker = if i == current { '│' } else { ' ' };
+                let light = step.r as u16 + step.g as u16 + step.b as u16 > 384;
+                let fg = Color::gray(if light { 0 } else { 255 });
+                surface.put(Position { x: 2 + i, y }, CharCell::new(marker).bg(step).fg(fg));
+            }
+        }
+        Ok(())
+    }
+
+    fn size_hint(&self, gui: &GuiState) -> Dimension {
+        match gui.picker {
+            Some(_) => Dimension { width: Self::WIDTH, height: 4 },
+            None => Dimension { width: 0, height: 0 },
+        }
+    }
+
+    fn on_mouse_input(&mut self, gui: &mut GuiState, action: MouseAction, button: MouseButton, pos: Position, _: Modifiers) -> io::Result<Handled> {
+        let swatch = match (gui.picker, button) {
+            (Some(swatch), MouseButton::Left) => swatch,
+            _ => return Ok(Handled::Bubble),
+        };
+        if action != MouseAction::Release && pos.x >= 2 && pos.x < Self::STEPS + 2 && pos.y >= 1 && pos.y <= 3 {
+            *Self::channel(gui.swatch_mut(swatch), pos.y - 1) = ((pos.x - 2) * 255 / (Self::STEPS - 1)) as u8;
+        }
+        Ok(Handled::Consumed)
+    }
+
+    fn on_key(&mut self, gui: &mut GuiState, event: &Event) -> io::Result<Handled> {
+        match (gui.picker, event) {
+            (Some(_), Event::Escape) | (Some(_), Event::Enter) => {
+                gui.picker = None;
+                Ok(Handled::Consumed)
+            }
+            _ => Ok(Handled::Bubble),
+        }
+    }
+}
+
+/// Shows the whole picture as it is, pixel for pixel, when `GuiState::preview` is set.
+/// With the terminal graphics it's an actual image over the cells, otherwise half-blocks.
+/// The wheel, or the arrows when it's focused, change the scale
+pub struct Preview {
+    scale: u16,
+    focused: bool,
+    /// The image as it was encoded the last time, it's only encoded again once it changes
+    encoded: RefCell<Option<Encoded>>,
+}
+
+/// What the preview image was encoded from, and what it turned into
+struct Encoded {
+    picture: Picture,
+    scale: u16,
+    graphics: Graphics,
+    cells: Dimension,
+    bytes: Vec<u8>,
+}
+
+impl Preview {
+    const KITTY_ID: u32 = 1;
+    const MAX_SCALE: u16 = 8;
+
+    /// Cells the image takes at the current scale. A picture scaled past what a u16 holds
+    /// is that many, far more than any screen has room for anyway
+    fn cells(&self, gui: &GuiState) -> Dimension {
+        let Dimension { width, height } = gui.picture.size;
+        let cells = |length: u16, cell: u16| (length as u32 * self.scale as u32).div_ceil(cell as u32).min(u16::MAX as u32) as u16;
+        match gui.graphics {
+            Graphics::HalfBlocks => Dimension { width: cells(width, 1), height: cells(height, 2) },
+            _ => {
+                // a guess, for when the terminal doesn't tell
+                let cell = gui.cell_size.unwrap_or(Dimension { width: 8, height: 16 });
+                Dimension { width: cells(width, cell.width), height: cells(height, cell.height) }
+            }
+        }
+    }
+
+    /// Whether the image fits into the surface below the title
+    fn fits(&self, gui: &GuiState, surface: &Surface) -> bool {
+        let cells = self.cells(gui);
+        let Dimension { width, height } = surface.size();
+        // sixels scroll the screen when they touch the bottom line, so keep away from it
+        cells.width <= width && cells.height < height && surface.origin().y + 1 + cells.height < gui.terminal.height
+    }
+
+    fn title(&self) -> String {
+        format!(" Preview {}x", self.scale)
+    }
+
+    /// The composited picture, with the transparency shown as a checkerboard
+    fn pixels(gui: &GuiState) -> Vec<Pixel> {
+        gui.picture.size.into_iter()
+            .map(|pos| {
+                let checker = if (pos.x / 4 + pos.y / 4) % 2 == 0 { 0x66 } else { 0x99 };
+                Pixel::blend(Pixel { r: checker, g: checker, b: checker, a: 0xff }, gui.picture.composite(pos))
+            })
+            .collect()
+    }
+
+    /// The image in the terminal graphics, encoded again only when the picture or how it's shown changed.
+    /// The picture itself tells whether it changed, as it's changed in too many places to keep count
+    fn image(&self, gui: &GuiState, cells: Dimension) -> Vec<u8> {
+        let mut encoded = self.encoded.borrow_mut();
+        if let Some(encoded) = &*encoded {
+            if encoded.scale == self.scale && encoded.graphics == gui.graphics && encoded.cells == cells && encoded.picture == gui.picture {
+                return encoded.bytes.clone();
+            }
+        }
+        let (pixels, size) = (Self::pixels(gui), gui.picture.size);
+        let bytes = match gui.graphics {
+            Graphics::Kitty => kitty_image(Self::KITTY_ID, &pixels, size, cells),
+            _ => sixel_image(&pixels, size, self.scale),
+        };
+        *encoded = Some(Encoded { picture: gui.picture.clone(), scale: self.scale, graphics: gui.graphics, cells, bytes: bytes.clone() });
+        bytes
+    }
+}
+
+impl Widget for Preview {
+    fn draw(&self, gui: &GuiState, surface: &mut Surface) -> io::Result<()> {
+        if !gui.preview {
+            return Ok(());
+        }
+        let cells = self.cells(gui);
+        let area = Position { x: 0, y: 1 };
+        let fits = self.fits(gui, surface);
+        let title = format!("{}{}", self.title(), if fits { "" } else { ", no room" });
+        surface.text(Position::default(), title_style(self.focused), format!("{:<1$}", title, surface.size().width as usize));
+        if !fits {
+            return Ok(());
+        }
+
+        let bytes = match gui.graphics {
+            Graphics::HalfBlocks => {
+                let (pixels, size) = (Self::pixels(gui), gui.picture.size);
+                let ink = |x: u16, y: u16| Color::from(pixels[size.offset(Position { x: x / self.scale, y: y / self.scale })]);
+                for cell in cells {
+                    let (x, y) = (cell.x, cell.y * 2);
+                    let mut color = CellColor::default().fg(ink(x, y));
+                    if y as u32 + 1 < size.height as u32 * self.scale as u32 {
+                        color = color.bg(ink(x, y + 1));
+                    }
+                    surface.put(area + cell, CharCell::new('▀').color(color));
+                }
+                return Ok(());
+            }
+            _ => self.image(gui, cells),
+        };
+        let clear = match gui.graphics {
+            Graphics::Kitty => kitty_delete(Self::KITTY_ID),
+            // the cells drawn over the sixels are enough
+            _ => vec![],
+        };
+        surface.overlay(Overlay { pos: area, size: cells, bytes, clear });
+        Ok(())
+    }
+
+    fn size_hint(&self, gui: &GuiState) -> Dimension {
+        if !gui.preview {
+            return Dimension { width: 0, height: 0 };
+        }
+        let cells = self.cells(gui);
+        let width = cells.width.max(text_width(&self.title()) as u16 + 1);
+        Dimension { width, height: cells.height.saturating_add(1) }
+    }
+
+    fn on_mouse_wheel(&mut self, _: &mut GuiState, direction: MouseWheelDirection, _: Position, _: Modifiers) -> io::Result<Handled> {
+        match direction {
+            MouseWheelDirection::Up => self.scale = (self.scale + 1).min(Self::MAX_SCALE),
+            MouseWheelDirection::Down => self.scale = (self.scale - 1).max(1),
+            _ => return Ok(Handled::Bubble),
+        }
+        Ok(Handled::Consumed)
+    }
+
+    fn on_key(&mut self, _: &mut GuiState, event: &Event) -> io::Result<Handled> {
+        match (self.focused, event) {
+            (true, Event::Arrow(Arrow::Up, Modifiers::None)) => self.scale = (self.scale + 1).min(Self::MAX_SCALE),
+            (true, Event::Arrow(Arrow::Down, Modifiers::None)) => self.scale = (self.scale - 1).max(1),
+            _ => return Ok(Handled::Bubble),
+        }
+        Ok(Handled::Consumed)
+    }
+
+    fn focusable(&self, gui: &GuiState) -> bool {
+        gui.preview
+    }
+
+    fn on_focus(&mut self, _: &mut GuiState, focused: bool) -> io::Result<()> {
+        self.focused = focused;
+        Ok(())
+    }
+}
+
+/// A line along the bottom telling the pixel under the mouse with its colors,
+/// then the tool, how the canvas shows the picture, and about the picture itself
+pub struct StatusBar;
+
+impl Widget for StatusBar {
+    fn draw(&self, gui: &GuiState, surface: &mut Surface) -> io::Result<()> {
+        let color = CellColor::default().bg(Color::gray(30)).fg(Color::gray(200));
+        surface.fill(CharCell::new(' ').color(color.clone()));
+
+        let mut x = 1;
+        let mut text = |surface: &mut Surface, text: String| {
+            surface.text(Position { x, y: 0 }, color.clone(), &text);
+            x += text_width(&text) as u16 + 2;
+        };
+        if let Some(pos) = gui.pixel_at(gui.mouse) {
+            text(surface, format!("{},{}", pos.x, pos.y));
+            let composite = gui.picture.composite(pos);
+            let layer = gui.picture.layers[gui.current_layer].pixels[gui.picture.size.offset(pos)];
+            text(surface, format!("{:?} layer {:?}", composite, layer));
+        }
+        text(surface, format!("{} {}px", gui.tool.name(), gui.brush));
+        text(surface, gui.canvas.name().to_string());
+
+        let Dimension { width, height } = gui.picture.size;
+        let layer = &gui.picture.layers[gui.current_layer].name;
+        let marker = if gui.modified { "*" } else { " " };
+        surface.aligned_text(0, Align::Right, color, format!("{}  {}x{}{} ", layer, width, height, marker));
+        Ok(())
+    }
+}
+
+/// What a command had to say
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    Info(String),
+    Error(String),
+}
+
+/// The `:` line at the bottom, for the commands with their arguments, and for everything
+/// the keys are bound to, by name. Up and down go through what was typed before and Tab
+/// completes. When closed, it shows what the last command had to say, if anything
+pub struct CommandLine {
+    text: TextInput,
+    history: Vec<String>,
+    /// Entry of the history being shown, while going through it
+    browsing: Option<usize>,
+    /// What Tab went through and which one of them is shown
+    completions: Option<(Vec<String>, usize)>,
+}
+
+impl CommandLine {
+    fn complete(&mut self, forward: bool) {
+        let (completions, i) = match self.completions.take() {
+            Some((completions, i)) => {
+                let count = completions.len();
+                let i = if forward { (i + 1) % count } else { (i + count - 1) % count };
+                (completions, i)
+            }
+            None => {
+                let completions = command::complete(self.text.text());
+                if completions.is_empty() {
+                    return;
+                }
+                let i = if forward { 0 } else { completions.len() - 1 };
+                (completions, i)
+            }
+        };
+        self.text.set(completions[i].clone());
+        // with just the one there's nothing to go through
+        if completions.len() > 1 {
+            self.completions = Some((completions, i));
+        }
+    }
+
+    fn browse(&mut self, back: bool) {
+        let last = match self.history.len() {
+            0 => return,
+            len => len - 1,
+        };
+        self.browsing = match (self.browsing, back) {
+            (None, true) => Some(last),
+            (None, false) => None,
+            (Some(i), true) => Some(i.saturating_sub(1)),
+            (Some(i), false) if i < last => Some(i + 1),
+            (Some(_), false) => None,
+        };
+        self.text.set(self.browsing.map_or_else(String::new, |i| self.history[i].clone()));
+    }
+
+    fn run(&mut self, gui: &mut GuiState) {
+        let line = self.text.take();
+        gui.command_line = false;
+        if line.trim().is_empty() {
+            return;
+        }
+        if self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+        }
+        match command::parse(&line) {
+            Ok(action) => gui.requested = Some(action),
+            Err(error) => gui.message = Some(Message::Error(error)),
+        }
+    }
+}
+
+impl Widget for CommandLine {
+    fn draw(&self, gui: &GuiState, surface: &mut Surface) -> io::Result<()> {
+        if !gui.command_line {
+            match &gui.message {
+                Some(Message::Info(info)) => surface.text(Position::default(), CellColor::default(), info),
+                Some(Message::Error(error)) => surface.text(Position::default(), CellColor::default().fg(Color { r: 255, g: 85, b: 85 }), error),
+                None => {}
+            }
+            return Ok(());
+        }
+        let count = match &self.completions {
+            Some((completions, i)) => format!("  ({}/{})", i + 1, completions.len()),
+            None => String::new(),
+        };
+        let Dimension { width, height } = surface.size();
+        let room = width.saturating_sub(text_width(&count) as u16 + 1);
+        surface.text(Position::default(), CellColor::default(), ":");
+        self.text.draw(&mut surface.sub(Position { x: 1, y: 0 }, Dimension { width: room, height }), 0, CellColor::default(), true);
+        let x = 1 + room.min(text_width(self.text.text()) as u16 + 1);
+        surface.text(Position { x, y: 0 }, CellColor::default().attrs(Attributes::default().dim()), count);
+        Ok(())
+    }
+
+    fn size_hint(&self, gui: &GuiState) -> Dimension {
+        match gui.command_line || gui.message.is_some() {
+            true => Dimension { width: 0, height: 1 },
+            false => Dimension { width: 0, height: 0 },
+        }
+    }
+
+    fn on_key(&mut self, gui: &mut GuiState, event: &Event) -> io::Result<Handled> {
+        if !gui.command_line {
+            return Ok(Handled::Bubble);
+        }
+        if !matches!(event, Event::Tab | Event::BackTab) {
+            self.completions = None;
+        }
+        match *event {
+            // like in vim, backspacing the `:` away closes it
+            Event::Backspace if self.text.is_empty() => gui.command_line = false,
+            Event::Tab => self.complete(true),
+            Event::BackTab => self.complete(false),
+            Event::Arrow(Arrow::Up, Modifiers::None) => self.browse(true),
+            Event::Arrow(Arrow::Down, Modifiers::None) => self.browse(false),
+            Event::Enter => self.run(gui),
+            Event::Escape => {
+                self.text.take();
+                gui.command_line = false;
+            }
+            _ => {
+                self.text.on_key(event);
+            }
+        }
+        if !matches!(event, Event::Arrow(..)) {
+            self.browsing = None;
+        }
+        Ok(Handled::Consumed)
+    }
+
+    fn focusable(&self, gui: &GuiState) -> bool {
+        gui.command_line
+    }
+}
+
+/// The colors loaded with `:palette load`, the left button picks the primary
+/// color out of them and the right one the secondary
+pub struct Palette;
+
+impl Palette {
+    const PER_ROW: u16 = 16;
+}
+
+impl Widget for Palette {
+    fn draw(&self, gui: &GuiState, surface: &mut Surface) -> io::Result<()> {
+        if gui.palette.is_empty() {
+            return Ok(());
+        }
+        surface.text(Position::default(), title_style(false), format!("{:<1$}", " Palette", surface.size().width as usize));
+        for (i, color) in gui.palette.iter().enumerate() {
+            let (x, y) = (i as u16 % Self::PER_ROW, i as u16 / Self::PER_ROW);
+            surface.text(Position { x: x * 2, y: y + 1 }, CellColor::default().bg(color.clone()), "  ");
+        }
+        Ok(())
+    }
+
+    fn size_hint(&self, gui: &GuiState) -> Dimension {
+        match gui.palette.len() as u16 {
+            0 => Dimension { width: 0, height: 0 },
+            count => Dimension { width: Self::PER_ROW * 2, height: 1 + count.div_ceil(Self::PER_ROW) },
+        }
+    }
+
+    fn on_mouse_input(&mut self, gui: &mut GuiState, action: MouseAction, button: MouseButton, pos: Position, _: Modifiers) -> io::Result<Handled> {
+        let swatch = match button {
+            MouseButton::Left => Swatch::Primary,
+            MouseButton::Right => Swatch::Secondary,
+            _ => return Ok(Handled::Bubble),
+        };
+        let i = pos.y.checked_sub(1).map(|row| (row * Self::PER_ROW + pos.x / 2) as usize);
+        if let (MouseAction::Press, true, Some(color)) = (action, pos.x < Self::PER_ROW * 2, i.and_then(|i| gui.palette.get(i))) {
+            *gui.swatch_mut(swatch) = color.clone();
+        }
+        Ok(Handled::Consumed)
+    }
+}
+
+pub struct GuiState {
+    pub terminal: Dimension,
+    pub tool: Tool,
+    /// Width and height of the square the tools paint, in pixels
+    pub brush: u16,
+    /// Whether the picture changed since it was last saved
+    pub modified: bool,
+    /// The file the picture is saved to, if it was opened from one or saved already
+    pub file: Option<PathBuf>,
+    pub history: History,
+    pub mouse: Position,
+    pub mouse_sub: Option<SubCell>,
+    /// Size of a cell in pixels, only known when the mouse reports pixel positions
+    pub pixel_mouse: Option<Dimension>,
+    pub focused: bool,
+    pub canvas: CanvasMode,
+    /// Picture pixel shown in the top left corner of the canvas
+    pub scroll: Position,
+    /// Where the canvas is on the screen, as laid out the last time
+    pub canvas_area: Rect,
+    /// Whether the mouse is over the canvas, or painting on it
+    pub canvas_hovered: bool,
+    /// Whether the canvas is dithered when the terminal has fewer colors
+    pub dither: bool,
+    pub preview: bool,
+    pub graphics: Graphics,
+    /// Size of a cell in pixels, if the terminal tells it
+    pub cell_size: Option<Dimension>,
+    pub primary: Color,
+    pub secondary: Color,
+    /// Swatch the color picker is open for
+    pub picker: Option<Swatch>,
+    /// The colors loaded with `:palette load`
+    pub palette: Vec<Color>,
+    /// Whether the `:` command line is open
+    pub command_line: bool,
+    /// What the last command had to say, until the next key
+    pub message: Option<Message>,
+    /// What a widget wants done, performed once it's done with the event
+    pub requested: Option<Action>,
+    /// Whether the autosave left behind is offered to be recovered, it's kept until that's answered
+    pub recovery_offered: bool,
+    pub picture: Picture,
+    pub current_layer: usize,
+}
+
+impl GuiState {
+    /// Remembers the picture as it is before changing it, so that the change can be undone
+    pub fn change(&mut self) {
+        self.history.record(&self.picture);
+        self.modified = true;
+    }
+
+    /// Keeps the current layer and the scroll within the picture, after its size
+    /// or its layers changed
+    fn fit_picture(&mut self) {
+        self.current_layer = self.current_layer.min(self.picture.layers.len() - 1);
+        self.scroll_by(0, 0);
+    }
+
+    /// Picture pixel under the given cell of the screen, not checked against the picture size.
+    /// Without the precise sub-cell position, the top left pixel of the cell is picked
+    pub fn pixel_under(&self, cell: Position) -> Option<Position> {
+        let origin = self.canvas_area.0;
+        if cell.x < origin.x || cell.y < origin.y {
+            return None;
+        }
+        let Position { x, y } = cell - origin;
+        Some(self.scroll + match self.canvas.block() {
+            None => Position { x: x / 2, y },
+            Some(Dimension { width, height }) => {
+                let sub = self.mouse_sub.unwrap_or_default();
+                Position {
+                    x: x * width + ((sub.x as u16 * width) >> 8),
+                    y: y * height + ((sub.y as u16 * height) >> 8),
+                }
+            }
+        })
+    }
+
+    /// Picture pixel under the given cell of the screen, if the cell is on the canvas
+    /// and there's a pixel there
+    fn pixel_at(&self, cell: Position) -> Option<Position> {
+        let (origin, size) = self.canvas_area;
+        if cell.x >= origin.x + size.width || cell.y >= origin.y + size.height {
+            return None;
+        }
+        let Dimension { width, height } = self.picture.size;
+        self.pixel_under(cell).filter(|pos| pos.x < width && pos.y < height)
+    }
+
+    /// How many pixels of the picture fit on the canvas, starting from the scroll position
+    pub fn view(&self) -> Dimension {
+        let Dimension { width, height } = self.canvas_area.1;
+        let (width, height) = match self.canvas.block() {
+            None => (width / 2, height),
+            Some(block) => (width * block.width, height * block.height),
+        };
+        let Dimension { width: pw, height: ph } = self.picture.size;
+        Dimension {
+            width: width.min(pw - self.scroll.x),
+            height: height.min(ph - self.scroll.y),
+        }
+    }
+
+    fn scroll_by(&mut self, dx: i32, dy: i32) {
+        let size = self.picture.size;
+        self.scroll = Position {
+            x: (self.scroll.x as i32 + dx).max(0).min(size.width as i32 - 1) as u16,
+            y: (self.scroll.y as i32 + dy).max(0).min(size.height as i32 - 1) as u16,
+        };
+    }
+
+    /// Remembers the mouse position reported by the terminal,
+    /// converting it from pixels into cells if needed, and returns it
+    pub fn locate_mouse(&mut self, pos: Position) -> Position {
+        match self.pixel_mouse {
+            Some(cell) => {
+                let (pos, sub) = SubCell::from_pixels(pos, cell);
+                self.mouse = pos;
+                self.mouse_sub = Some(sub);
+            }
+            None => {
+                self.mouse = pos;
+                self.mouse_sub = None;
+            }
+        }
+        self.mouse
+    }
+
+    /// Same as `locate_mouse`, but for any of the mouse events
+    pub fn locate_event(&mut self, event: Event) -> Event {
+        match event {
+            Event::Mouse(action, button, pos, mods) => Event::Mouse(action, button, self.locate_mouse(pos), mods),
+            Event::MouseMotion(pos, mods) => Event::MouseMotion(self.locate_mouse(pos), mods),
+            Event::MouseWheel(direction, pos, mods) => Event::MouseWheel(direction, self.locate_mouse(pos), mods),
+            event => event,
+        }
+    }
+
+    pub fn swatch(&self, swatch: Swatch) -> &Color {
+        match swatch {
+            Swatch::Primary => &self.primary,
+            Swatch::Secondary => &self.secondary,
+        }
+    }
+
+    pub fn swatch_mut(&mut self, swatch: Swatch) -> &mut Color {
+        match swatch {
+            Swatch::Primary => &mut self.primary,
+            Swatch::Secondary => &mut self.secondary,
+        }
+    }
+}
+
+/// What the caller of `Gui::handle` should do next
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Flow {
+    Continue,
+    /// The whole screen should be repainted, not only the changed cells
+    Redraw,
+    /// The screen should be wiped with `Gui::clear_screen`, without touching
+    /// what we think is drawn, so that only the changed cells come back
+    ClearScreen,
+    Quit,
+}
+
+pub struct Gui {
+    pub state: GuiState,
+    pub buffer: TerminalState,
+    widgets: Vec<Box<dyn Widget>>,
+    layout: Layout,
+    /// Where the widgets went, by their ids
+    rects: Vec<(usize, Rect)>,
+    /// Id of the canvas widget, `GuiState::canvas_area` is where it is
+    canvas: usize,
+    /// Id of the command line, which gets the focus when it opens
+    command_line: usize,
+    /// Dialogs over everything else, the last one is on top and gets all of the input
+    modals: Vec<Box<dyn Modal>>,
+    /// Widget the keys go to first
+    focus: Option<usize>,
+    /// Widget that got the last mouse press, until the release
+    captured: Option<usize>,
+    /// Widget the mouse is over, or the captured one
+    hovered: Option<usize>,
+    keymap: Keymap,
+    gestures: GestureRecognizer,
+}
+
+impl Gui {
+    const MAX_BRUSH: u16 = 16;
+
+    pub fn new(terminal: Dimension, picture: Picture) -> Gui {
+        let mut gui = Gui {
+            state: GuiState {
+                terminal,
+                tool: Tool::Pencil,
+                brush: 1,
+                modified: false,
+                file: None,
+                history: History::default(),
+                mouse: Position::default(),
+                mouse_sub: None,
+                pixel_mouse: None,
+                focused: true,
+                canvas: CanvasMode::Wide,
+                scroll: Position::default(),
+                canvas_area: (Position::default(), terminal),
+                canvas_hovered: false,
+                dither: false,
+                preview: false,
+                graphics: Graphics::HalfBlocks,
+                cell_size: None,
+                primary: Color::gray(255),
+                secondary: Color::gray(0),
+                picker: None,
+                palette: vec![],
+                command_line: false,
+                message: None,
+                requested: None,
+                recovery_offered: false,
+                current_layer: picture.layers.len() - 1,
+                picture,
+            },
+            buffer: TerminalState::new(Dimension { width: 80 * 4, height: 24 * 4 }),
+            widgets: vec![],
+            layout: Layout::Empty,
+            rects: vec![],
+            canvas: 0,
+            command_line: 0,
+            modals: vec![],
+            focus: None,
+            captured: None,
+            hovered: None,
+            keymap: Keymap::default(),
+            gestures: GestureRecognizer::new(GestureConfig::default()),
+        };
+        let swatches = gui.add(Swatches);
+        let top_ruler = gui.add(Ruler { vertical: false });
+        let side_ruler = gui.add(Ruler { vertical: true });
+        gui.canvas = gui.add(TheField { painted: false });
+        let layers = gui.add(LayersPanel { renaming: None, focused: false });
+        let picker = gui.add(ColorPicker);
+        let preview = gui.add(Preview { scale: 1, focused: false, encoded: RefCell::new(None) });
+        let palette = gui.add(Palette);
+        let status = gui.add(StatusBar);
+        gui.command_line = gui.add(CommandLine { text: TextInput::default(), history: vec![], browsing: None, completions: None });
+        gui.set_layout(Layout::Dock(
+            vec![
+                (Edge::Bottom, Size::Auto, Layout::Widget(gui.command_line)),
+                (Edge::Bottom, Size::Fixed(1), Layout::Widget(status)),
+                (Edge::Right, Size::Min(ColorPicker::WIDTH), Layout::Column(vec![
+                    (Size::Auto, Layout::Widget(layers)),
+                    (Size::Fixed(1), Layout::Empty),
+                    (Size::Auto, Layout::Widget(picker)),
+                    (Size::Fixed(1), Layout::Empty),
+                    (Size::Auto, Layout::Widget(preview)),
+                    (Size::Fixed(1), Layout::Empty),
+                    (Size::Auto, Layout::Widget(palette)),
+                ])),
+                (Edge::Right, Size::Fixed(2), Layout::Empty),
+                (Edge::Top, Size::Fixed(1), Layout::Row(vec![
+                    (Size::Fixed(2), Layout::Widget(swatches)),
+                    (Size::Flex(1), Layout::Widget(top_ruler)),
+                ])),
+                (Edge::Left, Size::Fixed(2), Layout::Widget(side_ruler)),
+            ],
+            Box::new(Layout::Widget(gui.canvas)),
+        ));
+        gui
+    }
+
+    /// Adds the widget, giving out its id for the layout
+    pub fn add<W: Widget + 'static>(&mut self, widget: W) -> usize {
+        self.widgets.push(Box::new(widget));
+        self.widgets.len() - 1
+    }
+
+    /// Opens the dialog over everything else, and over the other dialogs
+    pub fn open<M: Modal + 'static>(&mut self, modal: M) {
+        // the release goes to the dialog now
+        self.captured = None;
+        self.modals.push(Box::new(modal));
+    }
+
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+        self.relayout();
+    }
+
+    /// The terminal got resized, everything is laid out again for the new size
+    pub fn resize(&mut self, terminal: Dimension) {
+        self.state.terminal = terminal;
+        self.relayout();
+    }
+
+    /// Works out where the widgets go, which changes along with the state,
+    /// like when the color picker opens or a layer is added
+    fn relayout(&mut self) {
+        let (widgets, state) = (&self.widgets, &self.state);
+        let hint = |id: usize| widgets[id].size_hint(state);
+        self.rects = self.layout.compute((Position::default(), self.state.terminal), &hint);
+        let canvas = self.rects.iter().find(|(id, _)| *id == self.canvas);
+        self.state.canvas_area = canvas.map_or((Position::default(), Dimension { width: 0, height: 0 }), |(_, rect)| *rect);
+    }
+
+    pub fn draw(&mut self, out: &mut impl Write) -> io::Result<()> {
+        self.relayout();
+        self.buffer.clear(self.state.terminal);
+        let mut screen = Surface::new(&mut self.buffer, Position::default(), self.state.terminal);
+        for &(id, (pos, size)) in &self.rects {
+            self.widgets[id].draw(&self.state, &mut screen.sub(pos, size))?;
+        }
+        if !self.modals.is_empty() {
+            screen.dim();
+        }
+        for modal in &self.modals {
+            let rect = modal_rect(self.state.terminal, modal.size_hint(&self.state));
+            let mut frame = screen.sub(rect.0, rect.1);
+            frame.clear();
+            frame.border(Border::Rounded, CellColor::default());
+            frame.text(Position { x: 2, y: 0 }, CellColor::default().attrs(Attributes::default().bold()), format!(" {} ", modal.title()));
+            let (pos, size) = modal_inside((Position::default(), rect.1));
+            modal.draw(&self.state, &mut frame.sub(pos, size))?;
+        }
+        self.buffer.draw(self.state.terminal, out)
+    }
+
+    pub fn redraw(&mut self, out: &mut impl Write) -> io::Result<()> {
+        self.buffer.redraw(self.state.terminal, out)
+    }
+
+    pub fn clear_screen(&mut self, out: &mut impl Write) -> io::Result<()> {
+        write!(out, "\x1b[2J\x1b[1;1H")?;
+        out.flush()
+    }
+
+    /// Handles a single input event received at the given time.
+    /// Nothing is drawn here, so the caller should `draw` afterwards
+    pub fn handle(&mut self, event: Event, at: Instant) -> io::Result<Flow> {
+        // the deadline might have passed without us noticing, e.g. when replaying
+        self.tick(at)?;
+        if let Event::Resize(terminal) = event {
+            self.resize(terminal);
+            return Ok(Flow::Redraw);
+        }
+        // what the last event changed might have moved things around, even if it wasn't drawn
+        self.relayout();
+
+        // the focused widget might be gone, like the preview when it's turned off
+        if self.focus.is_some_and(|id| !self.is_focusable(id)) {
+            self.set_focus(None)?;
+        }
+
+        let event = self.state.locate_event(event);
+        let gesture = self.gestures.feed(&event, at);
+        if let Some(flow) = self.handle_modal(&event, gesture)? {
+            return Ok(flow);
+        }
+        match event {
+            _ if is_key(&event) => {
+                self.state.message = None;
+                let handled = self.dispatch_key(&event)?;
+                if let Some(action) = self.state.requested.take() {
+                    return self.perform_requested(action);
+                }
+                if handled == Handled::Consumed {
+                    return Ok(Flow::Continue);
+                }
+                match event {
+                    Event::Tab => self.cycle_focus(true)?,
+                    Event::BackTab => self.cycle_focus(false)?,
+                    Event::Escape => self.set_focus(None)?,
+                    Event::Press(ch, modifiers) => {
+                        if let Some(action) = self.keymap.get(Binding::Key(ch, modifiers)) {
+                            return self.perform(action);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::Mouse(action, button, mouse, modifiers) => {
+                let target = match action {
+                    MouseAction::Press => {
+                        let target = self.widget_at(mouse);
+                        self.captured = target;
+                        self.set_focus(target.filter(|&id| self.is_focusable(id)))?;
+                        target
+                    }
+                    _ => self.captured.or_else(|| self.widget_at(mouse)),
+                };
+                self.hover(mouse)?;
+                let handled = self.dispatch_mouse(target, mouse, |widget, state, pos| widget.on_mouse_input(state, action, button, pos, modifiers))?;
+                if action == MouseAction::Release {
+                    self.captured = None;
+                    self.hover(mouse)?;
+                }
+                if let (MouseAction::Press, Handled::Bubble) = (action, handled) {
+                    if let Some(bound) = self.keymap.get(Binding::Button(button, modifiers)) {
+                        return self.perform(bound);
+                    }
+                }
+            }
+            Event::MouseMotion(mouse, _) => self.hover(mouse)?,
+            Event::MouseWheel(direction, mouse, modifiers) => {
+                self.hover(mouse)?;
+                let target = self.widget_at(mouse);
+                self.dispatch_mouse(target, mouse, |widget, state, pos| widget.on_mouse_wheel(state, direction, pos, modifiers))?;
+            }
+            Event::FocusLost => self.state.focused = false,
+            Event::FocusGained => {
+                // the screen might have been messed with while
+                // we were away (e.g. switching tmux panes), so repaint everything
+                self.state.focused = true;
+                return Ok(Flow::Redraw);
+            }
+            _ => {}
+        }
+        if let Some(gesture) = gesture {
+            self.dispatch_gesture(gesture)?;
+        }
+        Ok(Flow::Continue)
+    }
+
+    /// Asks whether to recover the autosave left behind by an earlier run that didn't end well,
+    /// it's thrown away with the next autosave after the answer is no
+    pub fn offer_recovery(&mut self, autosave: PathBuf) {
+        let name = match &self.state.file {
+            Some(file) => file.display().to_string(),
+            None => "the new picture".to_string(),
+        };
+        let question = format!("There are unsaved changes to {} from before, recover them?\nThey're thrown away otherwise", name);
+        self.open(Confirm::new("Recover", question, Action::Recover(autosave)).otherwise(Action::DeclineRecovery));
+        self.state.recovery_offered = true;
+    }
+
+    /// Autosaves the picture, unless the autosave there is still offered to be recovered
+    pub fn autosave(&self, autosave: &mut Autosave) -> io::Result<()> {
+        if self.state.recovery_offered {
+            return Ok(());
+        }
+        autosave.update(&self.state.picture, self.state.modified, self.state.file.as_deref())
+    }
+
+    pub fn is_quit(&self, event: &Event) -> bool {
+        let binding = match *event {
+            Event::Press(ch, modifiers) => Binding::Key(ch, modifiers),
+            Event::Mouse(MouseAction::Press, button, _, modifiers) => Binding::Button(button, modifiers),
+            _ => return false,
+        };
+        self.keymap.get(binding) == Some(Action::Quit)
+    }
+
+    /// When `tick` should be called if no other events come in
+    pub fn deadline(&self) -> Option<Instant> {
+        self.gestures.deadline()
+    }
+
+    pub fn tick(&mut self, at: Instant) -> io::Result<()> {
+        match self.gestures.tick(at) {
+            Some(gesture) if self.modals.is_empty() => self.dispatch_gesture(gesture),
+            _ => Ok(()),
+        }
+    }
+
+    /// Performs what a widget asked for, telling about the errors instead of giving up
+    fn perform_requested(&mut self, action: Action) -> io::Result<Flow> {
+        self.perform(action).or_else(|e| {
+            self.state.message = Some(Message::Error(e.to_string()));
+            Ok(Flow::Continue)
+        })
+    }
+
+    /// Performs the action, asking first when it's one of those that throw something away
+    fn perform(&mut self, action: Action) -> io::Result<Flow> {
+        let question = match &action {
+            Action::Quit if self.state.modified => "There are unsaved changes, quit anyway?".to_string(),
+            Action::SaveAs(path) if path.exists() && self.state.file.as_ref() != Some(path) => {
+                format!("{} is already there, overwrite it?", path.display())
+            }
+            Action::Export(path, ..) if path.exists() => format!("{} is already there, overwrite it?", path.display()),
+            Action::Resize(size, _) if size.width < self.state.picture.size.width || size.height < self.state.picture.size.height => {
+                format!("Resizing to {}x{} cuts off the pixels outside, go on?", size.width, size.height)
+            }
+            Action::Crop(_, size) => format!("Cropping to {}x{} cuts off the pixels outside, go on?", size.width, size.height),
+            _ => return self.run(action),
+        };
+        self.open(Confirm::new("Confirm", question, Action::Confirmed(Box::new(action))));
+        Ok(Flow::Continue)
+    }
+
+    fn run(&mut self, action: Action) -> io::Result<Flow> {
+        match action {
+            Action::Confirmed(action) => return self.run(*action),
+            Action::Quit => return Ok(Flow::Quit),
+            Action::ClearScreen => return Ok(Flow::ClearScreen),
+            Action::Redraw => return Ok(Flow::Redraw),
+            Action::SwapColors => std::mem::swap(&mut self.state.primary, &mut self.state.secondary),
+            Action::NextCanvasMode => self.state.canvas = self.state.canvas.next(),
+            Action::ToggleDither => self.state.dither = !self.state.dither,
+            Action::TogglePreview => self.state.preview = !self.state.preview,
+            Action::NextTool => self.state.tool = self.state.tool.next(),
+            Action::BiggerBrush => self.state.brush = (self.state.brush + 1).min(Self::MAX_BRUSH),
+            Action::SmallerBrush => self.state.brush = (self.state.brush - 1).max(1),
+            Action::CommandLine => {
+                self.state.command_line = true;
+                self.state.message = None;
+                self.set_focus(Some(self.command_line))?;
+            }
+            Action::ExportDialog => {
+                let dialog = FileDialog::new("Export", std::env::current_dir()?, &["png"], false, |path| Action::Export(path, 1, Scaler::Nearest));
+                self.open(dialog);
+            }
+            Action::PaletteDialog => {
+                self.open(FileDialog::new("Load palette", std::env::current_dir()?, &["hex"], true, Action::LoadPalette));
+            }
+            Action::NewLayerDialog => {
+                let name = format!("Layer {}", self.state.picture.layers.len());
+                self.open(Prompt::new("New layer", name, |name| match name.trim() {
+                    "" => Err("the layer needs a name".to_string()),
+                    name => Ok(Action::NewLayer(name.to_string())),
+                }));
+            }
+            Action::Resize(size, anchor) => {
+                Picture::check_size(size).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+                self.state.change();
+                self.state.picture.resize_canvas(size, anchor);
+                self.state.fit_picture();
+            }
+            Action::Crop(origin, size) => {
+                let mut picture = self.state.picture.clone();
+                picture.crop(origin, size).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+                self.state.change();
+                self.state.picture = picture;
+                self.state.fit_picture();
+            }
+            Action::Scale(factor, scaler) => {
+                let mut picture = self.state.picture.clone();
+                picture.scale(factor, scaler).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+                self.state.change();
+                self.state.picture = picture;
+                self.state.fit_picture();
+            }
+            Action::Transform(transform, target) => {
+                self.state.change();
+                self.state.picture.transform(transform, target, self.state.current_layer);
+                self.state.fit_picture();
+            }
+            Action::RotateDialog(target) => {
+                let title = if target == Target::Picture { "Rotate the picture by" } else { "Rotate the layer by" };
+                self.open(Prompt::new(title, "45", move |degrees| command::parse(&format!("rotate {}{}", degrees, target_suffix(target)))));
+            }
+            Action::ShearDialog(target) => {
+                let title = if target == Target::Picture { "Shear the picture by (x y)" } else { "Shear the layer by (x y)" };
+                self.open(Prompt::new(title, "0.5 0", move |factors| command::parse(&format!("shear {}{}", factors, target_suffix(target)))));
+            }
+            Action::Trim => match self.state.picture.bounds() {
+                None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "there's nothing but transparent pixels")),
+                Some((_, size)) if size == self.state.picture.size => {
+                    self.state.message = Some(Message::Info("there are no transparent borders to trim".to_string()));
+                }
+                Some((origin, size)) => return self.run(Action::Crop(origin, size)),
+            },
+            Action::Undo | Action::Redo => {
+                let picture = &mut self.state.picture;
+                let done = match action {
+                    Action::Undo => self.state.history.undo(picture),
+                    _ => self.state.history.redo(picture),
+                };
+                if !done {
+                    let what = if action == Action::Undo { "undo" } else { "redo" };
+                    self.state.message = Some(Message::Info(format!("nothing to {}", what)));
+                    return Ok(Flow::Continue);
+                }
+                self.state.modified = true;
+                self.state.fit_picture();
+            }
+            Action::NewLayer(name) => {
+                self.state.change();
+                let pixels = vec![Pixel::default(); self.state.picture.size.number()].into_boxed_slice();
+                self.state.picture.layers.push(Layer { name, pixels });
+                self.state.current_layer = self.state.picture.layers.len() - 1;
+            }
+            Action::Save => match self.state.file.clone() {
+                Some(file) => return self.run(Action::SaveAs(file)),
+                None => {
+                    let dialog = FileDialog::new("Save", std::env::current_dir()?, &[document::EXTENSION], false, Action::SaveAs);
+                    self.open(dialog);
+                }
+            },
+            Action::SaveAs(path) => {
+                document::save(&self.state.picture, &path).map_err(|e| in_file(&path, e))?;
+                self.state.message = Some(Message::Info(format!("saved {}", path.display())));
+                self.state.file = Some(path);
+                self.state.modified = false;
+            }
+            Action::Recover(path) => {
+                self.state.recovery_offered = false;
+                let picture = document::load(&path).map_err(|e| in_file(&path, e))?;
+                // it's still not saved anywhere but in the recovery directory
+                self.state.change();
+                self.state.picture = picture;
+                self.state.current_layer = self.state.picture.layers.len() - 1;
+                self.state.fit_picture();
+                self.state.message = Some(Message::Info("recovered the unsaved changes".to_string()));
+            }
+            Action::DeclineRecovery => self.state.recovery_offered = false,
+            Action::Export(path, scale, scaler) => {
+                let size = self.state.picture.size;
+                let pixels: Vec<Pixel> = size.into_iter().map(|pos| self.state.picture.composite(pos)).collect();
+                let (pixels, size) = scaler.scale(&pixels, size, scale as f64).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+                fs::write(&path, png::encode(&pixels, size)).map_err(|e| in_file(&path, e))?;
+                let message = format!("exported {}, {}x{}", path.display(), size.width, size.height);
+                self.state.message = Some(Message::Info(message));
+            }
+            Action::LoadPalette(path) => {
+                let text = fs::read_to_string(&path).map_err(|e| in_file(&path, e))?;
+                let palette = command::parse_palette(&text).map_err(|e| in_file(&path, io::Error::new(io::ErrorKind::InvalidData, e)))?;
+                self.state.message = Some(Message::Info(format!("loaded {} colors", palette.len())));
+                self.state.palette = palette;
+            }
+            Action::Fill(pixel) => {
+                self.state.change();
+                for target in self.state.picture.layers[self.state.current_layer].pixels.iter_mut() {
+                    *target = pixel;
+                }
+            }
+        }
+        Ok(Flow::Continue)
+    }
+
+    /// Gives the input to the dialog on top, if there is one, as nothing under it should get any.
+    /// Returns what to do next when the dialog took the event
+    fn handle_modal(&mut self, event: &Event, gesture: Option<Gesture>) -> io::Result<Option<Flow>> {
+        let modal = match self.modals.last_mut() {
+            Some(modal) => modal,
+            None => return Ok(None),
+        };
+        let (origin, size) = modal_inside(modal_rect(self.state.terminal, modal.size_hint(&self.state)));
+        let inside = |mouse: Position| {
+            let within = mouse.x >= origin.x && mouse.y >= origin.y && mouse.x < origin.x + size.width && mouse.y < origin.y + size.height;
+            Some(mouse - origin).filter(|_| within)
+        };
+        match *event {
+            Event::Escape => modal.cancel(&mut self.state),
+            Event::Mouse(action, button, mouse, modifiers) => {
+                if let Some(pos) = inside(mouse) {
+                    modal.on_mouse_input(&mut self.state, action, button, pos, modifiers)?;
+                }
+            }
+            Event::MouseWheel(direction, mouse, modifiers) => {
+                if let Some(pos) = inside(mouse) {
+                    modal.on_mouse_wheel(&mut self.state, direction, pos, modifiers)?;
+                }
+            }
+            Event::MouseMotion(..) => {}
+            ref event if is_key(event) => {
+                modal.on_key(&mut self.state, event)?;
+            }
+            _ => return Ok(None),
+        }
+        if let Some(gesture) = gesture {
+            if let Some(pos) = inside(gesture.position()) {
+                modal.on_gesture(&mut self.state, gesture, pos)?;
+            }
+        }
+        if modal.done() || *event == Event::Escape {
+            self.modals.pop();
+        }
+        match self.state.requested.take() {
+            Some(action) => self.perform_requested(action).map(Some),
+            None => Ok(Some(Flow::Continue)),
+        }
+    }
+
+    /// The widget drawn at the cell the last time, the one drawn last if there are more
+    fn widget_at(&self, mouse: Position) -> Option<usize> {
+        self.rects.iter().rev()
+            .find(|(_, (pos, size))| mouse.x >= pos.x && mouse.y >= pos.y && mouse.x < pos.x + size.width && mouse.y < pos.y + size.height)
+            .map(|&(id, _)| id)
+    }
+
+    /// Gives the mouse event to the widget, with the position relative to where it was drawn
+    fn dispatch_mouse<F>(&mut self, target: Option<usize>, mouse: Position, mut handler: F) -> io::Result<Handled>
+        where F: FnMut(&mut dyn Widget, &mut GuiState, Position) -> io::Result<Handled> {
+        let id = match target {
+            Some(id) => id,
+            None => return Ok(Handled::Bubble),
+        };
+        let origin = self.rects.iter().find(|(other, _)| *other == id).map_or(Position::default(), |(_, (pos, _))| *pos);
+        let pos = Position { x: mouse.x.saturating_sub(origin.x), y: mouse.y.saturating_sub(origin.y) };
+        handler(self.widgets[id].as_mut(), &mut self.state, pos)
+    }
+
+    fn dispatch_gesture(&mut self, gesture: Gesture) -> io::Result<()> {
+        let target = self.widget_at(gesture.position());
+        self.dispatch_mouse(target, gesture.position(), |widget, state, pos| widget.on_gesture(state, gesture, pos))?;
+        Ok(())
+    }
+
+    /// Offers the key to the focused widget, then to the others until one of them takes it
+    fn dispatch_key(&mut self, event: &Event) -> io::Result<Handled> {
+        let others = (0..self.widgets.len()).filter(|&id| Some(id) != self.focus);
+        let order: Vec<usize> = self.focus.into_iter().chain(others).collect();
+        for id in order {
+            if self.widgets[id].on_key(&mut self.state, event)? == Handled::Consumed {
+                return Ok(Handled::Consumed);
+            }
+        }
+        Ok(Handled::Bubble)
+    }
+
+    /// Tells the widgets the mouse came over them or left them. The captured one
+    /// keeps the mouse until it's released, wherever it goes
+    fn hover(&mut self, mouse: Position) -> io::Result<()> {
+        let hovered = self.captured.or_else(|| self.widget_at(mouse));
+        if hovered != self.hovered {
+            if let Some(id) = self.hovered {
+                self.widgets[id].on_hover(&mut self.state, false)?;
+            }
+            if let Some(id) = hovered {
+                self.widgets[id].on_hover(&mut self.state, true)?;
+            }
+            self.hovered = hovered;
+        }
+        Ok(())
+    }
+
+    /// Whether the widget wants the focus and is on the screen to show it has it
+    fn is_focusable(&self, id: usize) -> bool {
+        self.widgets[id].focusable(&self.state)
+            && self.rects.iter().any(|(other, (_, size))| *other == id && size.width > 0 && size.height > 0)
+    }
+
+    fn set_focus(&mut self, focus: Option<usize>) -> io::Result<()> {
+        if focus != self.focus {
+            if let Some(id) = self.focus {
+                self.widgets[id].on_focus(&mut self.state, false)?;
+            }
+            if let Some(id) = focus {
+                self.widgets[id].on_focus(&mut self.state, true)?;
+            }
+            self.focus = focus;
+        }
+        Ok(())
+    }
+
+    /// Moves the focus on to the next focusable widget, or back to the previous one,
+    /// in the order they were added and wrapping around
+    fn cycle_focus(&mut self, forward: bool) -> io::Result<()> {
+        let focusable: Vec<usize> = (0..self.widgets.len()).filter(|&id| self.is_focusable(id)).collect();
+        let current = self.focus.and_then(|focus| focusable.iter().position(|&id| id == focus));
+        let count = focusable.len();
+        let next = match (current, forward) {
+            _ if count == 0 => None,
+            (None, true) => Some(0),
+            (None, false) => Some(count - 1),
+            (Some(i), true) => Some((i + 1) % count),
+            (Some(i), false) => Some((i + count - 1) % count),
+        };
+        self.set_focus(next.map(|i| focusable[i]))
+    }
+}
+
+/// What's added to the commands to make them apply to the whole picture
+fn target_suffix(target: Target) -> &'static str {
+    match target {
+        Target::Layer => "",
+        Target::Picture => " picture",
+    }
+}
+
+/// Adds the file to the error, which otherwise doesn't say which one it was about
+fn in_file(path: &Path, e: io::Error) -> io::Error {
+    io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+}
+
+/// Everything that can be done with a key, a button or a command, see the `command` module for their names
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    Quit,
+    ClearScreen,
+    Redraw,
+    SwapColors,
+    NextCanvasMode,
+    ToggleDither,
+    TogglePreview,
+    NextTool,
+    BiggerBrush,
+    SmallerBrush,
+    /// Opens the `:` command line
+    CommandLine,
+    /// Resizes the picture, keeping the part at the anchor in place
+    Resize(Dimension, Anchor),
+    /// Cuts the picture down to the rectangle, given by its corner and size as there's
+    /// no selection to crop to yet
+    Crop(Position, Dimension),
+    /// Crops the transparent borders off the picture
+    Trim,
+    /// Flips, turns or slants the current layer or the whole picture
+    Transform(Transform, Target),
+    /// Asks for the angle to rotate by
+    RotateDialog(Target),
+    /// Asks for how much to shear by
+    ShearDialog(Target),
+    Undo,
+    Redo,
+    /// Adds a transparent layer on top and makes it the current one
+    NewLayer(String),
+    /// Saves the picture into the file it came from, asking where to if there's none yet
+    Save,
+    /// Saves the picture into the file, which it's saved to from then on
+    SaveAs(PathBuf),
+    /// Replaces the picture with the autosave in the file, see `Gui::offer_recovery`
+    Recover(PathBuf),
+    /// Lets the autosave offered to be recovered be thrown away
+    DeclineRecovery,
+    /// Scales the picture by the factor
+    Scale(f64, Scaler),
+    /// Writes the picture into a PNG file, scaled up by the given factor
+    Export(PathBuf, u16, Scaler),
+    /// Loads the palette from a `.hex` file
+    LoadPalette(PathBuf),
+    /// Fills the current layer with the color
+    Fill(Pixel),
+    /// Opens a file dialog to pick where to export to
+    ExportDialog,
+    /// Opens a file dialog to pick the palette to load
+    PaletteDialog,
+    /// Asks for the name of a new layer
+    NewLayerDialog,
+    /// The action, without asking first if it throws something away, as that was already asked
+    Confirmed(Box<Action>),
+}
+
+/// Something the user can press to trigger an action
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(char, Modifiers),
+    Button(MouseButton, Modifiers),
+}
+
+pub struct Keymap {
+    bindings: HashMap<Binding, Action>,
+}
+
+impl Keymap {
+    pub fn bind(&mut self, binding: Binding, action: Action) {
+        self.bindings.insert(binding, action);
+    }
+
+    pub fn get(&self, binding: Binding) -> Option<Action> {
+        self.bindings.get(&binding).cloned()
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut keymap = Keymap { bindings: HashMap::new() };
+        keymap.bind(Binding::Key('c', Modifiers::Ctrl), Action::Quit);
+        keymap.bind(Binding::Key('q', Modifiers::None), Action::Quit);
+        keymap.bind(Binding::Key('s', Modifiers::Ctrl), Action::Save);
+        keymap.bind(Binding::Key('z', Modifiers::Ctrl), Action::Undo);
+        keymap.bind(Binding::Key('y', Modifiers::Ctrl), Action::Redo);
+        keymap.bind(Binding::Key('l', Modifiers::Ctrl), Action::ClearScreen);
+        keymap.bind(Binding::Key('r', Modifiers::Ctrl), Action::Redraw);
+        keymap.bind(Binding::Key('x', Modifiers::None), Action::SwapColors);
+        keymap.bind(Binding::Button(MouseButton::Back, Modifiers::None), Action::SwapColors);
+        keymap.bind(Binding::Key('z', Modifiers::None), Action::NextCanvasMode);
+        keymap.bind(Binding::Button(MouseButton::Forward, Modifiers::None), Action::NextCanvasMode);
+        keymap.bind(Binding::Key('d', Modifiers::None), Action::ToggleDither);
+        keymap.bind(Binding::Key('p', Modifiers::None), Action::TogglePreview);
+        keymap.bind(Binding::Key('t', Modifiers::None), Action::NextTool);
+        keymap.bind(Binding::Key(']', Modifiers::None), Action::BiggerBrush);
+        keymap.bind(Binding::Key('[', Modifiers::None), Action::SmallerBrush);
+        keymap.bind(Binding::Key(':', Modifiers::None), Action::CommandLine);
+        keymap.bind(Binding::Key('e', Modifiers::None), Action::ExportDialog);
+        keymap.bind(Binding::Key('n', Modifiers::None), Action::NewLayerDialog);
+        // the layer, or the whole picture with shift
+        for (modifiers, target) in [(Modifiers::None, Target::Layer), (Modifiers::Shift, Target::Picture)] {
+            keymap.bind(Binding::Key('h', modifiers), Action::Transform(Transform::FlipHorizontal, target));
+            keymap.bind(Binding::Key('v', modifiers), Action::Transform(Transform::FlipVertical, target));
+            keymap.bind(Binding::Key('r', modifiers), Action::Transform(Transform::Rotate(90.0), target));
+            keymap.bind(Binding::Key('a', modifiers), Action::RotateDialog(target));
+            keymap.bind(Binding::Key('s', modifiers), Action::ShearDialog(target));
+        }
+        keymap
+    }
+}
@@ -0,0 +1,53 @@
+use crate::state::Picture;
+
+/// The pictures as they were before the changes, to go back and forth between them.
+/// Keeping whole copies of them is simpler than keeping what every kind of change did,
+/// but a big picture is up to 64 MiB a layer, so there's a limit on their bytes too
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Picture>,
+    redo: Vec<Picture>,
+}
+
+impl History {
+    /// How many changes can be undone, the oldest ones are forgotten after that
+    pub const LIMIT: usize = 100;
+    /// How many bytes of pixels the changes that can be undone take at most.
+    /// The last one is kept whatever its size, so that there's always something to undo
+    pub const MAX_BYTES: usize = 256 * 1024 * 1024;
+
+    /// Remembers the picture as it is before it's changed. The changes undone before are gone then
+    pub fn record(&mut self, before: &Picture) {
+        self.redo.clear();
+        let mut total: usize = self.undo.iter().map(bytes).sum::<usize>() + bytes(before);
+        while !self.undo.is_empty() && (self.undo.len() == Self::LIMIT || total > Self::MAX_BYTES) {
+            total -= bytes(&self.undo.remove(0));
+        }
+        self.undo.push(before.clone());
+    }
+
+    /// Puts the picture back to how it was before the last change, false if there's none
+    pub fn undo(&mut self, picture: &mut Picture) -> bool {
+        Self::step(&mut self.undo, &mut self.redo, picture)
+    }
+
+    /// Makes the last undone change again, false if there's none
+    pub fn redo(&mut self, picture: &mut Picture) -> bool {
+        Self::step(&mut self.redo, &mut self.undo, picture)
+    }
+
+    fn step(from: &mut Vec<Picture>, to: &mut Vec<Picture>, picture: &mut Picture) -> bool {
+        match from.pop() {
+            Some(other) => {
+                to.push(std::mem::replace(picture, other));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// The bytes the pixels of the picture take
+fn bytes(picture: &Picture) -> usize {
+    picture.layers.len() * picture.size.number() * 4
+}
@@ -1,25 +1,32 @@
 use std::{io, thread};
+use std::time::Instant;
 use std::io::{ErrorKind, Read};
 
 use crossbeam_channel;
 use crossbeam_channel::Receiver;
 
-use crate::state::Position;
+use crate::state::{Dimension, Position};
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum MouseButton {
     Left,
     Middle,
     Right,
+    Back,
+    Forward,
+    /// Buttons 10 and 11, whatever the mouse maps them to
+    Extra(u8),
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum MouseWheelDirection {
     Up,
     Down,
+    Left,
+    Right,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Modifiers {
     // made as an enum for simpler matching
     None,
@@ -32,14 +39,14 @@ pub enum Modifiers {
     CtrlShiftAlt,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum MouseAction {
     Press,
     Release,
     Drag,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Arrow {
     Up,
     Down,
@@ -47,11 +54,58 @@ pub enum Arrow {
     Left,
 }
 
-#[derive(Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ModeState {
+    NotRecognized,
+    Set,
+    Reset,
+    PermanentlySet,
+    PermanentlyReset,
+}
+
+impl ModeState {
+    pub fn is_set(self) -> bool {
+        matches!(self, ModeState::Set | ModeState::PermanentlySet)
+    }
+
+    /// Whether the mode can be set at all
+    pub fn is_supported(self) -> bool {
+        matches!(self, ModeState::Set | ModeState::Reset | ModeState::PermanentlySet)
+    }
+}
+
+/// A point inside of a single cell, in 1/256ths of its width and height.
+/// It is only known with pixel-precise (SGR-pixels, 1016) mouse reporting
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SubCell {
+    pub x: u8,
+    pub y: u8,
+}
+
+impl SubCell {
+    /// Splits a position reported in SGR-pixels mode into the cell
+    /// under it and the point inside of that cell, given the cell size in pixels
+    pub fn from_pixels(pos: Position, cell: Dimension) -> (Position, SubCell) {
+        let Dimension { width, height } = cell;
+        let sub = SubCell {
+            x: ((pos.x % width) as u32 * 256 / width as u32) as u8,
+            y: ((pos.y % height) as u32 * 256 / height as u32) as u8,
+        };
+        (Position { x: pos.x / width, y: pos.y / height }, sub)
+    }
+
+    pub fn is_lower_half(self) -> bool {
+        self.y >= 128
+    }
+}
+
+#[derive(Debug, PartialEq)]
 pub enum Event {
     Enter,
     Space,
     Tab,
+    /// Shift+Tab
+    BackTab,
     Escape,
     Backspace,
 
@@ -70,59 +124,78 @@ pub enum Event {
     MouseMotion(Position, Modifiers),
     MouseWheel(MouseWheelDirection, Position, Modifiers),
 
+    FocusGained,
+    FocusLost,
+
+    /// Answer to a DECRQM query of a private mode, e.g. `\x1b[?1016$p`
+    ModeReport(u16, ModeState),
+    /// Answer to the primary device attributes query (DA1, `\x1b[c`),
+    /// the first one is the terminal class and the rest are the features it has
+    DeviceAttributes(Vec<u16>),
+    /// Answer to a kitty graphics protocol command with the given image id,
+    /// whether it was OK or an error
+    KittyGraphics(u32, bool),
+
+    /// The terminal got this size, which isn't read from it but comes
+    /// with the replayed recordings, where it was recorded along with the input
+    Resize(Dimension),
+
     UnknownByteSequence(Vec<u8>),
 }
 
-fn parse_decimal(bytes: &[u8]) -> u16 {
-    if bytes.len() > 3 || bytes.len() == 0 {
-        return 1000;
+fn parse_decimal(bytes: &[u8]) -> Option<u16> {
+    if bytes.is_empty() {
+        return None;
     }
-    let mut res = 0;
-    for &byte in bytes { // this should unroll I guess
+    let mut res: u16 = 0;
+    for &byte in bytes {
         if byte > 47 && byte < 58 {
-            res = res * 10 + (byte - 48) as u16;
+            // pixel coordinates easily go over 999, so just check for overflow instead
+            res = res.checked_mul(10)?.checked_add((byte - 48) as u16)?;
         } else {
-            return 1000;
+            return None;
         }
     }
-    res
+    Some(res)
 }
 
 // this is so stupid, ikr
 // idk for what reason I fear allocations in low-level parsing,
 // or why I do this lowest-level stupid parsing at all
 // this a dumb rust newcomer disease I guess ¯\_(ツ)_/¯
-fn read_params(bytes: &[u8], until: u8, until2: u8) -> ([u16; 3], usize) {
-    let mut res = [0; 3];
-    let mut buf = [0; 3];
-    let mut buf_idx = 0;
+fn read_params<const N: usize>(bytes: &[u8], until: &[u8]) -> (Option<[u16; N]>, usize) {
+    let mut res = [0; N];
     let mut res_idx = 0;
-    let mut read = 0;
-    for &byte in bytes {
-        read += 1;
-        if byte == until || byte == until2 {
-            if res_idx > 3 {
-                return ([1000; 3], read);
-            }
-            res[res_idx] = parse_decimal(&buf[..buf_idx]);
-            return (res, read);
+    let mut start = 0;
+    for (idx, &byte) in bytes.iter().enumerate() {
+        let last = until.contains(&byte);
+        if byte != 59 && !last {
+            continue;
         }
-        if byte == 59 {
-            if res_idx > 3 {
-                return ([1000; 3], read);
-            }
-            res[res_idx] = parse_decimal(&buf[..buf_idx]);
-            buf_idx = 0;
-            res_idx += 1;
-        } else {
-            if buf_idx > 3 {
-                return ([1000; 3], read);
-            }
-            buf[buf_idx] = byte;
-            buf_idx += 1;
+        let param = match parse_decimal(&bytes[start..idx]) {
+            Some(param) if res_idx < N => param,
+            _ => return (None, idx + 1),
+        };
+        res[res_idx] = param;
+        res_idx += 1;
+        start = idx + 1;
+        if last {
+            return (if res_idx == N { Some(res) } else { None }, idx + 1);
         }
     }
-    ([1000; 3], read)
+    (None, bytes.len())
+}
+
+/// Reads a single value of the legacy mouse encodings, which is either
+/// a raw byte offset by 32 (X10), or a UTF-8 encoded character offset by 32 (1005).
+/// Those two are ambiguous for values over 95, but the UTF-8 interpretation
+/// only kicks in for byte pairs that are not likely to come from X10.
+fn read_legacy_value(bytes: &[u8]) -> Option<(u16, usize)> {
+    match bytes {
+        [a @ 0xC2..=0xDF, b @ 0x80..=0xBF, ..] => Some(((((*a as u16) & 0x1F) << 6 | ((*b as u16) & 0x3F)) - 32, 2)),
+        [a @ 32..=255, ..] => Some((*a as u16 - 32, 1)),
+        _ => None,
+    }
 }
 
 fn parse_arrow(byte: u8) -> Option<Arrow> {
@@ -148,6 +221,59 @@ fn parse_mods(byte: u8) -> Option<Modifiers> {
     }
 }
 
+/// Decodes the button byte which is the same for all of the mouse encodings,
+/// see https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h2-Mouse-Tracking
+///
+/// Legacy encodings (X10, 1005 and 1015) do not tell which button was released,
+/// so the last pressed one is remembered in `last_press`
+fn decode_mouse(b: u16, pos: Position, release: bool, last_press: &mut Option<MouseButton>) -> Event {
+    let mods = match (b & 0b11100) >> 2 {
+        0b000 => Modifiers::None,
+        0b001 => Modifiers::Shift,
+        0b010 => Modifiers::Alt,
+        0b011 => Modifiers::AltShift,
+        0b100 => Modifiers::Ctrl,
+        0b101 => Modifiers::CtrlShift,
+        0b110 => Modifiers::CtrlAlt,
+        0b111 => Modifiers::CtrlShiftAlt,
+        _ => unreachable!(),
+    };
+    if b & 0b1000000 != 0 { // wheel bit
+        let dir = match b & 0b11 {
+            0b00 => MouseWheelDirection::Up,
+            0b01 => MouseWheelDirection::Down,
+            0b10 => MouseWheelDirection::Left,
+            0b11 => MouseWheelDirection::Right,
+            _ => unreachable!(),
+        };
+        return Event::MouseWheel(dir, pos, mods);
+    }
+    let action = if release {
+        MouseAction::Release
+    } else if b & 0b100000 != 0 { // drag bit
+        MouseAction::Drag
+    } else {
+        MouseAction::Press
+    };
+    let button = match (b & 0b10000000 != 0, b & 0b11) { // extra buttons bit
+        (false, 0b00) => MouseButton::Left,
+        (false, 0b01) => MouseButton::Middle,
+        (false, 0b10) => MouseButton::Right,
+        (false, 0b11) => return match action {
+            MouseAction::Release => Event::Mouse(action, last_press.take().unwrap_or(MouseButton::Left), pos, mods),
+            _ => Event::MouseMotion(pos, mods),
+        },
+        (true, 0b00) => MouseButton::Back,
+        (true, 0b01) => MouseButton::Forward,
+        (true, n) => MouseButton::Extra(n as u8 + 8),
+        _ => unreachable!(),
+    };
+    if let MouseAction::Press = action {
+        *last_press = Some(button);
+    }
+    Event::Mouse(action, button, pos, mods)
+}
+
 macro_rules! fail {
     ($bytes:ident) => {
         return (Event::UnknownByteSequence($bytes.to_vec()), $bytes.len())
@@ -158,11 +284,12 @@ macro_rules! fail {
 /// It returns an event, and a number of consumed bytes.
 /// If it fails to parse the event, it returns `Event::UnknownByteSequence`
 /// with all the bytes received, and the length of the given slice
-fn parse_input_sequence(bytes: &[u8]) -> (Event, usize) {
+fn parse_input_sequence(bytes: &[u8], last_press: &mut Option<MouseButton>) -> (Event, usize) {
     if bytes.len() == 1 {
         return (match bytes[0] {
             27 => Event::Escape,
             9 => Event::Tab,
+            13 => Event::Enter,
             32 => Event::Space,
             127 => Event::Backspace,
             b if b < 32 => Event::Press(char::from(b + 96), Modifiers::Ctrl),
@@ -192,6 +319,20 @@ fn parse_input_sequence(bytes: &[u8]) -> (Event, usize) {
             Event::Press(ch, Modifiers::Alt)
         }, 2);
     }
+    // kitty graphics answer, ESC _ G i=<id> ; <message> ESC \\
+    if bytes[1] == 95 {
+        let end = match bytes.windows(2).position(|w| w == b"\x1b\\") {
+            Some(end) => end,
+            None => fail!(bytes),
+        };
+        let body = String::from_utf8_lossy(&bytes[2..end]);
+        let (keys, message) = body.strip_prefix('G').and_then(|body| body.split_once(';')).unwrap_or(("", ""));
+        let id = keys.split(',').find_map(|key| key.strip_prefix("i=")?.parse().ok());
+        return match id {
+            Some(id) => (Event::KittyGraphics(id, message == "OK"), end + 2),
+            None => fail!(bytes),
+        };
+    }
     // all other ones start with CSI (ESC+[)
     if bytes[1] != 91 {
         fail!(bytes);
@@ -200,8 +341,12 @@ fn parse_input_sequence(bytes: &[u8]) -> (Event, usize) {
     match code {
         [b @ 65..=68] => (Event::Arrow(parse_arrow(*b).unwrap(), Modifiers::None), 3),
 
+        [73] => (Event::FocusGained, 3),
+        [79] => (Event::FocusLost, 3),
+
         [70] => (Event::End, 3),
         [72] => (Event::Home, 3),
+        [90] => (Event::BackTab, 3),
 
         [50, 126] => (Event::Insert, 4),
         [51, 126] => (Event::Delete, 4),
@@ -215,47 +360,140 @@ fn parse_input_sequence(bytes: &[u8]) -> (Event, usize) {
             }
         }
 
+        // SGR (1006) and SGR-pixels (1016), which look exactly the same
         _ if code.len() > 1 && code[0] == 60 => {
-            let ([b, x, y], read) = read_params(&code[1..], 109, 77);
-            if b == 1000 || x == 1000 || y == 1000 || x == 0 || y == 0 {
+            let (params, read) = read_params(&code[1..], &[109, 77]);
+            match params {
+                Some([b, x, y]) if x != 0 && y != 0 => {
+                    let pos = Position { x: x - 1, y: y - 1 };
+                    (decode_mouse(b, pos, code[read] == 109, last_press), read + 3)
+                }
+                _ => fail!(bytes),
+            }
+        }
+
+        // X10 and UTF-8 (1005)
+        _ if code.len() > 3 && code[0] == 77 => {
+            let mut read = 1;
+            let mut values = [0; 3];
+            for value in values.iter_mut() {
+                match read_legacy_value(&code[read..]) {
+                    Some((v, r)) => {
+                        *value = v;
+                        read += r;
+                    }
+                    None => fail!(bytes),
+                }
+            }
+            let [b, x, y] = values;
+            if x == 0 || y == 0 {
                 fail!(bytes);
             }
             let pos = Position { x: x - 1, y: y - 1 };
-            let mods = match (b & 0b11100) >> 2 {
-                0b000 => Modifiers::None,
-                0b001 => Modifiers::Shift,
-                0b010 => Modifiers::Alt,
-                0b011 => Modifiers::AltShift,
-                0b100 => Modifiers::Ctrl,
-                0b101 => Modifiers::CtrlShift,
-                0b110 => Modifiers::CtrlAlt,
-                0b111 => Modifiers::CtrlShiftAlt,
-                _ => unreachable!(),
-            };
-            if b & 0b1000000 != 0 { // wheel bit
-                let dir = if b & 0b1 == 0 { MouseWheelDirection::Up } else { MouseWheelDirection::Down };
-                return (Event::MouseWheel(dir, pos, mods), read + 3);
+            (decode_mouse(b, pos, b & 0b10100011 == 0b11, last_press), read + 2)
+        }
+
+        // URXVT (1015)
+        _ if code.len() > 1 && code[0] > 47 && code[0] < 58 => {
+            let (params, read) = read_params(code, &[77]);
+            match params {
+                Some([b, x, y]) if b >= 32 && x != 0 && y != 0 => {
+                    let pos = Position { x: x - 1, y: y - 1 };
+                    let b = b - 32;
+                    (decode_mouse(b, pos, b & 0b10100011 == 0b11, last_press), read + 2)
+                }
+                _ => fail!(bytes),
             }
-            let action = if code[read] == 109 {
-                MouseAction::Release
-            } else if b & 0b100000 != 0 { // drag bit
-                MouseAction::Drag
-            } else {
-                MouseAction::Press
-            };
-            let button = match b & 0b11 {
-                0b00 => MouseButton::Left,
-                0b01 => MouseButton::Middle,
-                0b10 => MouseButton::Right,
-                0b11 => return (Event::MouseMotion(pos, mods), read + 3),
-                _ => unreachable!(),
+        }
+
+        // DA1 answer, CSI ? Ps ; ... c
+        _ if code.len() > 1 && code[0] == 63
+            && code[1..].iter().find(|&&b| b != 59 && !b.is_ascii_digit()) == Some(&99) => {
+            let end = code.iter().position(|&b| b == 99).unwrap();
+            let attributes = code[1..end].split(|&b| b == 59).filter_map(parse_decimal).collect();
+            (Event::DeviceAttributes(attributes), end + 3)
+        }
+
+        // DECRQM answer, CSI ? Pd ; Ps $ y
+        _ if code.len() > 1 && code[0] == 63 => {
+            let (params, read) = read_params(&code[1..], &[36]);
+            let state = match params {
+                Some([_, 0]) => ModeState::NotRecognized,
+                Some([_, 1]) => ModeState::Set,
+                Some([_, 2]) => ModeState::Reset,
+                Some([_, 3]) => ModeState::PermanentlySet,
+                Some([_, 4]) => ModeState::PermanentlyReset,
+                _ => fail!(bytes),
             };
-            return (Event::Mouse(action, button, pos, mods), read + 3);
+            match (params, code.get(read + 1)) {
+                (Some([mode, _]), Some(121)) => (Event::ModeReport(mode, state), read + 4),
+                _ => fail!(bytes),
+            }
         }
         _ => fail!(bytes)
     }
 }
 
+/// Whether the bytes are the beginning of a sequence that got cut off by the end of a read.
+/// A lone ESC, ESC [ or ESC _ are the Escape, Alt+[ and Alt+_ keys, so those are never waited on
+fn is_incomplete(bytes: &[u8]) -> bool {
+    match bytes {
+        [27, 95, 71, rest @ ..] => !rest.windows(2).any(|w| w == b"\x1b\\"),
+        [27, 91, 77, rest @ ..] => {
+            // three values, which in 1005 are characters that can take two bytes. A lead byte
+            // at the very end could just as well be a whole X10 one, it's waited on anyway
+            let mut rest = rest;
+            for _ in 0..3 {
+                match rest {
+                    [] | [0xC2..=0xDF] => return true,
+                    _ => match read_legacy_value(rest) {
+                        Some((_, read)) => rest = &rest[read..],
+                        None => return false,
+                    },
+                }
+            }
+            false
+        }
+        [27, 91, rest @ ..] => !rest.is_empty() && rest.iter().all(|b| b.is_ascii_digit() || b";<?$".contains(b)),
+        _ => false,
+    }
+}
+
+/// Keeps the little state that parsing needs between the chunks of input
+#[derive(Default)]
+pub struct EventParser {
+    last_press: Option<MouseButton>,
+    /// The start of a sequence the last chunk ended in the middle of
+    pending: Vec<u8>,
+}
+
+impl EventParser {
+    /// Garbage that looks like the start of a sequence is not waited on for longer than that
+    const MAX_PENDING: usize = 64;
+
+    /// Parses all of the events from a chunk of input that was read at once.
+    /// A sequence cut off at the end of it is kept and finished with the next chunk
+    pub fn parse<'a>(&'a mut self, chunk: &[u8]) -> impl Iterator<Item = Event> + 'a {
+        let mut bytes = std::mem::take(&mut self.pending);
+        bytes.extend_from_slice(chunk);
+        let mut offset = 0;
+        std::iter::from_fn(move || {
+            let rest = &bytes[offset..];
+            if rest.is_empty() {
+                return None;
+            }
+            if rest.len() < Self::MAX_PENDING && is_incomplete(rest) {
+                self.pending = rest.to_vec();
+                offset = bytes.len();
+                return None;
+            }
+            let (event, read) = parse_input_sequence(rest, &mut self.last_press);
+            offset += read;
+            Some(event)
+        })
+    }
+}
+
 /// Creates a new instance of the receiver of the console events
 /// that might be read from the given input.
 ///
@@ -268,34 +506,31 @@ fn parse_input_sequence(bytes: &[u8]) -> (Event, usize) {
 ///
 /// The algorithm relies on the fact that it reads individual key presses
 /// as separate chunks, and when it receives a lot of bytes at once
-/// it tries to parse sequences of them, checking how many bytes they consumed and
-/// storing the excesses (when receiving events rapidly enough) for the next iterations
+/// it tries to parse sequences of them one after another, checking how many bytes they consumed
+/// (this happens when receiving events, mostly mouse ones, rapidly enough).
+/// A sequence that didn't fit into one read is kept until the rest of it comes with the next one
 ///
-pub fn create_event_receiver<R: Read + Send + 'static>(mut input: R) -> Receiver<io::Result<Event>> {
+pub fn create_event_receiver<R: Read + Send + 'static>(input: R) -> Receiver<io::Result<Event>> {
+    spawn_reader(input, |event| event)
+}
+
+/// Same as `create_event_receiver`, but every event comes with the time it was read at
+pub fn create_timed_event_receiver<R: Read + Send + 'static>(input: R) -> Receiver<io::Result<(Event, Instant)>> {
+    spawn_reader(input, |event| (event, Instant::now()))
+}
+
+fn spawn_reader<R, T, F>(mut input: R, wrap: F) -> Receiver<io::Result<T>>
+    where R: Read + Send + 'static, T: Send + 'static, F: Fn(Event) -> T + Send + 'static {
     let (tx, rx) = crossbeam_channel::unbounded();
     thread::spawn(move || {
-        let mut offset = 0;
-        // mouse event with both coords >100 takes 13 bytes, max found yet
-        let mut buf = [0; 16];
+        let mut parser = EventParser::default();
+        // SGR-pixels mouse event with huge coords takes 20 bytes, leave plenty of room
+        let mut buf = [0; 256];
         loop {
-            let res = match input.read(&mut buf[offset..]) {
-                Ok(bytes_read) => {
-                    match bytes_read {
-                        0 => break, // 0 bytes read means EOF
-                        _ => {
-                            let size = bytes_read + offset;
-                            let (event, read) = parse_input_sequence(&buf[0..size]);
-                            if read < size {
-                                // store the unconsumed part as part of the next buffer
-                                offset = size - read;
-                                buf.copy_within(read..size, 0);
-                            } else {
-                                offset = 0;
-                            }
-                            tx.send(Ok(event))
-                        }
-                    }
-                }
+            let res = match input.read(&mut buf) {
+                Ok(0) => break, // 0 bytes read means EOF
+                Ok(size) => parser.parse(&buf[..size])
+                    .try_for_each(|event| tx.send(Ok(wrap(event)))),
                 Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
                 Err(e) => tx.send(Err(e)),
             };
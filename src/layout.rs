@@ -0,0 +1,188 @@
+use crate::state::{Dimension, Position};
+
+/// Where something is on the screen and how big it is
+pub type Rect = (Position, Dimension);
+
+/// How much room a node takes along the row or the column it's in
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Size {
+    Fixed(u16),
+    /// As much as the node would like, see `Layout::preferred`
+    Auto,
+    /// As much as the node would like, but no less than this
+    Min(u16),
+    /// A share of what the others leave, by weight. Stuck to an edge it takes all that's left
+    Flex(u16),
+}
+
+/// The edge of the screen (or whatever is left of it) a node is stuck to
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Edge {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+/// A tree of rows and columns with the widgets in the leaves, which tells
+/// where each widget goes, whatever the size of the screen is
+pub enum Layout {
+    /// Nothing at all, for the gaps
+    Empty,
+    /// The widget with this id, as given out by `Gui::add`
+    Widget(usize),
+    /// Side by side, left to right
+    Row(Vec<(Size, Layout)>),
+    /// One above the other, top to bottom
+    Column(Vec<(Size, Layout)>),
+    /// The nodes stuck to the edges, each one in order taking its part of what
+    /// the ones before it left, and the last one filling what remains in the middle
+    Dock(Vec<(Edge, Size, Layout)>, Box<Layout>),
+}
+
+impl Layout {
+    /// The size the node would like to have, with `hint` giving it for the widgets
+    pub fn preferred(&self, hint: &dyn Fn(usize) -> Dimension) -> Dimension {
+        let empty = Dimension { width: 0, height: 0 };
+        match self {
+            Layout::Empty => empty,
+            Layout::Widget(id) => hint(*id),
+            Layout::Row(children) => children.iter().fold(empty, |sum, (size, child)| {
+                let preferred = child.preferred(hint);
+                Dimension {
+                    width: sum.width.saturating_add(wanted(*size, preferred.width)),
+                    height: sum.height.max(preferred.height),
+                }
+            }),
+            Layout::Column(children) => children.iter().fold(empty, |sum, (size, child)| {
+                let preferred = child.preferred(hint);
+                Dimension {
+                    width: sum.width.max(preferred.width),
+                    height: sum.height.saturating_add(wanted(*size, preferred.height)),
+                }
+            }),
+            Layout::Dock(docked, center) => docked.iter().rev().fold(center.preferred(hint), |inner, (edge, size, child)| {
+                let preferred = child.preferred(hint);
+                match edge {
+                    Edge::Left | Edge::Right => Dimension {
+                        width: inner.width.saturating_add(wanted(*size, preferred.width)),
+                        height: inner.height.max(preferred.height),
+                    },
+                    Edge::Top | Edge::Bottom => Dimension {
+                        width: inner.width.max(preferred.width),
+                        height: inner.height.saturating_add(wanted(*size, preferred.height)),
+                    },
+                }
+            }),
+        }
+    }
+
+    /// Places the widgets within the rect, giving out where each one went.
+    /// The rects don't overlap, and when there isn't enough room the last ones get less
+    pub fn compute(&self, rect: Rect, hint: &dyn Fn(usize) -> Dimension) -> Vec<(usize, Rect)> {
+        let mut placed = vec![];
+        self.place(rect, hint, &mut placed);
+        placed
+    }
+
+    fn place(&self, (pos, size): Rect, hint: &dyn Fn(usize) -> Dimension, placed: &mut Vec<(usize, Rect)>) {
+        match self {
+            Layout::Empty => {}
+            Layout::Widget(id) => placed.push((*id, (pos, size))),
+            Layout::Row(children) => {
+                let sizes: Vec<(Size, u16)> = children.iter().map(|(s, c)| (*s, c.preferred(hint).width)).collect();
+                let mut x = pos.x;
+                for (width, (_, child)) in split(size.width, &sizes).into_iter().zip(children) {
+                    child.place((Position { x, y: pos.y }, Dimension { width, height: size.height }), hint, placed);
+                    x += width;
+                }
+            }
+            Layout::Column(children) => {
+                let sizes: Vec<(Size, u16)> = children.iter().map(|(s, c)| (*s, c.preferred(hint).height)).collect();
+                let mut y = pos.y;
+                for (height, (_, child)) in split(size.height, &sizes).into_iter().zip(children) {
+                    child.place((Position { x: pos.x, y }, Dimension { width: size.width, height }), hint, placed);
+                    y += height;
+                }
+            }
+            Layout::Dock(docked, center) => {
+                let (mut pos, mut size) = (pos, size);
+                for (edge, wants, child) in docked {
+                    let preferred = child.preferred(hint);
+                    let rect = match edge {
+                        Edge::Left | Edge::Right => {
+                            let width = taken(*wants, preferred.width, size.width);
+                            size.width -= width;
+                            let x = if *edge == Edge::Left { pos.x } else { pos.x + size.width };
+                            if *edge == Edge::Left {
+                                pos.x += width;
+                            }
+                            (Position { x, y: pos.y }, Dimension { width, height: size.height })
+                        }
+                        Edge::Top | Edge::Bottom => {
+                            let height = taken(*wants, preferred.height, size.height);
+                            size.height -= height;
+                            let y = if *edge == Edge::Top { pos.y } else { pos.y + size.height };
+                            if *edge == Edge::Top {
+                                pos.y += height;
+                            }
+                            (Position { x: pos.x, y }, Dimension { width: size.width, height })
+                        }
+                    };
+                    child.place(rect, hint, placed);
+                }
+                center.place((pos, size), hint, placed);
+            }
+        }
+    }
+}
+
+/// How much a node asks for along the axis, the flexible ones don't ask for anything
+fn wanted(size: Size, preferred: u16) -> u16 {
+    match size {
+        Size::Fixed(fixed) => fixed,
+        Size::Auto => preferred,
+        Size::Min(min) => preferred.max(min),
+        Size::Flex(_) => 0,
+    }
+}
+
+/// How much a docked node gets out of what's left
+fn taken(size: Size, preferred: u16, left: u16) -> u16 {
+    match size {
+        Size::Flex(_) => left,
+        size => wanted(size, preferred).min(left),
+    }
+}
+
+/// Splits the length between the nodes, the ones that ask for something first,
+/// in order, then the flexible ones share the rest
+fn split(length: u16, sizes: &[(Size, u16)]) -> Vec<u16> {
+    let mut left = length;
+    let mut lengths: Vec<u16> = sizes.iter()
+        .map(|&(size, preferred)| {
+            let length = wanted(size, preferred).min(left);
+            left -= length;
+            length
+        })
+        .collect();
+    let weights: u32 = sizes.iter().map(|(size, _)| if let Size::Flex(w) = size { *w as u32 } else { 0 }).sum();
+    if weights == 0 {
+        return lengths;
+    }
+    let mut rest = left;
+    for (length, (size, _)) in lengths.iter_mut().zip(sizes) {
+        if let Size::Flex(weight) = size {
+            *length = (left as u32 * *weight as u32 / weights) as u16;
+            rest -= *length;
+        }
+    }
+    // what's left from the rounding goes to the first ones
+    for (length, (size, _)) in lengths.iter_mut().zip(sizes) {
+        if rest > 0 && matches!(size, Size::Flex(w) if *w > 0) {
+            *length += 1;
+            rest -= 1;
+        }
+    }
+    lengths
+}
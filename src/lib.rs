@@ -1,5 +1,18 @@
-
 pub mod term;
 pub mod input;
+pub mod gesture;
+pub mod record;
 pub mod state;
 pub mod draw;
+pub mod surface;
+pub mod layout;
+pub mod graphics;
+pub mod gui;
+pub mod command;
+pub mod dialog;
+pub mod png;
+pub mod scale;
+pub mod transform;
+pub mod document;
+pub mod history;
+pub mod vt;
@@ -1,145 +1,117 @@
 use std::{
     error::Error,
-    fs::OpenOptions,
-    io,
+    fs::{File, OpenOptions},
     io::Write,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, Instant},
 };
 
 use color_backtrace::{BacktracePrinter, default_output_stream};
 use crossbeam_channel::select;
 
 use entropic::{
-    draw::*,
+    document::{self, Autosave},
+    draw::ColorDepth,
+    graphics::*,
+    gui::*,
     input::*,
+    record::*,
     state::*,
     term::*,
 };
 
-trait Widget {
-    fn draw(&self, gui: &mut GuiState) -> io::Result<()>;
-
-    fn get_bounds(&self) -> (Position, Dimension);
-
-    fn on_mouse_input(&mut self, _: &mut GuiState, _: MouseAction, _: MouseButton, _: Position, _: Modifiers) -> io::Result<()> {
-        Ok(())
-    }
-}
-
-struct TheField {
-    current_layer: usize,
-    picture: Picture,
+/// How often the unsaved changes are autosaved
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Default)]
+struct Args {
+    /// The picture to open, a new one is made if it doesn't exist
+    file: Option<PathBuf>,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+    /// `None` means as fast as possible
+    speed: Option<f64>,
+    /// Detected from the environment if not given
+    colors: Option<ColorDepth>,
+    dither: bool,
 }
 
-impl Widget for TheField {
-    fn draw(&self, gui: &mut GuiState) -> io::Result<()> {
-        let Dimension { width, height } = self.picture.size.min(gui.terminal);
-
-        let mx = gui.mouse.x.max(3).min(width * 2);
-        let my = gui.mouse.y.max(2).min(height + 1);
-
-        gui.buffer.clear(gui.terminal);
-
-        gui.buffer.put(Position { x: 0, y: 0 }, CharCell { color: CellColor::none().bg(gui.primary.clone()), char: ' ' });
-        gui.buffer.put(Position { x: 1, y: 0 }, CharCell { color: CellColor::none().bg(gui.secondary.clone()), char: ' ' });
-
-        for i in 0..width.min(100) {
-            let bg = if i == (mx - 2) / 2 {
-                Color::gray(40)
-            } else {
-                Color::gray(120 - ((i % 2) as u8 * 20))
-            };
-            gui.buffer.put_text(Position { x: i * 2 + 2, y: 0 }, CellColor::default().bg(bg), format!("{:0>2}", i));
-        }
-
-        for i in 0..height.min(100) {
-            let bg = if i == my - 2 {
-                Color::gray(40)
-            } else {
-                Color::gray(120 - ((i % 2) as u8 * 20))
-            };
-            gui.buffer.put_text(Position { x: 0, y: i + 1 }, CellColor::default().bg(bg), format!("{:0>2}", i));
-        }
-
-        for (offset, pos) in self.picture.size.into_iter().enumerate() {
-            let mut pixel = Pixel::default();
-            for layer in self.picture.layers.iter() {
-                pixel = Pixel::blend(pixel, layer.pixels[offset]);
+fn parse_args() -> Result<Args, Box<dyn Error>> {
+    let mut args = Args { speed: Some(1.0), ..Args::default() };
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--record" => args.record = Some(value()?.into()),
+            "--replay" => args.replay = Some(value()?.into()),
+            "--colors" => args.colors = Some(value()?.parse()?),
+            "--dither" => args.dither = true,
+            "--speed" => {
+                let speed: f64 = value()?.parse()?;
+                args.speed = if speed > 0.0 { Some(speed) } else { None };
             }
-            gui.buffer.put_text(Position { x: pos.x * 2 + 2, y: pos.y + 1 }, CellColor::default().bg(pixel.into()), "  ")
+            _ if !arg.starts_with('-') && args.file.is_none() => args.file = Some(arg.into()),
+            _ => return Err(format!("unknown argument {}, expected FILE, --record FILE, --replay FILE, --speed N, --colors DEPTH or --dither", arg).into()),
         }
-
-        gui.buffer.draw(gui.terminal)?;
-
-        Ok(())
     }
-
-    fn get_bounds(&self) -> (Position, Dimension) {
-        let Dimension { width, height } = self.picture.size;
-        (Position { x: 0, y: 0 }, Dimension { width: width * 2 + 1, height: height + 1 })
-    }
-
-    fn on_mouse_input(&mut self, gui: &mut GuiState, _: MouseAction, button: MouseButton, pos: Position, _: Modifiers) -> io::Result<()> {
-        let pic = &mut self.picture;
-        let size = pic.size;
-        let pos = Position { x: pos.x / 2, ..pos };
-        if pos.x >= 1 && pos.y >= 1 {
-            let pos = pos - Position { x: 1, y: 1 };
-            pic.layers[self.current_layer].pixels[size.offset(pos)] = match button {
-                MouseButton::Left => Pixel { r: gui.primary.r, g: gui.primary.g, b: gui.primary.b, a: 255 },
-                _ => Pixel { r: 0, g: 0, b: 0, a: 0 },
-            };
-        }
-        Ok(())
+    if args.record.is_some() && args.replay.is_some() {
+        return Err("can't record and replay at the same time".into());
     }
+    Ok(args)
 }
 
-struct GuiState {
-    terminal: Dimension,
-    mouse: Position,
-    buffer: TerminalState,
-    primary: Color,
-    secondary: Color,
-}
-
-struct Gui {
-    state: GuiState,
-    widgets: Vec<Box<dyn Widget>>,
-}
-
-impl Gui {
-    pub fn add<W: Widget + 'static>(&mut self, widget: W) {
-        self.widgets.push(Box::new(widget));
-    }
-
-    fn draw(&mut self) -> io::Result<()> {
-        for widget in self.widgets.iter_mut() {
-            widget.draw(&mut self.state)?;
+/// Handles the answers to the queries sent at the start,
+/// while replaying those come from the live input
+fn on_capability(gui: &mut Gui, event: &Event) {
+    match event {
+        Event::ModeReport(2026, mode) => gui.buffer.set_synchronized(mode.is_supported()),
+        Event::KittyGraphics(KITTY_QUERY_ID, true) => gui.state.graphics = Graphics::Kitty,
+        // the kitty answer comes before this one, if there is any
+        Event::DeviceAttributes(attributes) if gui.state.graphics == Graphics::HalfBlocks && has_sixel(attributes) => {
+            gui.state.graphics = Graphics::Sixel;
         }
-        Ok(())
-    }
-
-    fn redraw(&mut self) -> io::Result<()> {
-        self.state.buffer.redraw(self.state.terminal)
+        _ => {}
     }
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let base_term = TerminalBase
+    let args = parse_args()?;
+
+    // before touching the terminal, so that the errors are seen
+    let picture = match &args.file {
+        Some(path) if path.exists() => document::load(path).map_err(|e| format!("{}: {}", path.display(), e))?,
+        _ => Picture {
+            size: Dimension { width: 32, height: 32 },
+            layers: vec![
+                Layer { name: "Background".into(), pixels: vec![Pixel { r: 0x3f, g: 0x3f, b: 0x3f, a: 0xff }; 32 * 32].into_boxed_slice() },
+                Layer { name: "Layer 1".into(), pixels: vec![Pixel { r: 0x0, g: 0x0, b: 0x0, a: 0x00 }; 32 * 32].into_boxed_slice() },
+            ],
+        },
+    };
+
+    let mut base_term = TerminalBase::stdout()
         .raw()?
         .mouse_input()?
         .hide_cursor()?
         .no_wrap()?
+        .focus_events()?
         .alt_screen()?;
 
-    let term_hook = base_term.clone();
+    // the hook can't have its own mutable state, it has to be shared
+    let term_hook = Mutex::new(base_term.clone());
 
     let hook = BacktracePrinter::default();
     std::panic::set_hook(Box::new(move |panic_info| {
         // apparently panic info prints before unwinding,
         // so drop calls happen only after its already printed
-        let _ = term_hook.dont_listen_to_mouse();
-        let _ = term_hook.switch_to_normal();
-        let _ = term_hook.normal_mode();
+        if let Ok(mut term_hook) = term_hook.lock() {
+            let _ = term_hook.dont_listen_to_mouse();
+            let _ = term_hook.dont_report_focus();
+            let _ = term_hook.switch_to_normal();
+            let _ = term_hook.normal_mode();
+        }
 
         let _ = hook.print_panic_info(panic_info, &mut default_output_stream());
         // set stored cursor pos so that switch_to_normal call from the actual drop
@@ -150,96 +122,173 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let (w, h) = term_size::dimensions_stdout().expect("can't get terminal dimensions, todo handle this");
 
-    let mut gui = Gui {
-        state: GuiState {
-            terminal: Dimension { width: w as u16, height: h as u16 },
-            mouse: Position::default(),
-            buffer: TerminalState::new(Dimension { width: 80 * 4, height: 24 * 4 }),
-            primary: Color::gray(255),
-            secondary: Color::gray(0),
-        },
-        widgets: vec![],
+    let mut gui = Gui::new(Dimension { width: w as u16, height: h as u16 }, picture);
+    gui.state.file = args.file.clone();
+
+    // a replay would only be autosaving what's already in the recording
+    let mut autosave = args.replay.is_none().then(|| Autosave::new(document::recovery_dir(), args.file.as_deref()));
+    if let Some(autosave) = &autosave {
+        if autosave.path().exists() {
+            gui.offer_recovery(autosave.path().to_path_buf());
+        }
+    }
+    let autosaves = match autosave {
+        Some(_) => crossbeam_channel::tick(AUTOSAVE_INTERVAL),
+        None => crossbeam_channel::never(),
     };
-    gui.add(TheField {
-        current_layer: 1,
-        picture: Picture {
-            size: Dimension { width: 32, height: 32 },
-            layers: vec![
-                Layer { pixels: vec![Pixel { r: 0x3f, g: 0x3f, b: 0x3f, a: 0xff }; 32 * 32].into_boxed_slice() },
-                Layer { pixels: vec![Pixel { r: 0x0, g: 0x0, b: 0x0, a: 0x00 }; 32 * 32].into_boxed_slice() },
-            ],
-        },
-    });
 
-    gui.draw()?;
+    gui.buffer.set_color_depth(args.colors.unwrap_or_else(ColorDepth::from_env));
+    gui.state.dither = args.dither;
 
-    let mut term = base_term.terminal_resizes()?;
+    gui.draw(&mut base_term)?;
+
+    gui.state.cell_size = cell_size();
+
+    base_term.query_mode(2026)?;
+    base_term.write_all(&capability_query())?;
+    base_term.flush()?;
+    // pixel positions are only meaningful with the cell size at the time,
+    // so keep the recordings in cells to be able to replay them anywhere
+    if args.record.is_none() && args.replay.is_none() {
+        base_term.report_pixels()?;
+    }
+
+    let mut term = base_term.terminal_resizes()?.termination_signals()?;
     let resizes_rx = term.get_resize_event_receiver().clone();
-    let events = create_event_receiver(std::io::stdin());
-
-    loop {
-        select! {
-            recv(events) -> event => {
-                match event {
-                    Ok(Ok(event)) => {
-                        match event {
-                            Event::Press('c', Modifiers::Ctrl) => break,
-                            Event::Press('q', Modifiers::None) => break,
-                            Event::Press('l', Modifiers::Ctrl) => {
-                                write!(term, "\x1b[2J\x1b[1;1H")?;
-                                term.flush()?;
-                            }
-        //                    Event::Arrow(arrow, Modifiers::None) => {
-        //                        let old_cursor = cursor;
-        //                        match arrow {
-        //                            Arrow::Up => cursor.y -= 1,
-        //                            Arrow::Down => cursor.y += 1,
-        //                            Arrow::Right => cursor.x += 1,
-        //                            Arrow::Left => cursor.x -= 1,
-        //                        }
-        //                        term.write_at((old_cursor.x - 1) / 2 * 2 + 1, old_cursor.y, "  ")?;
-        //                        term.write_at((cursor.x - 1) / 2 * 2 + 1, cursor.y, "╺╸")?;
-        //                    }
-                            Event::Press('r', Modifiers::Ctrl) => gui.redraw()?,
-                            Event::Press('x', Modifiers::None) => {
-                                std::mem::swap(&mut gui.state.primary, &mut gui.state.secondary);
-                                gui.draw()?;
-                            },
-                            Event::Mouse(action, button, mouse, modifiers) => {
-                                gui.state.mouse = mouse;
-                                for widget in &mut gui.widgets {
-                                    let (pos, size) = widget.get_bounds();
-
-                                    if mouse.x > pos.x && mouse.y > pos.y && mouse.x <= pos.x + size.width && mouse.y <= pos.y + size.height {
-                                        widget.on_mouse_input(&mut gui.state, action, button, mouse - pos, modifiers)?;
-                                    }
-                                };
-                                gui.draw()?;
+    let terminations_rx = term.get_termination_receiver().clone();
+
+    let mut sizes = None;
+    let (mut events, live) = match (&args.replay, &args.record) {
+        (Some(path), _) => {
+            let replay = create_replay_receiver(Recording::new(File::open(path)?)?, args.speed);
+            (replay, create_timed_event_receiver(std::io::stdin()))
+        }
+        (None, Some(path)) => {
+            let recorder = Recorder::new(std::io::stdin(), File::create(path)?, gui.state.terminal)?;
+            sizes = Some(recorder.sizes());
+            (create_timed_event_receiver(recorder), crossbeam_channel::never())
+        }
+        (None, None) => (create_timed_event_receiver(std::io::stdin()), crossbeam_channel::never()),
+    };
+
+    // the panic hook can't get to the picture, so the emergency save is done here
+    // once the panic has unwound to us, after the hook has restored the terminal
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| -> Result<Option<i32>, Box<dyn Error>> {
+        loop {
+            let long_press = gui.deadline().map(crossbeam_channel::at).unwrap_or_else(crossbeam_channel::never);
+            select! {
+                recv(events) -> event => {
+                    match event {
+                        Ok(Ok((event, at))) => {
+                            // the recorded answers are about the terminal it was recorded in
+                            if args.replay.is_none() {
+                                on_capability(&mut gui, &event);
                             }
-                            Event::MouseMotion(pos, _) => {
-                                gui.state.mouse = pos;
-                                gui.draw()?;
+                            if let Event::ModeReport(1016, mode) = event {
+                                gui.state.pixel_mouse = if mode.is_set() { cell_size() } else { None };
+                                if mode.is_set() && gui.state.pixel_mouse.is_none() {
+                                    // pixels are useless if we can't tell the cell size
+                                    term.report_cells()?;
+                                }
                             }
-                            _event => {
+                            if let Event::UnknownByteSequence(_) = event {
                                 // heheh, funny debug thing
                                 let _ = OpenOptions::new().write(true).open("/dev/pts/1")
-                                    .map(|mut f| writeln!(f, "{:?}", _event));
+                                    .map(|mut f| writeln!(f, "{:?}", event));
+                            }
+                            match gui.handle(event, at)? {
+                                Flow::Quit => return Ok(None),
+                                Flow::Redraw => {
+                                    gui.draw(&mut term)?;
+                                    gui.redraw(&mut term)?;
+                                }
+                                Flow::ClearScreen => {
+                                    gui.clear_screen(&mut term)?;
+                                    gui.draw(&mut term)?;
+                                }
+                                Flow::Continue => gui.draw(&mut term)?,
                             }
                         }
+                        Ok(Err(e)) => return Err(Box::new(e)),
+                        // the replay is over, leave the result on the screen
+                        Err(_) if args.replay.is_some() => events = crossbeam_channel::never(),
+                        Err(e) => return Err(Box::new(e)),
                     }
-                    Ok(Err(e)) => return Err(Box::new(e)),
-                    Err(e) => return Err(Box::new(e)),
                 }
-            }
-            recv(resizes_rx) -> _ => {
-                let (w, h) = term_size::dimensions_stdout().expect("can't get terminal dimensions, todo handle this");
-                gui.state.terminal = Dimension { width: w as u16, height: h as u16 };
-                gui.draw()?;
-                gui.redraw()?;
+                // while replaying, the actual input can only be used to quit
+                recv(live) -> event => {
+                    if let Ok(Ok((event, _))) = event {
+                        on_capability(&mut gui, &event);
+                        if gui.is_quit(&event) {
+                            return Ok(None);
+                        }
+                    }
+                }
+                recv(long_press) -> _ => {
+                    gui.tick(Instant::now())?;
+                    gui.draw(&mut term)?;
+                }
+                recv(resizes_rx) -> _ => {
+                    let (w, h) = term_size::dimensions_stdout().expect("can't get terminal dimensions, todo handle this");
+                    let terminal = Dimension { width: w as u16, height: h as u16 };
+                    if let Some(sizes) = &sizes {
+                        sizes.record(terminal)?;
+                    }
+                    // the replay lays things out for the size it was recorded with
+                    if args.replay.is_none() {
+                        gui.resize(terminal);
+                    }
+                    gui.state.cell_size = cell_size();
+                    if gui.state.pixel_mouse.is_some() {
+                        gui.state.pixel_mouse = cell_size();
+                        if gui.state.pixel_mouse.is_none() {
+                            term.report_cells()?;
+                        }
+                    }
+                    gui.draw(&mut term)?;
+                    gui.redraw(&mut term)?;
+                }
+                recv(autosaves) -> _ => {
+                    if let Some(autosave) = &mut autosave {
+                        if let Err(e) = gui.autosave(autosave) {
+                            gui.state.message = Some(Message::Error(format!("couldn't autosave: {}", e)));
+                            gui.draw(&mut term)?;
+                        }
+                    }
+                }
+                recv(terminations_rx) -> signal => return Ok(signal.ok()),
             }
         }
+    }));
+
+    // unless it was quit on purpose, the unsaved changes are kept for the next time
+    let saved = match (&outcome, &mut autosave) {
+        (Ok(Ok(None)), Some(autosave)) if !gui.state.recovery_offered => {
+            autosave.discard()?;
+            None
+        }
+        (_, Some(autosave)) if gui.state.modified => {
+            let saved = autosave.update(&gui.state.picture, true, gui.state.file.as_deref());
+            Some(saved.map(|_| autosave.path().to_path_buf()))
+        }
+        _ => None,
+    };
+
+    if let Ok(Ok(Some(signal_hook::consts::SIGHUP))) = outcome {
+        // there's no terminal left to restore, and writing to it would only fail
+        std::mem::forget(term);
+    } else {
+        drop(term);
+        // stderr is gone with the terminal on a hangup, this is for every other way out
+        match saved {
+            Some(Ok(path)) => eprintln!("the unsaved changes are in {}, they will be offered to recover the next time", path.display()),
+            Some(Err(e)) => eprintln!("couldn't save the unsaved changes: {}", e),
+            None => {}
+        }
     }
 
-    Ok(())
+    match outcome {
+        Ok(result) => result.map(|_| ()),
+        Err(panic) => panic::resume_unwind(panic),
+    }
 }
-
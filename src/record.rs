@@ -0,0 +1,184 @@
+use std::{io, thread};
+use std::convert::TryInto;
+use std::io::{ErrorKind, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Receiver;
+
+use crate::input::{Event, EventParser};
+use crate::state::Dimension;
+
+const MAGIC: &[u8] = b"entropic input recording v2\n";
+
+/// Stands in for the length of a chunk to mark a resize, which has the new size instead
+const RESIZE: u32 = u32::MAX;
+
+/// Wraps an input, writing every chunk read from it into the output
+/// along with the time it was read at, so that it could be replayed later.
+/// The size of the terminal is in there too, as the layout and so where
+/// the clicks land depend on it.
+///
+/// The chunks are kept as they were read, since the parser relies on them
+pub struct Recorder<R: Read, W: Write> {
+    input: R,
+    sizes: SizeRecorder<W>,
+}
+
+impl<R: Read, W: Write> Recorder<R, W> {
+    pub fn new(input: R, mut output: W, terminal: Dimension) -> io::Result<Recorder<R, W>> {
+        output.write_all(MAGIC)?;
+        let sizes = SizeRecorder { output: Arc::new(Mutex::new(output)), start: Instant::now() };
+        sizes.record(terminal)?;
+        Ok(Recorder { input, sizes })
+    }
+
+    /// Something to record the resizes with, from wherever they are noticed
+    pub fn sizes(&self) -> SizeRecorder<W> {
+        self.sizes.clone()
+    }
+}
+
+impl<R: Read, W: Write> Read for Recorder<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = self.input.read(buf)?;
+        if size > 0 {
+            self.sizes.write(size as u32, &buf[..size])?;
+        }
+        Ok(size)
+    }
+}
+
+/// Writes the resizes into the same recording as the `Recorder` it came from
+pub struct SizeRecorder<W: Write> {
+    output: Arc<Mutex<W>>,
+    start: Instant,
+}
+
+// derived Clone would want W to be Clone as well
+impl<W: Write> Clone for SizeRecorder<W> {
+    fn clone(&self) -> Self {
+        SizeRecorder { output: self.output.clone(), start: self.start }
+    }
+}
+
+impl<W: Write> SizeRecorder<W> {
+    pub fn record(&self, terminal: Dimension) -> io::Result<()> {
+        let mut bytes = terminal.width.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&terminal.height.to_le_bytes());
+        self.write(RESIZE, &bytes)
+    }
+
+    fn write(&self, length: u32, bytes: &[u8]) -> io::Result<()> {
+        let mut output = self.output.lock().map_err(|_| io::Error::other("the recording is poisoned"))?;
+        let micros = self.start.elapsed().as_micros() as u64;
+        output.write_all(&micros.to_le_bytes())?;
+        output.write_all(&length.to_le_bytes())?;
+        output.write_all(bytes)?;
+        // flush every time so that nothing is lost if we crash
+        output.flush()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Chunk {
+    /// Input, as it was read
+    Input(Duration, Vec<u8>),
+    /// The terminal size, at the start and then after every resize
+    Resize(Duration, Dimension),
+}
+
+impl Chunk {
+    /// Time since the recording started
+    pub fn at(&self) -> Duration {
+        match self {
+            Chunk::Input(at, _) | Chunk::Resize(at, _) => *at,
+        }
+    }
+}
+
+/// Iterates over the chunks of a recording made by the `Recorder`.
+/// A truncated last chunk is silently dropped, as it is most likely
+/// the result of the recording process being killed
+pub struct Recording<R: Read> {
+    input: R,
+}
+
+impl<R: Read> Recording<R> {
+    pub fn new(mut input: R) -> io::Result<Recording<R>> {
+        let mut magic = [0; MAGIC.len()];
+        input.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(io::Error::new(ErrorKind::InvalidData, "not an entropic input recording"));
+        }
+        Ok(Recording { input })
+    }
+}
+
+impl<R: Read> Iterator for Recording<R> {
+    type Item = io::Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut header = [0; 12];
+        match self.input.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return None,
+            Err(e) => return Some(Err(e)),
+        }
+        let (micros, size) = header.split_at(8);
+        let micros = u64::from_le_bytes(micros.try_into().unwrap());
+        let size = u32::from_le_bytes(size.try_into().unwrap());
+
+        let at = Duration::from_micros(micros);
+        let mut bytes = vec![0; if size == RESIZE { 4 } else { size as usize }];
+        match self.input.read_exact(&mut bytes) {
+            Ok(()) if size == RESIZE => {
+                let width = u16::from_le_bytes([bytes[0], bytes[1]]);
+                let height = u16::from_le_bytes([bytes[2], bytes[3]]);
+                Some(Ok(Chunk::Resize(at, Dimension { width, height })))
+            }
+            Ok(()) => Some(Ok(Chunk::Input(at, bytes))),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Feeds a recording through the same parser the live input goes through,
+/// with the resizes coming as `Event::Resize` in between.
+///
+/// With `speed` of `None` the chunks are sent as fast as possible, otherwise they
+/// keep their original timing, sped up `speed` times.
+/// Either way, the events come with the times they were recorded at (relative
+/// to the start of the replay), so that anything timing-dependent, like double clicks,
+/// comes out exactly the same
+pub fn create_replay_receiver<R: Read + Send + 'static>(recording: Recording<R>, speed: Option<f64>) -> Receiver<io::Result<(Event, Instant)>> {
+    let (tx, rx) = crossbeam_channel::unbounded();
+    thread::spawn(move || {
+        let start = Instant::now();
+        let mut parser = EventParser::default();
+        for chunk in recording {
+            let res = match chunk {
+                Ok(chunk) => {
+                    let at = chunk.at();
+                    if let Some(speed) = speed {
+                        let due = start + at.div_f64(speed);
+                        let now = Instant::now();
+                        if due > now {
+                            thread::sleep(due - now);
+                        }
+                    }
+                    match chunk {
+                        Chunk::Input(_, bytes) => parser.parse(&bytes).try_for_each(|event| tx.send(Ok((event, start + at)))),
+                        Chunk::Resize(_, terminal) => tx.send(Ok((Event::Resize(terminal), start + at))),
+                    }
+                }
+                Err(e) => tx.send(Err(e)),
+            };
+            if res.is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
@@ -0,0 +1,143 @@
+use crate::state::{Dimension, Picture, Pixel, Position};
+
+/// How the pixels are made up when the picture gets bigger
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Scaler {
+    /// Every pixel is the one closest to it in the original, works with any factor
+    Nearest,
+    /// EPX, which rounds off the corners of the diagonals, 2x at a time
+    Scale2x,
+    /// The same for 3x, keeping the straight lines straight
+    Scale3x,
+}
+
+impl Scaler {
+    pub const ALL: [Scaler; 3] = [Scaler::Nearest, Scaler::Scale2x, Scaler::Scale3x];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Scaler::Nearest => "nearest",
+            Scaler::Scale2x => "scale2x",
+            Scaler::Scale3x => "scale3x",
+        }
+    }
+
+    /// Scales the pixels by the factor, which for the pixel art ones has to be
+    /// a power of the factor they scale by, 2, 4, 8 and so on for Scale2x.
+    /// The size is rounded, but it's never less than a pixel, nor more than a picture can have
+    pub fn scale(self, pixels: &[Pixel], size: Dimension, factor: f64) -> Result<(Vec<Pixel>, Dimension), String> {
+        let scaled = |length: u16| (length as f64 * factor).round().max(1.0);
+        let (width, height) = (scaled(size.width), scaled(size.height));
+        if width > u16::MAX as f64 || height > u16::MAX as f64 {
+            return Err(too_big(size, factor));
+        }
+        if width * height > Picture::MAX_PIXELS as f64 {
+            let budget = Picture::MAX_PIXELS;
+            return Err(format!("{}x{} scaled by {} is too big, a picture has at most {} pixels", size.width, size.height, factor, budget));
+        }
+        let step = match self {
+            Scaler::Nearest => {
+                let scaled = Dimension { width: width as u16, height: height as u16 };
+                return Ok((nearest(pixels, size, scaled), scaled));
+            }
+            Scaler::Scale2x => 2u32,
+            Scaler::Scale3x => 3,
+        };
+        let mut times = 0;
+        let mut total = 1.0;
+        while total < factor {
+            total *= step as f64;
+            times += 1;
+        }
+        if total != factor || times == 0 {
+            let powers: Vec<String> = (1..4).map(|power| step.pow(power).to_string()).collect();
+            return Err(format!("{} only scales by {} and so on", self.name(), powers.join(", ")));
+        }
+        let mut result = (pixels.to_vec(), size);
+        for _ in 0..times {
+            result = match self {
+                Scaler::Scale2x => scale2x(&result.0, result.1),
+                _ => scale3x(&result.0, result.1),
+            };
+        }
+        Ok(result)
+    }
+}
+
+fn too_big(size: Dimension, factor: f64) -> String {
+    format!("{}x{} is too big to be scaled by {}", size.width, size.height, factor)
+}
+
+/// Scales the pixels to the size, taking the pixel closest to the center of every new one.
+/// Scaled up by a whole number, every pixel is just repeated that many times in both directions
+pub fn nearest(pixels: &[Pixel], size: Dimension, scaled: Dimension) -> Vec<Pixel> {
+    // the centers are at the halves, so everything is doubled to stay in integers
+    let closest = |pos: u16, from: u16, to: u16| ((pos as u32 * 2 + 1) * from as u32 / (to as u32 * 2)) as u16;
+    scaled.into_iter()
+        .map(|Position { x, y }| {
+            let pos = Position { x: closest(x, size.width, scaled.width), y: closest(y, size.height, scaled.height) };
+            pixels[size.offset(pos)]
+        })
+        .collect()
+}
+
+/// The pixel and its 8 neighbours, row by row, the ones over the edges being the closest ones inside
+fn neighbourhood(pixels: &[Pixel], size: Dimension, pos: Position) -> [Pixel; 9] {
+    let mut around = [Pixel::default(); 9];
+    for (i, pixel) in around.iter_mut().enumerate() {
+        let x = (pos.x as i32 + i as i32 % 3 - 1).clamp(0, size.width as i32 - 1);
+        let y = (pos.y as i32 + i as i32 / 3 - 1).clamp(0, size.height as i32 - 1);
+        *pixel = pixels[size.offset(Position { x: x as u16, y: y as u16 })];
+    }
+    around
+}
+
+/// Puts the blocks the pixels turned into together, every block being `factor` pixels square
+fn assemble(size: Dimension, factor: u16, block: impl Fn(Position) -> Vec<Pixel>) -> (Vec<Pixel>, Dimension) {
+    let scaled = Dimension { width: size.width * factor, height: size.height * factor };
+    let mut result = vec![Pixel::default(); scaled.number()];
+    for pos in size {
+        for (i, pixel) in block(pos).into_iter().enumerate() {
+            let (dx, dy) = (i as u16 % factor, i as u16 / factor);
+            result[scaled.offset(Position { x: pos.x * factor + dx, y: pos.y * factor + dy })] = pixel;
+        }
+    }
+    (result, scaled)
+}
+
+/// EPX, also known as Scale2x, see <https://www.scale2x.it/algorithm>
+pub fn scale2x(pixels: &[Pixel], size: Dimension) -> (Vec<Pixel>, Dimension) {
+    assemble(size, 2, |pos| {
+        let [_, b, _, d, e, f, _, h, _] = neighbourhood(pixels, size, pos);
+        if b == h || d == f {
+            return vec![e; 4];
+        }
+        vec![
+            if d == b { d } else { e },
+            if b == f { f } else { e },
+            if d == h { d } else { e },
+            if h == f { f } else { e },
+        ]
+    })
+}
+
+/// Scale3x, from the same place as Scale2x
+pub fn scale3x(pixels: &[Pixel], size: Dimension) -> (Vec<Pixel>, Dimension) {
+    assemble(size, 3, |pos| {
+        let [a, b, c, d, e, f, g, h, i] = neighbourhood(pixels, size, pos);
+        if b == h || d == f {
+            return vec![e; 9];
+        }
+        vec![
+            if d == b { d } else { e },
+            if (d == b && e != c) || (b == f && e != a) { b } else { e },
+            if b == f { f } else { e },
+            if (d == b && e != g) || (d == h && e != a) { d } else { e },
+            e,
+            if (b == f && e != i) || (h == f && e != c) { f } else { e },
+            if d == h { d } else { e },
+            if (d == h && e != i) || (h == f && e != g) { h } else { e },
+            if h == f { f } else { e },
+        ]
+    })
+}
@@ -1,13 +1,15 @@
 #![allow(unused)]
 
 use std::fmt::{Debug, Error, Formatter};
-use std::io::{StdoutLock, Write};
+use std::io::Write;
 use std::io;
 use std::ops::{Add, Sub};
 
 use crate::draw::Drawable;
+use crate::scale::Scaler;
+use crate::transform::{Target, Transform};
 
-#[derive(Copy, Clone, Default)]
+#[derive(Copy, Clone, Default, PartialEq)]
 pub struct Pixel {
     pub r: u8,
     pub g: u8,
@@ -35,17 +37,19 @@ impl Pixel {
     }
 }
 
+#[derive(Clone, PartialEq)]
 pub struct Layer {
+    pub name: String,
     pub pixels: Box<[Pixel]>,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Dimension {
     pub width: u16,
     pub height: u16,
 }
 
-#[derive(Copy, Clone, Debug, Default)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
 pub struct Position {
     pub x: u16,
     pub y: u16,
@@ -68,7 +72,7 @@ impl Sub for Position {
 }
 
 impl Drawable for Position {
-    fn draw(&self, handle: &mut StdoutLock) -> io::Result<()> {
+    fn draw<W: Write + ?Sized>(&self, handle: &mut W) -> io::Result<()> {
         write!(handle, "\x1b[{};{}H", self.y + 1, self.x + 1)
     }
 }
@@ -131,11 +135,177 @@ impl IntoIterator for Dimension {
     }
 }
 
+/// Every layer has exactly `size.number()` pixels, and there's always at least one layer
+#[derive(Clone, PartialEq)]
 pub struct Picture {
     pub size: Dimension,
     pub layers: Vec<Layer>,
 }
 
+impl Picture {
+    /// The most pixels a picture can have. A layer of that many is 64 MiB,
+    /// and the history keeps copies of them
+    pub const MAX_PIXELS: usize = 4096 * 4096;
+
+    /// Makes sure a picture of the size stays within `MAX_PIXELS`
+    pub fn check_size(size: Dimension) -> Result<(), String> {
+        if size.number() > Self::MAX_PIXELS {
+            return Err(format!("{}x{} is too big, a picture has at most {} pixels", size.width, size.height, Self::MAX_PIXELS));
+        }
+        Ok(())
+    }
+
+    /// All of the layers blended together at the given position
+    pub fn composite(&self, pos: Position) -> Pixel {
+        let offset = self.size.offset(pos);
+        let mut pixel = Pixel::default();
+        for layer in self.layers.iter() {
+            pixel = Pixel::blend(pixel, layer.pixels[offset]);
+        }
+        pixel
+    }
+
+    /// Whether the layers are all there and all of the size of the picture
+    pub fn is_consistent(&self) -> bool {
+        !self.layers.is_empty() && self.layers.iter().all(|layer| layer.pixels.len() == self.size.number())
+    }
+
+    /// Changes the size of the picture, keeping the pixels at the anchor where they are,
+    /// cutting off the ones that don't fit anymore and leaving the new ones transparent
+    pub fn resize_canvas(&mut self, size: Dimension, anchor: Anchor) {
+        let (x, y) = anchor.halves();
+        let dx = (size.width as i32 - self.size.width as i32) * x / 2;
+        let dy = (size.height as i32 - self.size.height as i32) * y / 2;
+        self.recanvas(size, dx, dy);
+    }
+
+    /// Cuts the picture down to the rectangle, which has to be inside of it
+    pub fn crop(&mut self, origin: Position, size: Dimension) -> Result<(), String> {
+        if size.number() == 0 || origin.x as u32 + size.width as u32 > self.size.width as u32 || origin.y as u32 + size.height as u32 > self.size.height as u32 {
+            return Err(format!("{}x{} at {},{} is not inside of the {}x{} picture", size.width, size.height, origin.x, origin.y, self.size.width, self.size.height));
+        }
+        self.recanvas(size, -(origin.x as i32), -(origin.y as i32));
+        Ok(())
+    }
+
+    /// The smallest rectangle with all of the pixels that aren't fully transparent
+    /// in any of the layers, `None` when there are none of those
+    pub fn bounds(&self) -> Option<(Position, Dimension)> {
+        let opaque = |pos: Position| self.layers.iter().any(|layer| layer.pixels[self.size.offset(pos)].a != 0);
+        let (min, max) = self.size.into_iter().filter(|&pos| opaque(pos)).fold(None, |bounds, pos| match bounds {
+            None => Some((pos, pos)),
+            Some((min, max)) => Some((
+                Position { x: pos.x.min(min.x), y: pos.y.min(min.y) },
+                Position { x: pos.x.max(max.x), y: pos.y.max(max.y) },
+            )),
+        })?;
+        Some((min, Dimension { width: max.x - min.x + 1, height: max.y - min.y + 1 }))
+    }
+
+    /// Scales every layer by the factor, see `Scaler::scale`
+    pub fn scale(&mut self, factor: f64, scaler: Scaler) -> Result<(), String> {
+        let mut size = self.size;
+        let scaled = self.layers.iter()
+            .map(|layer| scaler.scale(&layer.pixels, self.size, factor).map(|(pixels, scaled)| {
+                size = scaled;
+                pixels.into_boxed_slice()
+            }))
+            .collect::<Result<Vec<_>, _>>()?;
+        for (layer, pixels) in self.layers.iter_mut().zip(scaled) {
+            layer.pixels = pixels;
+        }
+        self.size = size;
+        debug_assert!(self.is_consistent());
+        Ok(())
+    }
+
+    /// Transforms the layer, or all of them. The layer keeps the size of the picture
+    /// even when turned, staying centered, while the whole picture changes its size with it
+    pub fn transform(&mut self, transform: Transform, target: Target, layer: usize) {
+        match target {
+            Target::Picture => {
+                let mut size = self.size;
+                for layer in self.layers.iter_mut() {
+                    let (pixels, transformed) = transform.apply(&layer.pixels, self.size);
+                    layer.pixels = pixels.into_boxed_slice();
+                    size = transformed;
+                }
+                self.size = size;
+            }
+            Target::Layer => {
+                let (pixels, size) = transform.apply(&self.layers[layer].pixels, self.size);
+                let mut turned = Picture { size, layers: vec![Layer { name: String::new(), pixels: pixels.into_boxed_slice() }] };
+                if size != self.size {
+                    turned.resize_canvas(self.size, Anchor::Center);
+                }
+                self.layers[layer].pixels = turned.layers.remove(0).pixels;
+            }
+        }
+        debug_assert!(self.is_consistent());
+    }
+
+    /// Moves every layer onto a canvas of the size, with the old top left corner
+    /// at the offset, which is negative for the pixels cut off at the top or the left
+    fn recanvas(&mut self, size: Dimension, dx: i32, dy: i32) {
+        assert!(size.number() > 0, "the canvas can't be empty");
+        let old = self.size;
+        for layer in self.layers.iter_mut() {
+            let mut pixels = vec![Pixel::default(); size.number()].into_boxed_slice();
+            for pos in size {
+                let (x, y) = (pos.x as i32 - dx, pos.y as i32 - dy);
+                if x >= 0 && y >= 0 && x < old.width as i32 && y < old.height as i32 {
+                    pixels[size.offset(pos)] = layer.pixels[old.offset(Position { x: x as u16, y: y as u16 })];
+                }
+            }
+            layer.pixels = pixels;
+        }
+        self.size = size;
+        debug_assert!(self.is_consistent());
+    }
+}
+
+/// The part of the picture that stays in place when the canvas changes its size
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    pub const ALL: [Anchor; 9] = [
+        Anchor::TopLeft, Anchor::Top, Anchor::TopRight,
+        Anchor::Left, Anchor::Center, Anchor::Right,
+        Anchor::BottomLeft, Anchor::Bottom, Anchor::BottomRight,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Anchor::TopLeft => "top-left",
+            Anchor::Top => "top",
+            Anchor::TopRight => "top-right",
+            Anchor::Left => "left",
+            Anchor::Center => "center",
+            Anchor::Right => "right",
+            Anchor::BottomLeft => "bottom-left",
+            Anchor::Bottom => "bottom",
+            Anchor::BottomRight => "bottom-right",
+        }
+    }
+
+    /// How many halves of the added or removed pixels go to the left and to the top
+    fn halves(self) -> (i32, i32) {
+        let index = Anchor::ALL.iter().position(|&anchor| anchor == self).unwrap() as i32;
+        (index % 3, index / 3)
+    }
+}
+
 impl Debug for Pixel {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         write!(f, "#{:02x?}{:02x?}{:02x?}{:02x?}", self.r, self.g, self.b, self.a)
@@ -0,0 +1,168 @@
+use crate::draw::*;
+use crate::state::{Dimension, Position};
+
+/// Where the text goes within a row
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// The lines boxes are drawn with
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Border {
+    Single,
+    Double,
+    Rounded,
+    Heavy,
+}
+
+impl Border {
+    /// The corners clockwise from the top left one, then the horizontal and the vertical line
+    fn chars(self) -> [char; 6] {
+        match self {
+            Border::Single => ['┌', '┐', '┘', '└', '─', '│'],
+            Border::Double => ['╔', '╗', '╝', '╚', '═', '║'],
+            Border::Rounded => ['╭', '╮', '╯', '╰', '─', '│'],
+            Border::Heavy => ['┏', '┓', '┛', '┗', '━', '┃'],
+        }
+    }
+}
+
+/// A rectangle of the screen to draw into, with the coordinates starting at its top left corner.
+/// Whatever falls outside of it is left out, so a widget can't draw over the others
+/// or past the edge of the screen, however far its coordinates go
+pub struct Surface<'a> {
+    state: &'a mut TerminalState,
+    origin: Position,
+    size: Dimension,
+}
+
+impl<'a> Surface<'a> {
+    /// The part of the screen at `origin`, as much of it as there is
+    pub fn new(state: &'a mut TerminalState, origin: Position, size: Dimension) -> Surface<'a> {
+        let screen = state.size();
+        let size = Dimension {
+            width: size.width.min(screen.width.saturating_sub(origin.x)),
+            height: size.height.min(screen.height.saturating_sub(origin.y)),
+        };
+        Surface { state, origin, size }
+    }
+
+    /// What can be drawn into, which might be less than asked for
+    pub fn size(&self) -> Dimension {
+        self.size
+    }
+
+    /// Where it is on the screen
+    pub fn origin(&self) -> Position {
+        self.origin
+    }
+
+    pub fn color_depth(&self) -> ColorDepth {
+        self.state.color_depth()
+    }
+
+    fn contains(&self, pos: Position) -> bool {
+        pos.x < self.size.width && pos.y < self.size.height
+    }
+
+    /// A part of this one, clipped to it
+    pub fn sub(&mut self, pos: Position, size: Dimension) -> Surface<'_> {
+        let pos = Position { x: pos.x.min(self.size.width), y: pos.y.min(self.size.height) };
+        let size = Dimension {
+            width: size.width.min(self.size.width - pos.x),
+            height: size.height.min(self.size.height - pos.y),
+        };
+        Surface { state: &mut *self.state, origin: self.origin + pos, size }
+    }
+
+    pub fn get(&self, pos: Position) -> Option<&CharCell> {
+        Some(self.state.get(self.origin + pos)).filter(|_| self.contains(pos))
+    }
+
+    /// A wide cell in the last column leaves a blank, as only half of it would fit
+    pub fn put(&mut self, pos: Position, cell: CharCell) {
+        if !self.contains(pos) {
+            return;
+        }
+        let cell = match cell.width {
+            CellWidth::Double if pos.x + 1 == self.size.width => CharCell::new(' ').color(cell.color).attrs(cell.attrs),
+            _ => cell,
+        };
+        self.state.put(self.origin + pos, cell);
+    }
+
+    pub fn text(&mut self, pos: Position, style: impl Into<Style>, text: impl AsRef<str>) {
+        if !self.contains(pos) {
+            return;
+        }
+        self.state.put_text_clipped(self.origin + pos, (self.origin, self.size), style, text);
+    }
+
+    /// Puts the text in the row, aligned within the width. Text that's too long
+    /// is cut off at the right either way
+    pub fn aligned_text(&mut self, y: u16, align: Align, style: impl Into<Style>, text: impl AsRef<str>) {
+        let room = (self.size.width as usize).saturating_sub(text_width(text.as_ref()));
+        let x = match align {
+            Align::Left => 0,
+            Align::Center => room / 2,
+            Align::Right => room,
+        };
+        self.text(Position { x: x as u16, y }, style, text);
+    }
+
+    pub fn fill(&mut self, cell: CharCell) {
+        for pos in self.size {
+            self.put(pos, cell.clone());
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.fill(CharCell::default());
+    }
+
+    /// Darkens what's drawn, so that something drawn over it stands out
+    pub fn dim(&mut self) {
+        self.state.dim((self.origin, self.size));
+    }
+
+    /// Draws a box along the edges, what's inside is left as it is
+    pub fn border(&mut self, border: Border, style: impl Into<Style>) {
+        let Dimension { width, height } = self.size;
+        if width < 2 || height < 2 {
+            return;
+        }
+        let style = style.into();
+        let [top_left, top_right, bottom_right, bottom_left, horizontal, vertical] = border.chars();
+        let line = |surface: &mut Surface, x: u16, y: u16, char: char| {
+            surface.put(Position { x, y }, CharCell::new(char).style(style.clone()));
+        };
+        for x in 1..width - 1 {
+            line(self, x, 0, horizontal);
+            line(self, x, height - 1, horizontal);
+        }
+        for y in 1..height - 1 {
+            line(self, 0, y, vertical);
+            line(self, width - 1, y, vertical);
+        }
+        line(self, 0, 0, top_left);
+        line(self, width - 1, 0, top_right);
+        line(self, width - 1, height - 1, bottom_right);
+        line(self, 0, height - 1, bottom_left);
+    }
+
+    /// Adds the overlay at `overlay.pos` within the surface, but only if all of it fits,
+    /// as there's no cutting up an image. Returns whether it did
+    pub fn overlay(&mut self, mut overlay: Overlay) -> bool {
+        let Dimension { width, height } = overlay.size;
+        let fits = overlay.pos.x as usize + width as usize <= self.size.width as usize
+            && overlay.pos.y as usize + height as usize <= self.size.height as usize;
+        if fits {
+            overlay.pos = self.origin + overlay.pos;
+            self.state.overlay(overlay);
+        }
+        fits
+    }
+}
@@ -5,13 +5,26 @@ use std::thread;
 use std::thread::JoinHandle;
 
 use crossbeam_channel::{Receiver, Sender};
+use libc::c_int;
 use signal_hook::iterator::{Signals, Handle as SignalsHandle};
 use termios::*;
 
+use crate::state::Dimension;
+
 macro_rules! terminal_mixin {
     ($name:ident, drop(&mut $self:ident) { $($code:tt)* }) => {
         impl<T: Terminal> Terminal for $name<T> {}
 
+        impl<T: Terminal> Write for $name<T> {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.peer.write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                self.peer.flush()
+            }
+        }
+
         impl<T: Terminal> Deref for $name<T> {
             type Target = T;
 
@@ -34,7 +47,9 @@ macro_rules! terminal_mixin {
     };
 }
 
-pub trait Terminal where Self: Sized {
+/// A chain of mixins, every one of which writes its escape codes
+/// into the output at the start of the chain, `TerminalBase`
+pub trait Terminal: Write where Self: Sized {
     fn raw(self) -> io::Result<Raw<Self>> {
         let raw = Raw { prev_ios: Termios::from_fd(libc::STDOUT_FILENO)?, peer: self };
         raw.raw_mode()?;
@@ -42,19 +57,19 @@ pub trait Terminal where Self: Sized {
     }
 
     fn alt_screen(self) -> io::Result<AltScreen<Self>> {
-        let alt_screen = AltScreen { peer: self };
+        let mut alt_screen = AltScreen { peer: self };
         alt_screen.switch_to_alt()?;
         Ok(alt_screen)
     }
 
     fn hide_cursor(self) -> io::Result<HideCursor<Self>> {
-        let cursor_control = HideCursor { peer: self };
+        let mut cursor_control = HideCursor { peer: self };
         cursor_control.set_cursor_hidden()?;
         Ok(cursor_control)
     }
 
     fn mouse_input(self) -> io::Result<MouseInput<Self>> {
-        let mouse_input = MouseInput { peer: self };
+        let mut mouse_input = MouseInput { peer: self };
         mouse_input.listen_to_mouse()?;
         Ok(mouse_input)
     }
@@ -71,19 +86,36 @@ pub trait Terminal where Self: Sized {
         Ok(resizes)
     }
 
+    fn termination_signals(self) -> io::Result<TerminationSignals<Self>> {
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        let mut terminations = TerminationSignals {
+            terminations_process: None,
+            tx,
+            rx,
+            peer: self
+        };
+        terminations.listen_to_terminations()?;
+        Ok(terminations)
+    }
+
     fn no_wrap(self) -> io::Result<NoWrap<Self>> {
-        let no_wrap = NoWrap { peer: self };
+        let mut no_wrap = NoWrap { peer: self };
         no_wrap.no_wrap_mode()?;
         Ok(no_wrap)
     }
+
+    fn focus_events(self) -> io::Result<FocusEvents<Self>> {
+        let mut focus_events = FocusEvents { peer: self };
+        focus_events.report_focus()?;
+        Ok(focus_events)
+    }
 }
 
+/// The process stdout, unlike `std::io::Stdout` it can be cloned
 #[derive(Clone)]
-pub struct TerminalBase;
-
-impl Terminal for TerminalBase {}
+pub struct StdoutOutput;
 
-impl Write for TerminalBase {
+impl Write for StdoutOutput {
     fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
         std::io::stdout().write(buf)
     }
@@ -93,6 +125,50 @@ impl Write for TerminalBase {
     }
 }
 
+#[derive(Clone)]
+pub struct TerminalBase<W: Write = StdoutOutput> {
+    output: W,
+}
+
+impl TerminalBase {
+    pub fn stdout() -> TerminalBase {
+        TerminalBase { output: StdoutOutput }
+    }
+}
+
+impl<W: Write> TerminalBase<W> {
+    pub fn new(output: W) -> TerminalBase<W> {
+        TerminalBase { output }
+    }
+
+    /// Asks the terminal whether it knows the given DEC private mode,
+    /// the answer comes in as `Event::ModeReport`
+    pub fn query_mode(&mut self, mode: u16) -> io::Result<()> {
+        write!(self.output, "\x1b[?{}$p", mode)?;
+        self.output.flush()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.output
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.output
+    }
+}
+
+impl<W: Write> Terminal for TerminalBase<W> {}
+
+impl<W: Write> Write for TerminalBase<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.output.flush()
+    }
+}
+
 #[derive(Clone)]
 pub struct Raw<T: Terminal> {
     prev_ios: Termios,
@@ -120,18 +196,14 @@ pub struct AltScreen<T: Terminal> {
 }
 
 impl<T: Terminal> AltScreen<T> {
-    pub fn switch_to_alt(&self) -> io::Result<()> {
-        let stdout = std::io::stdout();
-        let mut handle = stdout.lock();
-        handle.write_all(b"\x1b[?1049h")?;
-        handle.flush()
+    pub fn switch_to_alt(&mut self) -> io::Result<()> {
+        self.write_all(b"\x1b[?1049h")?;
+        self.flush()
     }
 
-    pub fn switch_to_normal(&self) -> io::Result<()> {
-        let stdout = std::io::stdout();
-        let mut handle = stdout.lock();
-        handle.write_all(b"\x1b[?1049l")?;
-        handle.flush()
+    pub fn switch_to_normal(&mut self) -> io::Result<()> {
+        self.write_all(b"\x1b[?1049l")?;
+        self.flush()
     }
 }
 
@@ -143,18 +215,14 @@ pub struct HideCursor<T: Terminal> {
 }
 
 impl<T: Terminal> HideCursor<T> {
-    pub fn set_cursor_hidden(&self) -> io::Result<()> {
-        let stdout = std::io::stdout();
-        let mut handle = stdout.lock();
-        handle.write_all(b"\x1b[?25l")?;
-        handle.flush()
+    pub fn set_cursor_hidden(&mut self) -> io::Result<()> {
+        self.write_all(b"\x1b[?25l")?;
+        self.flush()
     }
 
-    pub fn set_cursor_visible(&self) -> io::Result<()> {
-        let stdout = std::io::stdout();
-        let mut handle = stdout.lock();
-        handle.write_all(b"\x1b[?25h")?;
-        handle.flush()
+    pub fn set_cursor_visible(&mut self) -> io::Result<()> {
+        self.write_all(b"\x1b[?25h")?;
+        self.flush()
     }
 }
 
@@ -166,24 +234,51 @@ pub struct MouseInput<T: Terminal> {
 }
 
 impl<T: Terminal> MouseInput<T> {
-    pub fn listen_to_mouse(&self) -> io::Result<()> {
-        let stdout = std::io::stdout();
-        let mut handle = stdout.lock();
-        handle.write_all(b"\x1b[?1003h\x1b[?1006h")?;
-        handle.flush()
-    }
-
-    pub fn dont_listen_to_mouse(&self) -> io::Result<()> {
-        let stdout = std::io::stdout();
-        let mut handle = stdout.lock();
-        handle.write_all(b"\x1b[?1003l\x1b[?1006l")?;
-        handle.flush()?;
+    pub fn listen_to_mouse(&mut self) -> io::Result<()> {
+        // extended coordinate modes override each other, so they go from
+        // the least to the most preferred one, terminal would use the last one it knows
+        self.write_all(b"\x1b[?1003h\x1b[?1005h\x1b[?1015h\x1b[?1006h")?;
+        self.flush()
+    }
+
+    /// Asks the terminal to report mouse positions in pixels (SGR-pixels, 1016).
+    /// Since those reports look exactly like the SGR ones, this also
+    /// queries the mode back, and the answer comes in as `Event::ModeReport`
+    pub fn report_pixels(&mut self) -> io::Result<()> {
+        self.write_all(b"\x1b[?1016h\x1b[?1016$p")?;
+        self.flush()
+    }
+
+    pub fn report_cells(&mut self) -> io::Result<()> {
+        self.write_all(b"\x1b[?1016l\x1b[?1006h")?;
+        self.flush()
+    }
+
+    pub fn dont_listen_to_mouse(&mut self) -> io::Result<()> {
+        self.write_all(b"\x1b[?1003l\x1b[?1005l\x1b[?1015l\x1b[?1006l\x1b[?1016l")?;
+        self.flush()?;
         std::thread::sleep(std::time::Duration::from_millis(30));
         // ↑ this is needed so that the terminal has time to actually disable mouse input
         Ok(())
     }
 }
 
+/// Size of a single cell in pixels, if the terminal tells it
+pub fn cell_size() -> Option<Dimension> {
+    let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) } != 0 {
+        return None;
+    }
+    if ws.ws_col == 0 || ws.ws_row == 0 {
+        return None;
+    }
+    let cell = Dimension { width: ws.ws_xpixel / ws.ws_col, height: ws.ws_ypixel / ws.ws_row };
+    if cell.width == 0 || cell.height == 0 {
+        return None;
+    }
+    Some(cell)
+}
+
 terminal_mixin!(MouseInput, drop(&mut self) { self.dont_listen_to_mouse().unwrap(); });
 
 pub struct TerminalResizes<T: Terminal> {
@@ -196,39 +291,78 @@ pub struct TerminalResizes<T: Terminal> {
 impl<T: Terminal> TerminalResizes<T> {
     pub fn listen_to_resizes(&mut self) -> io::Result<()> {
         self.dont_listen_to_resizes(); // noop if not listening, need to call anyway if listening
+        let tx = self.tx.clone();
+        self.resizes_process = Some(listen_to_signals(&[signal_hook::consts::SIGWINCH], move |_| tx.send(()).is_ok())?);
+        Ok(())
+    }
 
-        let mut signals = Signals::new(&[signal_hook::consts::SIGWINCH])?;
+    pub fn get_resize_event_receiver(&self) -> &Receiver<()> {
+        &self.rx
+    }
 
-        let tx_bg = self.tx.clone();
-        let signals_handle = signals.handle();
+    pub fn dont_listen_to_resizes(&mut self) {
+        if let Some(process) = self.resizes_process.take() {
+            stop_listening(process);
+        }
+    }
+}
 
-        let join_handle = thread::spawn(move ||
-            while !signals.is_closed() {
-                if signals.wait().count() > 0 {
-                    match tx_bg.send(()) {
-                        Err(_) => break,
-                        _ => {}
-                    }
-                }
-            });
+terminal_mixin!(TerminalResizes, drop(&mut self) { self.dont_listen_to_resizes() });
 
-        self.resizes_process = Some((signals_handle, join_handle));
+/// SIGTERM and SIGHUP, which would otherwise kill the process without restoring the terminal
+pub struct TerminationSignals<T: Terminal> {
+    terminations_process: Option<(SignalsHandle, JoinHandle<()>)>,
+    tx: Sender<c_int>,
+    rx: Receiver<c_int>,
+    peer: T,
+}
+
+impl<T: Terminal> TerminationSignals<T> {
+    pub fn listen_to_terminations(&mut self) -> io::Result<()> {
+        self.dont_listen_to_terminations();
+        let tx = self.tx.clone();
+        let signals = [signal_hook::consts::SIGTERM, signal_hook::consts::SIGHUP];
+        // a second one while the first is being handled is dropped, it's going away already
+        self.terminations_process = Some(listen_to_signals(&signals, move |signal| {
+            let _ = tx.try_send(signal);
+            true
+        })?);
         Ok(())
     }
 
-    pub fn get_resize_event_receiver(&self) -> &Receiver<()> {
+    /// Gets the signal number, it's `SIGHUP` when the terminal went away
+    pub fn get_termination_receiver(&self) -> &Receiver<c_int> {
         &self.rx
     }
 
-    pub fn dont_listen_to_resizes(&mut self) {
-        if let Some((signals, join_handle)) = self.resizes_process.take() {
-            signals.close();
-            join_handle.join().expect("couldn't join on the listening thread");
+    pub fn dont_listen_to_terminations(&mut self) {
+        if let Some(process) = self.terminations_process.take() {
+            stop_listening(process);
         }
     }
 }
 
-terminal_mixin!(TerminalResizes, drop(&mut self) { self.dont_listen_to_resizes() });
+terminal_mixin!(TerminationSignals, drop(&mut self) { self.dont_listen_to_terminations() });
+
+/// Calls `on_signal` on a thread of its own for every one of the signals that comes,
+/// until it returns false or the returned handle is closed
+fn listen_to_signals<F>(signals: &[c_int], on_signal: F) -> io::Result<(SignalsHandle, JoinHandle<()>)>
+    where F: Fn(c_int) -> bool + Send + 'static {
+    let mut signals = Signals::new(signals)?;
+    let handle = signals.handle();
+    let join_handle = thread::spawn(move ||
+        while !signals.is_closed() {
+            if !signals.wait().all(&on_signal) {
+                break;
+            }
+        });
+    Ok((handle, join_handle))
+}
+
+fn stop_listening((signals, join_handle): (SignalsHandle, JoinHandle<()>)) {
+    signals.close();
+    join_handle.join().expect("couldn't join on the listening thread");
+}
 
 #[derive(Clone)]
 pub struct NoWrap<T: Terminal> {
@@ -236,19 +370,34 @@ pub struct NoWrap<T: Terminal> {
 }
 
 impl<T: Terminal> NoWrap<T> {
-    pub fn no_wrap_mode(&self) -> io::Result<()> {
-        let stdout = std::io::stdout();
-        let mut handle = stdout.lock();
-        handle.write_all(b"\x1b[?7l")?;
-        handle.flush()
+    pub fn no_wrap_mode(&mut self) -> io::Result<()> {
+        self.write_all(b"\x1b[?7l")?;
+        self.flush()
     }
 
-    pub fn wrap_mode(&self) -> io::Result<()> {
-        let stdout = std::io::stdout();
-        let mut handle = stdout.lock();
-        handle.write_all(b"\x1b[?7h")?;
-        handle.flush()
+    pub fn wrap_mode(&mut self) -> io::Result<()> {
+        self.write_all(b"\x1b[?7h")?;
+        self.flush()
     }
 }
 
 terminal_mixin!(NoWrap, drop(&mut self) { self.wrap_mode().unwrap() });
+
+#[derive(Clone)]
+pub struct FocusEvents<T: Terminal> {
+    peer: T,
+}
+
+impl<T: Terminal> FocusEvents<T> {
+    pub fn report_focus(&mut self) -> io::Result<()> {
+        self.write_all(b"\x1b[?1004h")?;
+        self.flush()
+    }
+
+    pub fn dont_report_focus(&mut self) -> io::Result<()> {
+        self.write_all(b"\x1b[?1004l")?;
+        self.flush()
+    }
+}
+
+terminal_mixin!(FocusEvents, drop(&mut self) { self.dont_report_focus().unwrap() });
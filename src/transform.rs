@@ -0,0 +1,117 @@
+use crate::state::{Dimension, Pixel, Position};
+
+/// Moves the pixels around without changing any of them
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Transform {
+    /// Mirrors it left to right
+    FlipHorizontal,
+    /// Mirrors it top to bottom
+    FlipVertical,
+    /// Clockwise, in degrees. The quarter turns are exact, any other angle goes through RotSprite
+    Rotate(f64),
+    /// Slants it by how much every row moves right per row down and every column moves down per column right
+    Shear(f64, f64),
+}
+
+/// What gets transformed. There's no selection to transform the part of yet
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Target {
+    /// The current layer, in place, so it keeps the size of the picture
+    Layer,
+    /// All of the layers, the picture changing its size with the quarter turns
+    Picture,
+}
+
+impl Transform {
+    /// The pixels transformed and their size, which only changes with the quarter turns.
+    /// Everything else keeps the size, cutting off what's moved out of it, as the canvas
+    /// can always be resized first to make room
+    pub fn apply(self, pixels: &[Pixel], size: Dimension) -> (Vec<Pixel>, Dimension) {
+        match self {
+            Transform::FlipHorizontal => (map(size, |Position { x, y }| Some(pixels[size.offset(Position { x: size.width - 1 - x, y })])), size),
+            Transform::FlipVertical => (map(size, |Position { x, y }| Some(pixels[size.offset(Position { x, y: size.height - 1 - y })])), size),
+            Transform::Rotate(degrees) => {
+                let degrees = degrees.rem_euclid(360.0);
+                if degrees % 90.0 == 0.0 {
+                    let mut result = (pixels.to_vec(), size);
+                    for _ in 0..(degrees / 90.0) as u8 {
+                        result = quarter_turn(&result.0, result.1);
+                    }
+                    result
+                } else {
+                    (rotsprite(pixels, size, degrees), size)
+                }
+            }
+            Transform::Shear(x, y) => {
+                let (center_x, center_y) = (size.width as f64 / 2.0, size.height as f64 / 2.0);
+                let sheared = map(size, |pos| {
+                    let (px, py) = (pos.x as f64 + 0.5, pos.y as f64 + 0.5);
+                    sample(pixels, size, px - (py - center_y) * x, py - (px - center_x) * y)
+                });
+                (sheared, size)
+            }
+        }
+    }
+}
+
+fn map(size: Dimension, pixel: impl Fn(Position) -> Option<Pixel>) -> Vec<Pixel> {
+    size.into_iter().map(|pos| pixel(pos).unwrap_or_default()).collect()
+}
+
+/// The pixel at the point, `None` if that's outside
+fn sample(pixels: &[Pixel], size: Dimension, x: f64, y: f64) -> Option<Pixel> {
+    let inside = x >= 0.0 && y >= 0.0 && x < size.width as f64 && y < size.height as f64;
+    if !inside {
+        return None;
+    }
+    Some(pixels[size.offset(Position { x: x as u16, y: y as u16 })])
+}
+
+/// Turns it clockwise by 90 degrees, the width and the height swapping places
+fn quarter_turn(pixels: &[Pixel], size: Dimension) -> (Vec<Pixel>, Dimension) {
+    let turned = Dimension { width: size.height, height: size.width };
+    (map(turned, |Position { x, y }| Some(pixels[size.offset(Position { x: y, y: size.height - 1 - x })])), turned)
+}
+
+/// RotSprite: the pixels are scaled up 8x with Scale2x, which turns the steps of the lines
+/// into slopes, then the pixels of the result are picked from that by nearest neighbour.
+/// That keeps the lines a pixel thick and the colors as they were, unlike any filtering would
+fn rotsprite(pixels: &[Pixel], size: Dimension, degrees: f64) -> Vec<Pixel> {
+    const TIMES: u32 = 3;
+    let scale = (1 << TIMES) as f64;
+    let (sin, cos) = (-degrees.to_radians()).sin_cos();
+    let (center_x, center_y) = (size.width as f64 / 2.0, size.height as f64 / 2.0);
+    map(size, |pos| {
+        // back from where the pixel ended up to where it was, from its center
+        let (dx, dy) = (pos.x as f64 + 0.5 - center_x, pos.y as f64 + 0.5 - center_y);
+        let (x, y) = (center_x + dx * cos - dy * sin, center_y + dx * sin + dy * cos);
+        let inside = x >= 0.0 && y >= 0.0 && x < size.width as f64 && y < size.height as f64;
+        inside.then(|| scale2x_at(pixels, size, TIMES, (x * scale) as u32, (y * scale) as u32))
+    })
+}
+
+/// The pixel of the pixels scaled up by Scale2x that many times, worked out from the ones
+/// around it. Only the pixels picked are, all of them would be 64 times as many for RotSprite,
+/// more than the sizes even go up to
+fn scale2x_at(pixels: &[Pixel], size: Dimension, times: u32, x: u32, y: u32) -> Pixel {
+    if times == 0 {
+        return pixels[size.offset(Position { x: x as u16, y: y as u16 })];
+    }
+    // the pixel it came from and its neighbours, the ones over the edges being the closest ones inside
+    let (width, height) = ((size.width as i64) << (times - 1), (size.height as i64) << (times - 1));
+    let around = |dx: i64, dy: i64| {
+        let (x, y) = ((x as i64 / 2 + dx).clamp(0, width - 1), (y as i64 / 2 + dy).clamp(0, height - 1));
+        scale2x_at(pixels, size, times - 1, x as u32, y as u32)
+    };
+    let [b, d, e, f, h] = [around(0, -1), around(-1, 0), around(0, 0), around(1, 0), around(0, 1)];
+    if b == h || d == f {
+        return e;
+    }
+    match (x % 2, y % 2) {
+        (0, 0) if d == b => d,
+        (1, 0) if b == f => f,
+        (0, 1) if d == h => d,
+        (1, 1) if h == f => f,
+        _ => e,
+    }
+}
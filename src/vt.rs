@@ -0,0 +1,391 @@
+use std::io;
+use std::io::Write;
+
+use unicode_width::UnicodeWidthChar;
+
+use crate::draw::{xterm_color, Attributes, CellWidth, Color, Underline};
+use crate::state::{Dimension, Position};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScreenCell {
+    pub char: char,
+    /// The zero width chars that came after it
+    pub marks: String,
+    pub width: CellWidth,
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub attrs: Attributes,
+}
+
+impl Default for ScreenCell {
+    fn default() -> Self {
+        ScreenCell { char: ' ', marks: String::new(), width: CellWidth::Single, fg: None, bg: None, attrs: Attributes::default() }
+    }
+}
+
+/// A headless terminal, interpreting the escape sequences written into it
+/// into a grid of cells, so that what we draw could be checked without a real terminal.
+///
+/// It only knows what we actually emit: cursor positioning and moving forward,
+/// SGR colors, erasing of the screen, lines and chars. Images are kept aside in `graphics`.
+/// Mode switches are accepted and ignored, anything else ends up in `unrecognized`.
+/// Wrapping is off, like it is for the editor, so the last column is overwritten.
+/// Chars take as many columns as `wcwidth` says, the zero width ones go with the char before
+pub struct VirtualTerminal {
+    size: Dimension,
+    cells: Box<[ScreenCell]>,
+    cursor: Position,
+    fg: Option<Color>,
+    bg: Option<Color>,
+    attrs: Attributes,
+    /// Where the last char went, for the zero width ones to join it
+    last: Option<usize>,
+    /// An incomplete escape sequence or UTF-8 char left from the previous write
+    pending: Vec<u8>,
+    /// Graphics (APC and DCS strings, so kitty and sixel images) along with where they were written
+    pub graphics: Vec<(Position, Vec<u8>)>,
+    pub unrecognized: Vec<Vec<u8>>,
+}
+
+impl VirtualTerminal {
+    pub fn new(size: Dimension) -> VirtualTerminal {
+        VirtualTerminal {
+            size,
+            cells: vec![ScreenCell::default(); size.number()].into_boxed_slice(),
+            cursor: Position::default(),
+            fg: None,
+            bg: None,
+            attrs: Attributes::default(),
+            last: None,
+            pending: vec![],
+            graphics: vec![],
+            unrecognized: vec![],
+        }
+    }
+
+    pub fn size(&self) -> Dimension {
+        self.size
+    }
+
+    pub fn cursor(&self) -> Position {
+        self.cursor
+    }
+
+    pub fn cell(&self, pos: Position) -> &ScreenCell {
+        &self.cells[self.size.offset(pos)]
+    }
+
+    /// The characters on the screen, one line per row, without the trailing spaces
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for y in 0..self.size.height {
+            let mut row = String::new();
+            for x in 0..self.size.width {
+                let cell = self.cell(Position { x, y });
+                if cell.width != CellWidth::Continuation {
+                    row.push(cell.char);
+                    row.push_str(&cell.marks);
+                }
+            }
+            text.push_str(row.trim_end());
+            text.push('\n');
+        }
+        text
+    }
+
+    /// The colors on the screen, as a grid of symbols standing for the fg/bg pairs
+    /// (along with the attributes), followed by the legend. Symbols are given out
+    /// in the order the pairs first appear, `.` is the default for all of them.
+    /// The foreground of a space can't be seen, so it doesn't count,
+    /// as it often depends on how the space got there. The same goes for the attributes
+    /// other than the reverse video and underline
+    pub fn colors(&self) -> String {
+        const SYMBOLS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+        // gradients easily run out of the alphanumerics, so go on with the latin-1 letters
+        let symbol = |index: usize| match SYMBOLS.get(index) {
+            Some(&symbol) => symbol as char,
+            None => std::char::from_u32(0xc0 + (index - SYMBOLS.len()) as u32).unwrap_or('?'),
+        };
+        let mut pairs: Vec<(Option<Color>, Option<Color>, Attributes)> = vec![];
+        let mut grid = String::new();
+        for y in 0..self.size.height {
+            for x in 0..self.size.width {
+                let cell = self.cell(Position { x, y });
+                let space = cell.char == ' ' && cell.width != CellWidth::Continuation;
+                let fg = if space { None } else { cell.fg.clone() };
+                let attrs = match space {
+                    // and neither can most of the attributes
+                    true => Attributes { reverse: cell.attrs.reverse, underline: cell.attrs.underline, ..Attributes::default() },
+                    false => cell.attrs.clone(),
+                };
+                if fg.is_none() && cell.bg.is_none() && attrs == Attributes::default() {
+                    grid.push('.');
+                    continue;
+                }
+                let pair = (fg, cell.bg.clone(), attrs);
+                let index = match pairs.iter().position(|p| *p == pair) {
+                    Some(index) => index,
+                    None => {
+                        pairs.push(pair);
+                        pairs.len() - 1
+                    }
+                };
+                grid.push(symbol(index));
+            }
+            grid.push('\n');
+        }
+        let hex = |color: &Option<Color>| match color {
+            Some(Color { r, g, b }) => format!("#{:02x}{:02x}{:02x}", r, g, b),
+            None => "default".into(),
+        };
+        for (index, (fg, bg, attrs)) in pairs.iter().enumerate() {
+            grid.push_str(&format!("{}: fg {} bg {}", symbol(index), hex(fg), hex(bg)));
+            let flags = [(attrs.bold, "bold"), (attrs.dim, "dim"), (attrs.italic, "italic"), (attrs.reverse, "reverse")];
+            for (_, name) in flags.iter().filter(|(set, _)| *set) {
+                grid.push(' ');
+                grid.push_str(name);
+            }
+            if let Some(underline) = attrs.underline {
+                grid.push_str(&format!(" underline {:?}", underline).to_lowercase());
+            }
+            if attrs.underline_color.is_some() {
+                grid.push_str(&format!(" underline color {}", hex(&attrs.underline_color)));
+            }
+            grid.push('\n');
+        }
+        grid
+    }
+
+    fn put(&mut self, char: char) {
+        let width = char.width().unwrap_or(1);
+        if width == 0 {
+            if let Some(last) = self.last {
+                self.cells[last].marks.push(char);
+            }
+            return;
+        }
+        // no wrapping, so a wide char doesn't fit in the last column at all
+        if self.cursor.x as usize + width > self.size.width as usize || self.cursor.y >= self.size.height {
+            return;
+        }
+        let offset = self.size.offset(self.cursor);
+        let cell = ScreenCell {
+            char,
+            marks: String::new(),
+            width: CellWidth::Single,
+            fg: self.fg.clone(),
+            bg: self.bg.clone(),
+            attrs: self.attrs.clone(),
+        };
+        self.split(offset);
+        if width == 2 {
+            self.split(offset + 1);
+            self.cells[offset + 1] = ScreenCell { width: CellWidth::Continuation, ..cell.clone() };
+            self.cells[offset] = ScreenCell { width: CellWidth::Double, ..cell };
+        } else {
+            self.cells[offset] = cell;
+        }
+        self.last = Some(offset);
+        self.cursor.x = (self.cursor.x + width as u16).min(self.size.width - 1);
+    }
+
+    /// Overwriting either half of a wide char blanks the other one, like the real terminals do
+    fn split(&mut self, offset: usize) {
+        let other = match self.cells[offset].width {
+            CellWidth::Single => return,
+            CellWidth::Double => offset + 1,
+            CellWidth::Continuation => offset - 1,
+        };
+        let cell = &mut self.cells[other];
+        *cell = ScreenCell { fg: cell.fg.clone(), bg: cell.bg.clone(), ..ScreenCell::default() };
+    }
+
+    fn erase(&mut self, from: Position, count: usize) {
+        let start = self.size.offset(from);
+        let end = (start + count).min(self.cells.len());
+        if start < end {
+            self.split(start);
+            self.split(end - 1);
+        }
+        for cell in &mut self.cells[start..end] {
+            // erasing fills with the current background, like the real terminals do
+            *cell = ScreenCell { bg: self.bg.clone(), ..ScreenCell::default() };
+        }
+    }
+
+    /// `params` are the groups of colon separated sub-parameters
+    fn sgr(&mut self, params: &[Vec<u16>]) {
+        let mut params = params.iter();
+        while let Some(group) = params.next() {
+            let param = group[0];
+            match param {
+                0 => {
+                    self.fg = None;
+                    self.bg = None;
+                    self.attrs = Attributes::default();
+                }
+                38 | 48 | 58 => {
+                    // either all in the group with colons, or in the following ones
+                    let from_group = group.len() > 1;
+                    let mut rest = group[1..].iter().copied();
+                    let mut next = || match from_group {
+                        true => rest.next().unwrap_or(0),
+                        false => params.next().map_or(0, |g| g[0]),
+                    };
+                    let color = match next() {
+                        2 => Some(Color::new(next() as u8, next() as u8, next() as u8)),
+                        5 => Some(xterm_color(next() as u8)),
+                        _ => None,
+                    };
+                    match param {
+                        38 => self.fg = color,
+                        48 => self.bg = color,
+                        _ => self.attrs.underline_color = color,
+                    }
+                }
+                1 => self.attrs.bold = true,
+                2 => self.attrs.dim = true,
+                3 => self.attrs.italic = true,
+                4 => {
+                    self.attrs.underline = match group.get(1).copied().unwrap_or(1) {
+                        0 => None,
+                        2 => Some(Underline::Double),
+                        3 => Some(Underline::Curly),
+                        4 => Some(Underline::Dotted),
+                        5 => Some(Underline::Dashed),
+                        _ => Some(Underline::Single),
+                    }
+                }
+                7 => self.attrs.reverse = true,
+                22 => {
+                    self.attrs.bold = false;
+                    self.attrs.dim = false;
+                }
+                23 => self.attrs.italic = false,
+                24 => self.attrs.underline = None,
+                27 => self.attrs.reverse = false,
+                30..=37 => self.fg = Some(xterm_color(param as u8 - 30)),
+                90..=97 => self.fg = Some(xterm_color(param as u8 - 90 + 8)),
+                40..=47 => self.bg = Some(xterm_color(param as u8 - 40)),
+                100..=107 => self.bg = Some(xterm_color(param as u8 - 100 + 8)),
+                39 => self.fg = None,
+                49 => self.bg = None,
+                59 => self.attrs.underline_color = None,
+                _ => {}
+            }
+        }
+    }
+
+    /// Applies a complete CSI sequence, `bytes` being everything after `\x1b[`
+    fn csi(&mut self, bytes: &[u8]) {
+        let (&last, body) = bytes.split_last().unwrap();
+        if last != b'm' {
+            self.last = None;
+        }
+        if body.first() == Some(&b'?') {
+            // private modes
+            if last == b'h' || last == b'l' || last == b'p' {
+                return;
+            }
+        }
+        let number = |p: &str| if p.is_empty() { Some(0) } else { p.parse().ok() };
+        let params: Option<Vec<Vec<u16>>> = std::str::from_utf8(body).ok().and_then(|body| {
+            if body.is_empty() {
+                return Some(vec![]);
+            }
+            body.split(';').map(|p| p.split(':').map(number).collect()).collect()
+        });
+        let params = match params {
+            Some(params) => params,
+            None => return self.unrecognized.push(bytes.to_vec()),
+        };
+        let param = |index: usize, default: u16| params.get(index).map(|g| g[0]).filter(|&p| p != 0).unwrap_or(default);
+        let Dimension { width, height } = self.size;
+        match last {
+            b'H' => {
+                self.cursor = Position {
+                    x: (param(1, 1) - 1).min(width - 1),
+                    y: (param(0, 1) - 1).min(height - 1),
+                };
+            }
+            b'm' if params.is_empty() => self.sgr(&[vec![0]]),
+            b'm' => self.sgr(&params),
+            b'C' => self.cursor.x = (self.cursor.x + param(0, 1)).min(width - 1),
+            b'X' => self.erase(self.cursor, (param(0, 1) as usize).min((width - self.cursor.x) as usize)),
+            b'K' => match param(0, 0) {
+                0 => self.erase(self.cursor, (width - self.cursor.x) as usize),
+                _ => self.unrecognized.push(bytes.to_vec()),
+            },
+            b'J' => match param(0, 0) {
+                2 => self.erase(Position::default(), self.cells.len()),
+                // scrollback, which we don't have
+                3 => {}
+                _ => self.unrecognized.push(bytes.to_vec()),
+            },
+            _ => self.unrecognized.push(bytes.to_vec()),
+        }
+    }
+
+    /// Consumes a single sequence or char from the start of `bytes`,
+    /// returns how many bytes it took, or `None` if it's incomplete
+    fn consume(&mut self, bytes: &[u8]) -> Option<usize> {
+        match bytes[0] {
+            b'\x1b' if matches!(bytes.get(1), Some(b'_') | Some(b'P')) => {
+                let end = bytes.windows(2).position(|w| w == b"\x1b\\")?;
+                self.graphics.push((self.cursor, bytes[..end + 2].to_vec()));
+                Some(end + 2)
+            }
+            b'\x1b' => {
+                if *bytes.get(1)? != b'[' {
+                    self.unrecognized.push(bytes[..2].to_vec());
+                    return Some(2);
+                }
+                let end = bytes[2..].iter().position(|b| (0x40..=0x7e).contains(b))?;
+                self.csi(&bytes[2..end + 3]);
+                Some(end + 3)
+            }
+            b'\r' => {
+                self.cursor.x = 0;
+                Some(1)
+            }
+            b'\n' => {
+                self.cursor.y = (self.cursor.y + 1).min(self.size.height - 1);
+                Some(1)
+            }
+            first => {
+                let len = match first {
+                    0x00..=0x7f => 1,
+                    0xc0..=0xdf => 2,
+                    0xe0..=0xef => 3,
+                    _ => 4,
+                };
+                let char = std::str::from_utf8(bytes.get(..len)?).ok().and_then(|s| s.chars().next());
+                match char {
+                    Some(char) if !char.is_control() => self.put(char),
+                    _ => self.unrecognized.push(bytes[..len].to_vec()),
+                }
+                Some(len)
+            }
+        }
+    }
+}
+
+impl Write for VirtualTerminal {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut bytes = std::mem::take(&mut self.pending);
+        bytes.extend_from_slice(buf);
+        let mut start = 0;
+        while start < bytes.len() {
+            match self.consume(&bytes[start..]) {
+                Some(len) => start += len,
+                None => break,
+            }
+        }
+        self.pending = bytes.split_off(start);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+use entropic::gui::*;
+use entropic::history::History;
+use entropic::state::*;
+
+mod common;
+use common::*;
+
+/// A picture with every pixel numbered in its red, starting from 1, so none are transparent
+fn numbered(width: u16, height: u16) -> Picture {
+    let size = Dimension { width, height };
+    let pixels = (0..size.number()).map(|i| Pixel { r: i as u8 + 1, g: 0, b: 0, a: 255 }).collect();
+    Picture { size, layers: vec![Layer { name: "Background".into(), pixels }] }
+}
+
+/// The reds of the pixels row by row, 0 for the transparent ones
+fn reds(picture: &Picture) -> Vec<Vec<u8>> {
+    picture.layers[0].pixels.chunks(picture.size.width as usize)
+        .map(|row| row.iter().map(|pixel| if pixel.a == 0 { 0 } else { pixel.r }).collect())
+        .collect()
+}
+
+#[test]
+fn resizing_around_anchors() {
+    let mut picture = numbered(2, 2);
+    picture.resize_canvas(Dimension { width: 4, height: 3 }, Anchor::Center);
+    assert_eq!(reds(&picture), vec![vec![0, 1, 2, 0], vec![0, 3, 4, 0], vec![0, 0, 0, 0]]);
+
+    let mut picture = numbered(2, 2);
+    picture.resize_canvas(Dimension { width: 3, height: 3 }, Anchor::BottomRight);
+    assert_eq!(reds(&picture), vec![vec![0, 0, 0], vec![0, 1, 2], vec![0, 3, 4]]);
+
+    let mut picture = numbered(3, 3);
+    picture.resize_canvas(Dimension { width: 1, height: 2 }, Anchor::Top);
+    assert_eq!(reds(&picture), vec![vec![2], vec![5]]);
+    assert!(picture.is_consistent());
+}
+
+#[test]
+fn cropping_and_trimming() {
+    let mut picture = numbered(3, 3);
+    picture.crop(Position { x: 1, y: 1 }, Dimension { width: 2, height: 1 }).unwrap();
+    assert_eq!(reds(&picture), vec![vec![5, 6]]);
+    assert_eq!(picture.crop(Position { x: 1, y: 0 }, Dimension { width: 2, height: 1 }), Err("2x1 at 1,0 is not inside of the 2x1 picture".into()));
+    assert_eq!(picture.size, Dimension { width: 2, height: 1 });
+
+    let mut picture = numbered(2, 2);
+    picture.resize_canvas(Dimension { width: 5, height: 4 }, Anchor::Right);
+    assert_eq!(picture.bounds(), Some((Position { x: 3, y: 1 }, Dimension { width: 2, height: 2 })));
+    picture.layers[0].pixels.iter_mut().for_each(|pixel| pixel.a = 0);
+    assert_eq!(picture.bounds(), None);
+}
+
+#[test]
+fn history() {
+    let mut history = History::default();
+    let mut picture = numbered(1, 1);
+    assert!(!history.undo(&mut picture));
+    for red in 2..=History::LIMIT as u8 + 5 {
+        history.record(&picture);
+        picture.layers[0].pixels[0].r = red;
+    }
+    let mut undone = 0;
+    while history.undo(&mut picture) {
+        undone += 1;
+    }
+    assert_eq!(undone, History::LIMIT);
+    // the first four are forgotten
+    assert_eq!(picture.layers[0].pixels[0].r, 5);
+    assert!(history.redo(&mut picture));
+    assert_eq!(picture.layers[0].pixels[0].r, 6);
+
+    // a new change forgets what was undone
+    history.record(&picture);
+    assert!(!history.redo(&mut picture));
+
+    // the big pictures are forgotten sooner, 64 MiB each
+    let mut history = History::default();
+    let mut picture = blank(Dimension { width: 4096, height: 4096 }, &["Background"]);
+    for _ in 0..6 {
+        history.record(&picture);
+    }
+    let mut undone = 0;
+    while history.undo(&mut picture) {
+        undone += 1;
+    }
+    assert_eq!(undone, History::MAX_BYTES / (64 * 1024 * 1024));
+}
+
+#[test]
+fn undoing_canvas_changes() {
+    let mut gui = Gui::new(Dimension { width: 80, height: 30 }, numbered(4, 4));
+    type_in(&mut gui, ":resize 6 6 center\r:trim\r");
+    assert!(gui.state.picture == numbered(4, 4));
+    type_in(&mut gui, ":crop 1 1 3 2\r");
+    // it asks first, unlike the trim which only cuts off what's transparent
+    assert_eq!(gui.state.picture.size, Dimension { width: 4, height: 4 });
+    type_in(&mut gui, "y");
+    assert_eq!(reds(&gui.state.picture), vec![vec![6, 7, 8], vec![10, 11, 12]]);
+    assert_eq!(gui.state.current_layer, 0);
+
+    // back to before the crop, the trim and the resize, then forward again
+    type_in(&mut gui, "\x1a\x1a");
+    assert_eq!(gui.state.picture.size, Dimension { width: 6, height: 6 });
+    type_in(&mut gui, "\x1a");
+    assert!(gui.state.picture == numbered(4, 4));
+    type_in(&mut gui, "\x19\x19\x19");
+    assert_eq!(reds(&gui.state.picture), vec![vec![6, 7, 8], vec![10, 11, 12]]);
+
+    type_in(&mut gui, ":scale 2x\r");
+    assert_eq!(reds(&gui.state.picture)[3], vec![10, 10, 11, 11, 12, 12]);
+    type_in(&mut gui, "\x1a");
+    assert_eq!(gui.state.picture.size, Dimension { width: 3, height: 2 });
+}
+
+#[test]
+fn strokes_that_paint_nothing() {
+    let mut gui = Gui::new(Dimension { width: 80, height: 30 }, numbered(4, 4));
+    // on the canvas, but below the picture
+    run(&mut gui, b"\x1b[<0;5;20M\x1b[<0;5;20m");
+    assert!(!gui.state.modified);
+    type_in(&mut gui, "\x1a");
+    assert!(gui.state.picture == numbered(4, 4));
+
+    // one that comes in from there is still undone at once
+    run(&mut gui, b"\x1b[<0;5;20M\x1b[<32;5;4M\x1b[<32;7;4M\x1b[<0;7;4m");
+    assert!(gui.state.modified);
+    assert_eq!(reds(&gui.state.picture)[2], vec![9, 255, 255, 12]);
+    type_in(&mut gui, "\x1a");
+    assert!(gui.state.picture == numbered(4, 4));
+}
@@ -0,0 +1,99 @@
+use std::io::Write;
+
+use entropic::draw::*;
+use entropic::state::*;
+use entropic::vt::*;
+
+#[test]
+fn detection() {
+    assert_eq!(ColorDepth::detect(Some("truecolor"), Some("screen")), ColorDepth::TrueColor);
+    assert_eq!(ColorDepth::detect(Some("24bit"), None), ColorDepth::TrueColor);
+    assert_eq!(ColorDepth::detect(None, Some("xterm-direct")), ColorDepth::TrueColor);
+    assert_eq!(ColorDepth::detect(None, Some("tmux-256color")), ColorDepth::Indexed256);
+    assert_eq!(ColorDepth::detect(Some("yes"), Some("xterm-256color")), ColorDepth::Indexed256);
+    assert_eq!(ColorDepth::detect(None, Some("linux")), ColorDepth::Ansi16);
+    assert_eq!(ColorDepth::detect(None, Some("screen")), ColorDepth::Ansi16);
+    assert_eq!(ColorDepth::detect(None, Some("vt100")), ColorDepth::Mono);
+    assert_eq!(ColorDepth::detect(None, Some("dumb")), ColorDepth::Mono);
+    assert_eq!(ColorDepth::detect(None, None), ColorDepth::Mono);
+
+    assert_eq!("256".parse(), Ok(ColorDepth::Indexed256));
+    assert!("lots".parse::<ColorDepth>().is_err());
+}
+
+#[test]
+fn quantization() {
+    assert_eq!(Color::new(255, 0, 0).to_256(), 196);
+    assert_eq!(Color::new(0, 0, 0).to_256(), 16);
+    assert_eq!(Color::gray(128).to_256(), 244);
+    assert_eq!(Color::new(100, 140, 210).to_256(), 68);
+    assert_eq!(Color::new(250, 10, 5).to_16(), 9);
+    assert_eq!(Color::new(30, 30, 200).to_16(), 4);
+    assert_eq!(Color::gray(200).quantize(ColorDepth::Mono), Color::gray(255));
+    assert_eq!(Color::gray(50).quantize(ColorDepth::Mono), Color::gray(0));
+
+    for index in 16..=255 {
+        assert_eq!(xterm_color(index).to_256(), index);
+    }
+}
+
+#[test]
+fn dithering() {
+    let gray = Color::gray(128);
+    let area = Dimension { width: 4, height: 4 };
+    let white = area.into_iter().filter(|&pos| gray.dither(ColorDepth::Mono, pos) == Color::gray(255)).count();
+    assert_eq!(white, 8);
+    assert!(area.into_iter().all(|pos| gray.dither(ColorDepth::TrueColor, pos) == gray));
+}
+
+fn render(depth: ColorDepth, cell: CharCell) -> (String, ScreenCell) {
+    let size = Dimension { width: 2, height: 1 };
+    let mut state = TerminalState::new(size);
+    state.set_color_depth(depth);
+    state.put(Position { x: 0, y: 0 }, cell);
+    let mut out = Vec::new();
+    state.draw(size, &mut out).unwrap();
+
+    let mut screen = VirtualTerminal::new(size);
+    screen.write_all(&out).unwrap();
+    (String::from_utf8(out).unwrap(), screen.cell(Position { x: 0, y: 0 }).clone())
+}
+
+#[test]
+fn fallback_output() {
+    let cell = CharCell::new('x').fg(Color::new(255, 0, 0)).bg(Color::new(0, 0, 238));
+
+    let (out, screen) = render(ColorDepth::Indexed256, cell.clone());
+    assert!(out.contains("\x1b[48;5;21;38;5;196mx"));
+    assert_eq!(screen.fg, Some(Color::new(255, 0, 0)));
+    assert_eq!(screen.bg, Some(Color::new(0, 0, 255)));
+
+    let (out, screen) = render(ColorDepth::Ansi16, cell.clone());
+    assert!(out.contains("\x1b[44;91mx"));
+    assert_eq!(screen.bg, Some(Color::new(0, 0, 238)));
+
+    let (out, _) = render(ColorDepth::Mono, cell.bg(Color::gray(255)));
+    assert!(out.contains("\x1b[7mx"));
+}
+
+#[test]
+fn default_and_transparent() {
+    let size = Dimension { width: 2, height: 1 };
+    let mut state = TerminalState::new(size);
+    state.put(Position { x: 0, y: 0 }, CharCell::new(' ').bg(Color::new(0, 0, 255)));
+    state.put(Position { x: 1, y: 0 }, CharCell::new(' ').bg(Color::new(0, 0, 255)));
+    // the text over it keeps the background, and the default one goes back to the terminal's
+    state.put(Position { x: 0, y: 0 }, CharCell::new('a').color(CellColor::transparent().fg(Color::gray(255))));
+    state.put(Position { x: 1, y: 0 }, CharCell::new('b').fg(Color::gray(255)));
+    assert_eq!(state.get(Position { x: 0, y: 0 }).color, CellColor::none().bg(Color::new(0, 0, 255)).fg(Color::gray(255)));
+    assert_eq!(state.get(Position { x: 1, y: 0 }).color.bg, Ink::Default);
+
+    let mut out = Vec::new();
+    state.set_color_depth(ColorDepth::Indexed256);
+    state.draw(size, &mut out).unwrap();
+    let mut screen = VirtualTerminal::new(size);
+    screen.write_all(&out).unwrap();
+    assert_eq!(screen.cell(Position { x: 0, y: 0 }).bg, Some(Color::new(0, 0, 255)));
+    assert_eq!(screen.cell(Position { x: 1, y: 0 }).bg, None);
+    assert!(String::from_utf8(out).unwrap().contains("49"));
+}
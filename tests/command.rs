@@ -0,0 +1,121 @@
+use std::fs;
+
+use entropic::command::*;
+use entropic::draw::Color;
+use entropic::gui::*;
+use entropic::scale::Scaler;
+use entropic::state::*;
+use entropic::vt::*;
+
+mod common;
+use common::*;
+
+#[test]
+fn parsing() {
+    assert_eq!(parse("resize 64 48"), Ok(Action::Resize(Dimension { width: 64, height: 48 }, Anchor::TopLeft)));
+    assert_eq!(parse("resize 64 48 bottom-right"), Ok(Action::Resize(Dimension { width: 64, height: 48 }, Anchor::BottomRight)));
+    assert_eq!(parse("crop 0 2 8 4"), Ok(Action::Crop(Position { x: 0, y: 2 }, Dimension { width: 8, height: 4 })));
+    assert_eq!(parse("  layer new  Dark Shadows"), Ok(Action::NewLayer("Dark Shadows".into())));
+    assert_eq!(parse("export out.png --scale 4"), Ok(Action::Export("out.png".into(), 4, Scaler::Nearest)));
+    assert_eq!(parse("export out.png"), Ok(Action::Export("out.png".into(), 1, Scaler::Nearest)));
+    assert_eq!(parse("export --scaler scale2x out.png --scale 8"), Ok(Action::Export("out.png".into(), 8, Scaler::Scale2x)));
+    assert_eq!(parse("scale 0.5x"), Ok(Action::Scale(0.5, Scaler::Nearest)));
+    assert_eq!(parse("scale 3 scale3x"), Ok(Action::Scale(3.0, Scaler::Scale3x)));
+    assert_eq!(parse("fill #ff000080"), Ok(Action::Fill(Pixel { r: 255, g: 0, b: 0, a: 128 })));
+    assert_eq!(parse("swap-colors"), Ok(Action::SwapColors));
+    assert_eq!(parse("save"), Ok(Action::Save));
+    assert_eq!(parse("save art.entropic"), Ok(Action::SaveAs("art.entropic".into())));
+
+    assert_eq!(parse(""), Err("no command given".into()));
+    assert_eq!(parse("frobnicate"), Err("unknown command: frobnicate".into()));
+    assert_eq!(parse("resize 64"), Err("usage: resize <width> <height> [<anchor>]".into()));
+    assert_eq!(parse("resize 64 0"), Err("not a size: 0".into()));
+    assert_eq!(parse("resize 64 48 middle"), Err("not an anchor: middle".into()));
+    assert_eq!(parse("crop -1 0 8 8"), Err("not a coordinate: -1".into()));
+    assert_eq!(parse("export out.png --scale"), Err("usage: export [<file.png> [--scale <n>] [--scaler <name>]]".into()));
+    assert_eq!(parse("export out.png --scaler hq4x"), Err("not a scaler: hq4x".into()));
+    assert_eq!(parse("scale -2"), Err("not a factor: -2".into()));
+    assert_eq!(parse("fill red"), Err("not a color: red".into()));
+    assert_eq!(parse("quit now"), Err("quit takes no arguments".into()));
+}
+
+#[test]
+fn completion() {
+    assert_eq!(complete("re"), vec!["redo", "redraw", "resize "]);
+    assert_eq!(complete("layer "), vec!["layer new "]);
+    assert_eq!(complete("resize 8 8 bottom"), vec!["resize 8 8 bottom", "resize 8 8 bottom-left", "resize 8 8 bottom-right"]);
+    assert_eq!(complete("palette lo"), vec!["palette load "]);
+    assert_eq!(complete("export --"), vec!["export --scale ", "export --scaler "]);
+    assert_eq!(complete("export a.png --scaler s"), vec!["export a.png --scaler scale2x", "export a.png --scaler scale3x"]);
+    assert!(complete("fill ").is_empty());
+
+    let dir = std::env::temp_dir().join(format!("entropic-completion-test-{}", std::process::id()));
+    fs::create_dir_all(dir.join("sprites")).unwrap();
+    fs::write(dir.join("pico8.hex"), "").unwrap();
+    fs::write(dir.join(".hidden"), "").unwrap();
+    let dir = dir.display().to_string();
+    assert_eq!(complete(&format!("palette load {}/", dir)), vec![
+        format!("palette load {}/pico8.hex", dir),
+        format!("palette load {}/sprites/", dir),
+    ]);
+    assert_eq!(complete(&format!("export --scale 2 {}/sp", dir)), vec![format!("export --scale 2 {}/sprites/", dir)]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn palettes() {
+    assert_eq!(parse_palette("000000\n\nFF004d\n"), Ok(vec![Color::gray(0), Color { r: 255, g: 0, b: 77 }]));
+    assert_eq!(parse_palette("000000\nnope"), Err("line 2: not a color: nope".into()));
+}
+
+const SCREEN: Dimension = Dimension { width: 104, height: 34 };
+
+fn last_line(gui: &mut Gui) -> String {
+    gui.draw(&mut VirtualTerminal::new(SCREEN)).unwrap();
+    // only the changes are drawn, so a fresh terminal needs all of it
+    let mut vt = VirtualTerminal::new(SCREEN);
+    gui.redraw(&mut vt).unwrap();
+    vt.text().lines().last().unwrap_or("").trim_end().to_string()
+}
+
+#[test]
+fn command_line() {
+    let size = Dimension { width: 8, height: 8 };
+    let mut gui = Gui::new(SCREEN, blank(size, &["Background"]));
+
+    type_in(&mut gui, ":layer new Shadows");
+    assert_eq!(last_line(&mut gui), ":layer new Shadows");
+    type_in(&mut gui, "\r:fi⇥ #ff0000\r");
+    assert_eq!(gui.state.picture.layers.len(), 2);
+    assert_eq!(gui.state.picture.layers[1].name, "Shadows");
+    assert!(gui.state.picture.layers[1].pixels.iter().all(|&pixel| pixel == Pixel { r: 255, g: 0, b: 0, a: 255 }));
+    assert!(gui.state.modified);
+
+    // errors are shown until the next key, and don't stop anything
+    type_in(&mut gui, ":resize 0 4\r");
+    assert_eq!(last_line(&mut gui), "not a size: 0");
+    type_in(&mut gui, ":palette load /nonexistent.hex\r");
+    assert!(last_line(&mut gui).starts_with("/nonexistent.hex: "));
+
+    // the commands typed before come back, most recent first
+    type_in(&mut gui, ":↑↑\x7f\x7f\x7f8 4\r");
+    // which crops the picture, so it asks first
+    assert_eq!(gui.state.picture.size, Dimension { width: 8, height: 8 });
+    type_in(&mut gui, "y");
+    assert_eq!(gui.state.picture.size, Dimension { width: 8, height: 4 });
+    assert_eq!(gui.state.picture.layers[0].pixels.len(), 32);
+    type_in(&mut gui, ":export /nonexistent.png --scale 40000\r");
+    assert_eq!(last_line(&mut gui), "8x4 is too big to be scaled by 40000");
+    type_in(&mut gui, ":export /nonexistent.png --scale 2000\r");
+    assert_eq!(last_line(&mut gui), "8x4 scaled by 2000 is too big, a picture has at most 16777216 pixels");
+    type_in(&mut gui, ":resize 65535 65535\r");
+    assert_eq!(last_line(&mut gui), "65535x65535 is too big, a picture has at most 16777216 pixels");
+    assert_eq!(gui.state.picture.size, Dimension { width: 8, height: 4 });
+
+    // every action the keys are bound to can be run by name
+    type_in(&mut gui, ":swap-colors\r");
+    assert_eq!(gui.state.primary, Color::gray(0));
+    type_in(&mut gui, ":");
+    type_in(&mut gui, "\x1b");
+    assert!(last_line(&mut gui).ends_with("Shadows  8x4*"));
+}
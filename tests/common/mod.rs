@@ -0,0 +1,62 @@
+//! What the tests share, not every one of them uses all of it
+#![allow(dead_code)]
+
+use std::time::Instant;
+
+use entropic::gui::*;
+use entropic::input::*;
+use entropic::state::*;
+
+/// A picture with transparent layers of the names, the last one being the current one
+pub fn blank(size: Dimension, names: &[&str]) -> Picture {
+    let layers = names.iter()
+        .map(|name| Layer { name: name.to_string(), pixels: vec![Pixel::default(); size.number()].into_boxed_slice() })
+        .collect();
+    Picture { size, layers }
+}
+
+/// A tiny picture drawn with `#` for black, `o` for white and `.` for transparent
+pub fn fixture(art: &str) -> (Vec<Pixel>, Dimension) {
+    let rows: Vec<&str> = art.split_whitespace().collect();
+    let pixels = rows.iter().flat_map(|row| row.chars()).map(|ch| match ch {
+        '#' => Pixel { r: 0, g: 0, b: 0, a: 255 },
+        'o' => Pixel { r: 255, g: 255, b: 255, a: 255 },
+        _ => Pixel::default(),
+    }).collect();
+    (pixels, Dimension { width: rows[0].len() as u16, height: rows.len() as u16 })
+}
+
+/// The pixels drawn back the way `fixture` takes them, a row per line
+pub fn art(pixels: &[Pixel], size: Dimension) -> String {
+    pixels.chunks(size.width as usize)
+        .map(|row| row.iter().map(|pixel| match (pixel.a, pixel.r) {
+            (0, _) => '.',
+            (_, 0) => '#',
+            _ => 'o',
+        }).collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Feeds the input as a single chunk, returning what the last event asked for
+pub fn run(gui: &mut Gui, input: &[u8]) -> Flow {
+    let mut parser = EventParser::default();
+    let events: Vec<_> = parser.parse(input).collect();
+    events.into_iter().fold(Flow::Continue, |_, event| gui.handle(event, Instant::now()).unwrap())
+}
+
+/// Sends the keys one at a time, the way a terminal does, with glyphs for the ones without a character
+pub fn type_in(gui: &mut Gui, keys: &str) {
+    for key in keys.split_inclusive(|_| true) {
+        let key = match key {
+            "↑" => "\x1b[A",
+            "↓" => "\x1b[B",
+            "⇱" => "\x1b[H",
+            "⌦" => "\x1b[3~",
+            "⇥" => "\t",
+            "⎋" => "\x1b",
+            key => key,
+        };
+        run(gui, key.as_bytes());
+    }
+}
@@ -0,0 +1,125 @@
+use std::fs;
+
+use entropic::dialog::*;
+use entropic::gui::*;
+use entropic::input::*;
+use entropic::scale::Scaler;
+use entropic::state::*;
+use entropic::vt::*;
+
+mod common;
+use common::*;
+
+const SCREEN: Dimension = Dimension { width: 80, height: 30 };
+
+fn gui() -> Gui {
+    let size = Dimension { width: 8, height: 8 };
+    Gui::new(SCREEN, blank(size, &["Background"]))
+}
+
+fn screen(gui: &mut Gui) -> String {
+    gui.draw(&mut VirtualTerminal::new(SCREEN)).unwrap();
+    let mut vt = VirtualTerminal::new(SCREEN);
+    gui.redraw(&mut vt).unwrap();
+    vt.text()
+}
+
+#[test]
+fn text_input() {
+    let mut input = TextInput::new("helo");
+    assert_eq!(input.cursor(), 4);
+    for event in [Event::Arrow(Arrow::Left, Modifiers::None), Event::Press('l', Modifiers::None), Event::Home, Event::Delete, Event::Press('h', Modifiers::Shift)] {
+        assert!(input.on_key(&event));
+    }
+    assert_eq!(input.text(), "Hello");
+    assert_eq!(input.cursor(), 1);
+    assert!(!input.on_key(&Event::Enter));
+    input.on_key(&Event::End);
+    input.on_key(&Event::Backspace);
+    assert_eq!(input.take(), "Hell");
+    assert!(input.is_empty());
+}
+
+#[test]
+fn confirmation() {
+    let mut gui = gui();
+    type_in(&mut gui, ":resize 4 4\r");
+    assert!(screen(&mut gui).contains("│ Resizing to 4x4 cuts off the pixels outside, go on? │"));
+
+    // nothing under it gets the keys, and the escape closes it
+    type_in(&mut gui, "x");
+    assert_eq!(gui.state.primary, entropic::draw::Color::gray(255));
+    type_in(&mut gui, "⎋");
+    assert!(!screen(&mut gui).contains("Resizing"));
+    assert_eq!(gui.state.picture.size, Dimension { width: 8, height: 8 });
+
+    // cancel is the default, and the OK button can be clicked too
+    type_in(&mut gui, ":resize 4 4\r\r");
+    assert_eq!(gui.state.picture.size, Dimension { width: 8, height: 8 });
+    type_in(&mut gui, ":resize 4 4\r");
+    let text = screen(&mut gui);
+    let (y, line) = text.lines().enumerate().find(|(_, line)| line.contains("[ OK ]")).unwrap();
+    // the terminal counts from 1
+    let (x, y) = (line[..line.find("[ OK ]").unwrap()].chars().count() + 3, y + 1);
+    run(&mut gui, format!("\x1b[<0;{x};{y}M\x1b[<0;{x};{y}m", x = x, y = y).as_bytes());
+    assert_eq!(gui.state.picture.size, Dimension { width: 4, height: 4 });
+}
+
+#[test]
+fn file_dialog() {
+    let dir = std::env::temp_dir().join(format!("entropic-dialog-test-{}", std::process::id()));
+    fs::create_dir_all(dir.join("sub")).unwrap();
+    fs::write(dir.join("a.png"), "").unwrap();
+    fs::write(dir.join("notes.txt"), "").unwrap();
+    fs::write(dir.join("sub").join("b.PNG"), "").unwrap();
+
+    let mut gui = gui();
+    gui.open(FileDialog::new("Export", dir.clone(), &["png"], false, |path| Action::Export(path, 1, Scaler::Nearest)));
+    let text = screen(&mut gui);
+    assert!(text.contains("│  ../") && text.contains("│  sub/") && text.contains("│  a.png"));
+    assert!(!text.contains("notes.txt"));
+
+    // into the directory and picking the file there, which is already there, so it asks
+    type_in(&mut gui, "↓\r↓\r");
+    assert!(screen(&mut gui).contains("b.PNG is already there, overwrite it?"));
+    type_in(&mut gui, "y");
+    assert!(fs::read(dir.join("sub").join("b.PNG")).unwrap().starts_with(b"\x89PNG"));
+
+    // a typed name gets the extension
+    gui.open(FileDialog::new("Export", dir.clone(), &["png"], false, |path| Action::Export(path, 1, Scaler::Nearest)));
+    type_in(&mut gui, "new\r");
+    assert!(dir.join("new.png").exists());
+
+    // and the ones to open have to be there
+    gui.open(FileDialog::new("Load palette", dir.clone(), &["hex"], true, Action::LoadPalette));
+    type_in(&mut gui, "nope\r");
+    assert!(screen(&mut gui).contains("no such file"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn prompt() {
+    let mut gui = gui();
+    type_in(&mut gui, "n");
+    assert!(screen(&mut gui).contains("│ Layer 1"));
+    type_in(&mut gui, "⇱⌦⌦⌦⌦⌦⌦⌦\r");
+    assert!(screen(&mut gui).contains("the layer needs a name"));
+    type_in(&mut gui, "Ink\r");
+    assert_eq!(gui.state.picture.layers.len(), 2);
+    assert_eq!(gui.state.picture.layers[1].name, "Ink");
+}
+
+#[test]
+fn quitting_with_unsaved_changes() {
+    let mut gui = gui();
+    assert_eq!(run(&mut gui, b"q"), Flow::Quit);
+
+    run(&mut gui, b"\x1b[<0;5;4M\x1b[<0;5;4m");
+    assert!(gui.state.modified);
+    assert_eq!(run(&mut gui, b"q"), Flow::Continue);
+    assert!(screen(&mut gui).contains("There are unsaved changes, quit anyway?"));
+    assert_eq!(run(&mut gui, b"n"), Flow::Continue);
+    assert_eq!(run(&mut gui, b"\x03"), Flow::Continue);
+    assert_eq!(run(&mut gui, b"y"), Flow::Quit);
+}
@@ -0,0 +1,145 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use entropic::document::*;
+use entropic::gui::*;
+use entropic::state::*;
+
+mod common;
+use common::*;
+
+fn picture() -> Picture {
+    let size = Dimension { width: 3, height: 2 };
+    let pixels = |a| (0..6).map(|i| Pixel { r: i * 40, g: 255 - i, b: 7, a }).collect();
+    Picture {
+        size,
+        layers: vec![
+            Layer { name: "Background".into(), pixels: pixels(255) },
+            Layer { name: "Ink ✒".into(), pixels: pixels(128) },
+        ],
+    }
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("entropic-{}-test-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn files(dir: &Path) -> Vec<String> {
+    let mut files: Vec<_> = fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn round_trip() {
+    let mut bytes = vec![];
+    write(&picture(), &mut bytes).unwrap();
+    assert!(bytes.starts_with(b"entropic picture v1\n"));
+    assert!(read(bytes.as_slice()).unwrap() == picture());
+
+    assert_eq!(read(&bytes[..bytes.len() - 1]).err().unwrap().kind(), std::io::ErrorKind::UnexpectedEof);
+    assert_eq!(read(&b"entropic input recording v1\n"[..]).err().unwrap().to_string(), "not an entropic picture");
+
+    // broken lengths are errors, not everything the memory has
+    let mut broken = b"entropic picture v1\n\x03\x00\x02\x00\x01\x00\x00\x00".to_vec();
+    broken.extend_from_slice(&u32::MAX.to_le_bytes());
+    assert_eq!(read(broken.as_slice()).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+    let huge = b"entropic picture v1\n\x00\x10\x00\x10\x01\x00\x00\x00\x00\x00\x00\x00";
+    assert_eq!(read(&huge[..]).err().unwrap().kind(), std::io::ErrorKind::UnexpectedEof);
+    // and so are the sizes a picture can't have
+    let empty = b"entropic picture v1\n\x00\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00";
+    assert_eq!(read(&empty[..]).err().unwrap().to_string(), "a picture with no pixels");
+    let too_big = b"entropic picture v1\n\xff\xff\xff\xff\x01\x00\x00\x00\x00\x00\x00\x00";
+    assert_eq!(read(&too_big[..]).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn saving_replaces_the_file() {
+    let dir = temp_dir("save");
+    let path = dir.join("art.entropic");
+    fs::write(&path, "old").unwrap();
+    save(&picture(), &path).unwrap();
+    assert!(load(&path).unwrap() == picture());
+    // nothing is left next to it
+    assert_eq!(files(&dir), vec!["art.entropic"]);
+
+    // and when it can't be written, the old one stays as it was
+    assert!(save(&picture(), &dir.join("nowhere").join("art.entropic")).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn autosaves() {
+    let dir = temp_dir("autosave");
+    let recovery = dir.join("recovery");
+    assert_eq!(recovery_path(&recovery, None), recovery.join("untitled.entropic"));
+    assert_eq!(recovery_path(&recovery, Some(Path::new("/art/100%.entropic"))), recovery.join("%art%100%%.entropic.entropic"));
+
+    let mut autosave = Autosave::new(recovery.clone(), Some(Path::new("/art/a.entropic")));
+    autosave.update(&picture(), false, Some(Path::new("/art/a.entropic"))).unwrap();
+    assert!(!recovery.exists());
+    autosave.update(&picture(), true, Some(Path::new("/art/a.entropic"))).unwrap();
+    assert!(load(autosave.path()).unwrap() == picture());
+    // saved as another file, the old autosave goes away
+    autosave.update(&picture(), true, Some(Path::new("/art/b.entropic"))).unwrap();
+    assert_eq!(files(&recovery), vec!["%art%b.entropic.entropic"]);
+    autosave.update(&picture(), false, Some(Path::new("/art/b.entropic"))).unwrap();
+    assert!(files(&recovery).is_empty());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn saving_and_recovering() {
+    let dir = temp_dir("recover");
+    let autosave = dir.join("autosave.entropic");
+    save(&picture(), &autosave).unwrap();
+
+    let size = Dimension { width: 8, height: 8 };
+    let mut gui = Gui::new(Dimension { width: 80, height: 30 }, blank(size, &["Background"]));
+    gui.offer_recovery(autosave);
+    run(&mut gui, b"y");
+    assert!(gui.state.picture == picture());
+    assert!(gui.state.modified);
+
+    // with no file yet, saving asks where to
+    let path = dir.join("art.entropic");
+    gui.state.file = None;
+    run(&mut gui, b"\x13");
+    // the dialog is in the working directory, a whole path goes anywhere
+    for key in format!("{}\r", dir.join("art").display()).bytes() {
+        run(&mut gui, &[key]);
+    }
+    assert!(!gui.state.modified);
+    assert_eq!(gui.state.file.as_deref(), Some(path.as_path()));
+    assert!(load(&path).unwrap() == picture());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn recovery_waits_for_the_answer() {
+    let dir = temp_dir("offer");
+    let recovery = dir.join("recovery");
+    let mut autosave = Autosave::new(recovery.clone(), None);
+    fs::create_dir_all(&recovery).unwrap();
+    save(&picture(), autosave.path()).unwrap();
+
+    let size = Dimension { width: 8, height: 8 };
+    let mut gui = Gui::new(Dimension { width: 80, height: 30 }, blank(size, &["Background"]));
+    gui.offer_recovery(autosave.path().to_path_buf());
+    // nothing is modified yet, but the autosave isn't thrown away before it's answered
+    gui.autosave(&mut autosave).unwrap();
+    run(&mut gui, b"y");
+    assert!(gui.state.picture == picture());
+
+    // and the same with no, until then
+    let mut gui = Gui::new(Dimension { width: 80, height: 30 }, blank(size, &["Background"]));
+    gui.offer_recovery(autosave.path().to_path_buf());
+    gui.autosave(&mut autosave).unwrap();
+    assert!(autosave.path().exists());
+    run(&mut gui, b"\x1b");
+    gui.autosave(&mut autosave).unwrap();
+    assert!(!autosave.path().exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,51 @@
+use std::time::{Duration, Instant};
+
+use entropic::gesture::*;
+use entropic::input::*;
+use entropic::state::Position;
+
+const POS: Position = Position { x: 3, y: 4 };
+
+fn press() -> Event {
+    Event::Mouse(MouseAction::Press, MouseButton::Left, POS, Modifiers::None)
+}
+
+fn release() -> Event {
+    Event::Mouse(MouseAction::Release, MouseButton::Left, POS, Modifiers::None)
+}
+
+#[test]
+fn clicks() {
+    let mut gestures = GestureRecognizer::new(GestureConfig::default());
+    let start = Instant::now();
+    let at = |ms| start + Duration::from_millis(ms);
+
+    assert_eq!(gestures.feed(&press(), at(0)), None);
+    assert_eq!(gestures.feed(&release(), at(50)), Some(Gesture::Click(MouseButton::Left, POS, Modifiers::None)));
+    gestures.feed(&press(), at(100));
+    assert_eq!(gestures.feed(&release(), at(150)), Some(Gesture::DoubleClick(MouseButton::Left, POS, Modifiers::None)));
+    gestures.feed(&press(), at(200));
+    assert_eq!(gestures.feed(&release(), at(250)), Some(Gesture::TripleClick(MouseButton::Left, POS, Modifiers::None)));
+    // too late to be a part of the same series
+    gestures.feed(&press(), at(1000));
+    assert_eq!(gestures.feed(&release(), at(1050)), Some(Gesture::Click(MouseButton::Left, POS, Modifiers::None)));
+}
+
+#[test]
+fn drag_and_long_press() {
+    let mut gestures = GestureRecognizer::new(GestureConfig::default());
+    let start = Instant::now();
+
+    gestures.feed(&press(), start);
+    let drag = Event::Mouse(MouseAction::Drag, MouseButton::Left, Position { x: 5, y: 4 }, Modifiers::None);
+    assert_eq!(gestures.feed(&drag, start), Some(Gesture::DragStart(MouseButton::Left, POS, Modifiers::None)));
+    assert_eq!(gestures.feed(&drag, start), None);
+    assert_eq!(gestures.deadline(), None);
+    assert_eq!(gestures.feed(&release(), start), Some(Gesture::DragEnd(MouseButton::Left, POS, Modifiers::None)));
+
+    gestures.feed(&press(), start);
+    let deadline = gestures.deadline().unwrap();
+    assert_eq!(gestures.tick(start), None);
+    assert_eq!(gestures.tick(deadline), Some(Gesture::LongPress(MouseButton::Left, POS, Modifiers::None)));
+    assert_eq!(gestures.feed(&release(), deadline), None);
+}
@@ -0,0 +1,63 @@
+use entropic::draw::*;
+
+/// What the cell looks like, pixel by pixel
+fn shows(glyphs: Glyphs, cell: &CharCell) -> Vec<Option<Color>> {
+    let mask = (0..=255u8).find(|&mask| glyphs.glyph(mask) == cell.char).unwrap();
+    (0..glyphs.block().number())
+        .map(|i| if mask & (1 << i) != 0 { cell.color.fg.rgb() } else { cell.color.bg.rgb() }.cloned())
+        .collect()
+}
+
+#[test]
+fn tables() {
+    assert_eq!(Glyphs::Quadrants.glyph(0b1001), '▚');
+    assert_eq!(Glyphs::Quadrants.glyph(0b1100), '▄');
+    assert_eq!(Glyphs::Sextants.glyph(1), '\u{1fb00}');
+    assert_eq!(Glyphs::Sextants.glyph(20), '\u{1fb13}');
+    assert_eq!(Glyphs::Sextants.glyph(21), '▌');
+    assert_eq!(Glyphs::Sextants.glyph(22), '\u{1fb14}');
+    assert_eq!(Glyphs::Sextants.glyph(62), '\u{1fb3b}');
+    assert_eq!(Glyphs::Braille.glyph(0b1), '⠁');
+    assert_eq!(Glyphs::Braille.glyph(0b10), '⠈');
+    assert_eq!(Glyphs::Braille.glyph(0b1000000), '⡀');
+    assert_eq!(Glyphs::Braille.glyph(0xff), '⣿');
+
+    // every mask has its own glyph
+    for glyphs in [Glyphs::Quadrants, Glyphs::Sextants, Glyphs::Braille] {
+        let count = 1 << glyphs.block().number();
+        let mut chars: Vec<char> = (0..count).map(|mask| glyphs.glyph(mask as u8)).collect();
+        chars.sort();
+        chars.dedup();
+        assert_eq!(chars.len(), count);
+    }
+}
+
+#[test]
+fn two_colors_are_exact() {
+    let (a, b) = (Some(Color::new(200, 10, 10)), Some(Color::new(10, 10, 200)));
+    for glyphs in [Glyphs::Quadrants, Glyphs::Sextants, Glyphs::Braille] {
+        let n = glyphs.block().number();
+        for mask in [0b1u8, 0b0110, 0b101010, 0b11000011] {
+            let pixels: Vec<_> = (0..n).map(|i| if mask & (1 << i) != 0 { a.clone() } else { b.clone() }).collect();
+            let cell = fit_cell(glyphs, &pixels);
+            assert_eq!(shows(glyphs, &cell), pixels, "{:?} {:#b}", glyphs, mask);
+        }
+    }
+}
+
+#[test]
+fn closest_split() {
+    let dark = Some(Color::gray(10));
+    let light = Some(Color::gray(250));
+    let lighter = Some(Color::gray(240));
+    let cell = fit_cell(Glyphs::Quadrants, &[dark.clone(), light, dark.clone(), lighter]);
+    let mid = Some(Color::gray(245));
+    assert_eq!(shows(Glyphs::Quadrants, &cell), [dark.clone(), mid.clone(), dark, mid]);
+
+    // nothing in the right column, past the edge of the picture
+    let red = Some(Color::new(255, 0, 0));
+    let cell = fit_cell(Glyphs::Quadrants, &[red.clone(), None, red, None]);
+    assert_eq!(cell.char, '▌');
+    assert_eq!(cell.color.fg, Ink::Rgb(Color::new(255, 0, 0)));
+    assert_eq!(cell.color.bg, Ink::Default);
+}
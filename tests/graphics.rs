@@ -0,0 +1,136 @@
+use entropic::draw::*;
+use entropic::graphics::*;
+use entropic::gui::*;
+use entropic::input::*;
+use entropic::state::*;
+use entropic::vt::*;
+
+use std::time::Instant;
+
+mod common;
+use common::*;
+
+const RED: Pixel = Pixel { r: 255, g: 0, b: 0, a: 255 };
+const CLEAR: Pixel = Pixel { r: 0, g: 0, b: 0, a: 0 };
+
+#[test]
+fn kitty() {
+    let size = Dimension { width: 2, height: 1 };
+    let out = String::from_utf8(kitty_image(7, &[RED, CLEAR], size, Dimension { width: 1, height: 1 })).unwrap();
+    assert_eq!(out, "\x1b_Ga=d,d=I,i=7,q=2\x1b\\\x1b_Ga=T,f=32,i=7,s=2,v=1,c=1,r=1,C=1,q=2,m=0;/wAA/wAAAAA=\x1b\\");
+
+    // big images are sent in chunks
+    let size = Dimension { width: 64, height: 64 };
+    let out = String::from_utf8(kitty_image(7, &vec![RED; size.number()], size, size)).unwrap();
+    assert_eq!(out.matches("m=1;").count(), 5);
+    assert_eq!(out.matches("\x1b_Gm=0;").count(), 1);
+}
+
+#[test]
+fn sixel() {
+    let size = Dimension { width: 3, height: 2 };
+    let out = String::from_utf8(sixel_image(&[RED, RED, CLEAR, CLEAR, RED, RED], size, 1)).unwrap();
+    assert_eq!(out, "\x1bP0;1;0q\"1;1;3;2#196;2;100;0;0#196@BA-\x1b\\");
+
+    let out = String::from_utf8(sixel_image(&[RED; 6], size, 2)).unwrap();
+    assert_eq!(out, "\x1bP0;1;0q\"1;1;6;4#196;2;100;0;0#196!6N-\x1b\\");
+}
+
+#[test]
+fn capabilities() {
+    assert!(has_sixel(&[62, 4, 22]));
+    assert!(!has_sixel(&[4, 22]));
+}
+
+fn gui() -> Gui {
+    let size = Dimension { width: 8, height: 8 };
+    let mut picture = blank(size, &["Layer"]);
+    picture.layers[0].pixels.fill(RED);
+    let mut gui = Gui::new(Dimension { width: 100, height: 30 }, picture);
+    gui.state.graphics = Graphics::Kitty;
+    gui.state.cell_size = Some(Dimension { width: 4, height: 8 });
+    gui
+}
+
+fn press(gui: &mut Gui, screen: &mut VirtualTerminal, input: &[u8]) {
+    for event in EventParser::default().parse(input).collect::<Vec<_>>() {
+        gui.handle(event, Instant::now()).unwrap();
+    }
+    gui.draw(screen).unwrap();
+}
+
+/// The kitty images transmitted so far
+fn images(screen: &VirtualTerminal) -> Vec<(Position, String)> {
+    screen.graphics.iter()
+        .map(|(pos, bytes)| (*pos, String::from_utf8_lossy(bytes).into_owned()))
+        .filter(|(_, image)| image.contains("a=T"))
+        .collect()
+}
+
+#[test]
+fn preview_overlay() {
+    let mut gui = gui();
+    let mut screen = VirtualTerminal::new(gui.state.terminal);
+    gui.draw(&mut screen).unwrap();
+    press(&mut gui, &mut screen, b"p");
+    let sent = images(&screen);
+    assert_eq!(sent.len(), 1);
+    let (pos, image) = &sent[0];
+    assert_eq!(*pos, Position { x: 66, y: 5 });
+    assert!(image.contains("i=1,s=8,v=8,c=2,r=1"));
+    // the cells under it are left alone
+    assert_eq!(screen.cell(*pos + Position { x: 1, y: 0 }).bg, None);
+
+    // nothing changed, nothing sent
+    let sent = screen.graphics.len();
+    gui.draw(&mut screen).unwrap();
+    assert_eq!(screen.graphics.len(), sent);
+
+    // a bigger scale is a new image
+    press(&mut gui, &mut screen, b"\x1b[<64;67;6M");
+    assert_eq!(images(&screen).len(), 2);
+    assert!(images(&screen)[1].1.contains("c=4,r=2"));
+
+    // moving the mouse around changes nothing, painting changes the image
+    press(&mut gui, &mut screen, b"\x1b[<35;5;4M");
+    assert_eq!(images(&screen).len(), 2);
+    press(&mut gui, &mut screen, b"\x1b[<0;5;4M\x1b[<0;5;4m");
+    assert_eq!(images(&screen).len(), 3);
+    assert_ne!(images(&screen)[2].1, images(&screen)[1].1);
+
+    // and when it's closed, it is deleted and the cells come back
+    press(&mut gui, &mut screen, b"p");
+    assert_eq!(images(&screen).len(), 3);
+    assert_eq!(screen.graphics.last().unwrap().1, b"\x1b_Ga=d,d=I,i=1,q=2\x1b\\");
+
+    let mut fresh = VirtualTerminal::new(gui.state.terminal);
+    gui.redraw(&mut fresh).unwrap();
+    assert_eq!(screen.text(), fresh.text());
+    assert_eq!(screen.colors(), fresh.colors());
+}
+
+#[test]
+fn half_block_preview() {
+    let mut gui = gui();
+    gui.state.graphics = Graphics::HalfBlocks;
+    let mut screen = VirtualTerminal::new(gui.state.terminal);
+    gui.draw(&mut screen).unwrap();
+    press(&mut gui, &mut screen, b"p");
+    assert!(screen.graphics.is_empty());
+    let cell = screen.cell(Position { x: 66, y: 5 });
+    assert_eq!(cell.char, '▀');
+    assert_eq!(cell.fg, Some(Color::new(255, 0, 0)));
+    assert!(screen.text().contains(" Preview 1x"));
+}
+
+#[test]
+fn preview_of_a_long_picture() {
+    // twice as tall as a u16 goes at the scale of 2
+    let mut gui = Gui::new(Dimension { width: 100, height: 30 }, blank(Dimension { width: 4, height: 32768 }, &["Layer"]));
+    let mut screen = VirtualTerminal::new(gui.state.terminal);
+    gui.draw(&mut screen).unwrap();
+    press(&mut gui, &mut screen, b"p");
+    press(&mut gui, &mut screen, b"\x1b[Z");
+    press(&mut gui, &mut screen, b"\x1b[A");
+    assert!(screen.text().contains(" Preview 2x, no room"));
+}
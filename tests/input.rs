@@ -0,0 +1,135 @@
+use entropic::input::*;
+use entropic::state::{Dimension, Position};
+
+fn parse(bytes: &'static [u8]) -> Vec<Event> {
+    create_event_receiver(bytes).iter().map(Result::unwrap).collect()
+}
+
+fn pos(x: u16, y: u16) -> Position {
+    Position { x, y }
+}
+
+#[test]
+fn sgr() {
+    assert_eq!(parse(b"\x1b[<0;3;5M\x1b[<0;3;5m"), vec![
+        Event::Mouse(MouseAction::Press, MouseButton::Left, pos(2, 4), Modifiers::None),
+        Event::Mouse(MouseAction::Release, MouseButton::Left, pos(2, 4), Modifiers::None),
+    ]);
+    assert_eq!(parse(b"\x1b[<34;10;20M"), vec![
+        Event::Mouse(MouseAction::Drag, MouseButton::Right, pos(9, 19), Modifiers::None),
+    ]);
+    assert_eq!(parse(b"\x1b[<65;1;1M"), vec![
+        Event::MouseWheel(MouseWheelDirection::Down, pos(0, 0), Modifiers::None),
+    ]);
+}
+
+#[test]
+fn sgr_extra_buttons() {
+    assert_eq!(parse(b"\x1b[<66;1;1M\x1b[<71;1;1M"), vec![
+        Event::MouseWheel(MouseWheelDirection::Left, pos(0, 0), Modifiers::None),
+        Event::MouseWheel(MouseWheelDirection::Right, pos(0, 0), Modifiers::Shift),
+    ]);
+    assert_eq!(parse(b"\x1b[<128;1;1M\x1b[<129;1;1m\x1b[<131;1;1M"), vec![
+        Event::Mouse(MouseAction::Press, MouseButton::Back, pos(0, 0), Modifiers::None),
+        Event::Mouse(MouseAction::Release, MouseButton::Forward, pos(0, 0), Modifiers::None),
+        Event::Mouse(MouseAction::Press, MouseButton::Extra(11), pos(0, 0), Modifiers::None),
+    ]);
+}
+
+#[test]
+fn sgr_pixels() {
+    // same as SGR, but coordinates easily go over 999
+    assert_eq!(parse(b"\x1b[<35;1234;56789M"), vec![
+        Event::MouseMotion(pos(1233, 56788), Modifiers::None),
+    ]);
+    let cell = Dimension { width: 10, height: 20 };
+    let (cell_pos, sub) = SubCell::from_pixels(pos(1233, 56798), cell);
+    assert_eq!(cell_pos, pos(123, 2839));
+    assert!(sub.is_lower_half());
+    let (_, sub) = SubCell::from_pixels(pos(1233, 56785), cell);
+    assert!(!sub.is_lower_half());
+}
+
+#[test]
+fn x10() {
+    assert_eq!(parse(b"\x1b[M\x20\x23\x25\x1b[M\x23\x23\x25"), vec![
+        Event::Mouse(MouseAction::Press, MouseButton::Left, pos(2, 4), Modifiers::None),
+        Event::Mouse(MouseAction::Release, MouseButton::Left, pos(2, 4), Modifiers::None),
+    ]);
+    // ctrl + middle button, coordinates past 95 are just raw bytes
+    assert_eq!(parse(b"\x1b[M\x31\xf0\x25"), vec![
+        Event::Mouse(MouseAction::Press, MouseButton::Middle, pos(207, 4), Modifiers::Ctrl),
+    ]);
+}
+
+#[test]
+fn utf8() {
+    // 1005 encodes coordinates past 95 as UTF-8, here x is 300
+    assert_eq!(parse(b"\x1b[M\x22\xc5\x8c\x25\x1b[M\x23\xc5\x8c\x25"), vec![
+        Event::Mouse(MouseAction::Press, MouseButton::Right, pos(299, 4), Modifiers::None),
+        Event::Mouse(MouseAction::Release, MouseButton::Right, pos(299, 4), Modifiers::None),
+    ]);
+}
+
+#[test]
+fn urxvt() {
+    assert_eq!(parse(b"\x1b[32;1500;7M\x1b[67;1500;8M"), vec![
+        Event::Mouse(MouseAction::Press, MouseButton::Left, pos(1499, 6), Modifiers::None),
+        Event::MouseMotion(pos(1499, 7), Modifiers::None),
+    ]);
+}
+
+#[test]
+fn mode_report() {
+    assert_eq!(parse(b"\x1b[?1016;1$y"), vec![Event::ModeReport(1016, ModeState::Set)]);
+    assert_eq!(parse(b"\x1b[?2026;0$y"), vec![Event::ModeReport(2026, ModeState::NotRecognized)]);
+}
+
+#[test]
+fn focus() {
+    assert_eq!(parse(b"\x1b[I"), vec![Event::FocusGained]);
+    assert_eq!(parse(b"\x1b[O"), vec![Event::FocusLost]);
+}
+
+#[test]
+fn capability_answers() {
+    assert_eq!(parse(b"\x1b_Gi=31;OK\x1b\\\x1b[?62;4;22c"), vec![
+        Event::KittyGraphics(31, true),
+        Event::DeviceAttributes(vec![62, 4, 22]),
+    ]);
+    assert_eq!(parse(b"\x1b_Gi=31;ENOENT:no such image\x1b\\"), vec![Event::KittyGraphics(31, false)]);
+    assert_eq!(parse(b"\x1b[?1;2c"), vec![Event::DeviceAttributes(vec![1, 2])]);
+}
+
+#[test]
+fn keys() {
+    assert_eq!(parse(b"\r"), vec![Event::Enter]);
+    assert_eq!(parse(b"\t"), vec![Event::Tab]);
+    assert_eq!(parse(b"\x1b[Z"), vec![Event::BackTab]);
+}
+
+#[test]
+fn split_across_reads() {
+    let mut parser = EventParser::default();
+    assert_eq!(parser.parse(b"\x1b[<0;10").collect::<Vec<_>>(), vec![]);
+    assert_eq!(parser.parse(b";5M").collect::<Vec<_>>(), vec![
+        Event::Mouse(MouseAction::Press, MouseButton::Left, pos(9, 4), Modifiers::None),
+    ]);
+    // the events before the cut off one come out right away
+    assert_eq!(parser.parse(b"\x1b[<35;1;1M\x1b[<35;2").collect::<Vec<_>>(), vec![
+        Event::MouseMotion(pos(0, 0), Modifiers::None),
+    ]);
+    assert_eq!(parser.parse(b";1M").collect::<Vec<_>>(), vec![
+        Event::MouseMotion(pos(1, 0), Modifiers::None),
+    ]);
+    // the keys that are prefixes of the sequences are not held back
+    assert_eq!(parser.parse(b"\x1b").collect::<Vec<_>>(), vec![Event::Escape]);
+    assert_eq!(parser.parse(b"\x1b[").collect::<Vec<_>>(), vec![Event::Press('[', Modifiers::Alt)]);
+    assert_eq!(parser.parse(b"\x1b_").collect::<Vec<_>>(), vec![Event::Press('_', Modifiers::Alt)]);
+    assert_eq!(parser.parse(b"a").collect::<Vec<_>>(), vec![Event::Press('a', Modifiers::None)]);
+    // a character of the UTF-8 mouse encoding cut in half
+    assert_eq!(parser.parse(b"\x1b[M \xc3").collect::<Vec<_>>(), vec![]);
+    assert_eq!(parser.parse(b"\xa8%").collect::<Vec<_>>(), vec![
+        Event::Mouse(MouseAction::Press, MouseButton::Left, pos(199, 4), Modifiers::None),
+    ]);
+}
@@ -0,0 +1,71 @@
+use entropic::layout::*;
+use entropic::state::*;
+
+fn rect(x: u16, y: u16, width: u16, height: u16) -> Rect {
+    (Position { x, y }, Dimension { width, height })
+}
+
+fn hint(id: usize) -> Dimension {
+    Dimension { width: 3 * id as u16, height: id as u16 }
+}
+
+#[test]
+fn rows_and_columns() {
+    let row = Layout::Row(vec![
+        (Size::Fixed(4), Layout::Widget(0)),
+        (Size::Flex(1), Layout::Widget(1)),
+        (Size::Auto, Layout::Widget(2)),
+        (Size::Flex(2), Layout::Widget(3)),
+        (Size::Min(5), Layout::Widget(1)),
+    ]);
+    // 4 fixed, 6 auto and 5 min leave 10 to share one to two
+    assert_eq!(row.compute(rect(1, 1, 25, 2), &hint), vec![
+        (0, rect(1, 1, 4, 2)),
+        (1, rect(5, 1, 4, 2)),
+        (2, rect(9, 1, 6, 2)),
+        (3, rect(15, 1, 6, 2)),
+        (1, rect(21, 1, 5, 2)),
+    ]);
+
+    // too small, so the last ones get what's left
+    let column = Layout::Column(vec![
+        (Size::Fixed(2), Layout::Widget(0)),
+        (Size::Auto, Layout::Widget(3)),
+        (Size::Flex(1), Layout::Widget(1)),
+    ]);
+    assert_eq!(column.compute(rect(0, 0, 10, 4), &hint), vec![
+        (0, rect(0, 0, 10, 2)),
+        (3, rect(0, 2, 10, 2)),
+        (1, rect(0, 4, 10, 0)),
+    ]);
+    assert_eq!(column.preferred(&hint), Dimension { width: 9, height: 5 });
+}
+
+#[test]
+fn docking() {
+    let layout = Layout::Dock(
+        vec![
+            (Edge::Left, Size::Fixed(3), Layout::Widget(0)),
+            (Edge::Right, Size::Min(4), Layout::Widget(2)),
+            (Edge::Bottom, Size::Fixed(1), Layout::Widget(1)),
+            (Edge::Top, Size::Auto, Layout::Widget(1)),
+        ],
+        Box::new(Layout::Widget(5)),
+    );
+    assert_eq!(layout.compute(rect(0, 0, 20, 10), &hint), vec![
+        (0, rect(0, 0, 3, 10)),
+        (2, rect(14, 0, 6, 10)),
+        (1, rect(3, 9, 11, 1)),
+        (1, rect(3, 0, 11, 1)),
+        (5, rect(3, 1, 11, 8)),
+    ]);
+
+    // a smaller screen squeezes the middle first
+    assert_eq!(layout.compute(rect(0, 0, 8, 3), &hint), vec![
+        (0, rect(0, 0, 3, 3)),
+        (2, rect(3, 0, 5, 3)),
+        (1, rect(3, 2, 0, 1)),
+        (1, rect(3, 0, 0, 1)),
+        (5, rect(3, 1, 0, 1)),
+    ]);
+}
@@ -0,0 +1,14 @@
+use entropic::png::*;
+use entropic::state::*;
+
+#[test]
+fn stored_rgba() {
+    let pixels = [Pixel { r: 255, g: 0, b: 0, a: 255 }, Pixel { r: 0, g: 255, b: 0, a: 128 }, Pixel::default(), Pixel { r: 1, g: 2, b: 3, a: 4 }];
+    let mut expected = b"\x89PNG\r\n\x1a\n".to_vec();
+    expected.extend_from_slice(b"\0\0\0\x0dIHDR\0\0\0\x02\0\0\0\x02\x08\x06\0\0\0\x72\xb6\x0d\x24");
+    expected.extend_from_slice(b"\0\0\0\x1dIDAT\x78\x01\x01\x12\0\xed\xff");
+    expected.extend_from_slice(b"\0\xff\0\0\xff\0\xff\0\x80\0\0\0\0\0\x01\x02\x03\x04");
+    expected.extend_from_slice(b"\x2f\xfb\x03\x88\x09\x91\x18\x89");
+    expected.extend_from_slice(b"\0\0\0\0IEND\xae\x42\x60\x82");
+    assert_eq!(encode(&pixels, Dimension { width: 2, height: 2 }), expected);
+}
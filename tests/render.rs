@@ -0,0 +1,198 @@
+use std::io::{self, Write};
+
+use entropic::draw::*;
+use entropic::state::*;
+use entropic::term::*;
+
+#[test]
+fn terminal_state_into_a_vec() {
+    let size = Dimension { width: 3, height: 2 };
+    let mut state = TerminalState::new(size);
+    state.put_text(Position { x: 0, y: 0 }, CellColor::none(), "ab");
+    state.put(Position { x: 1, y: 1 }, CharCell::new('c').fg(Color::new(1, 2, 3)));
+
+    let mut out = Vec::new();
+    state.draw(size, &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "\x1b[3J\x1b[1;1Hab \x1b[2;1H \x1b[38;2;1;2;3mc\x1b[0m ",
+    );
+
+    // only the changed cell goes out the second time
+    state.put(Position { x: 2, y: 0 }, CharCell::new('d'));
+    let mut out = Vec::new();
+    state.draw(size, &mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "\x1b[1;3Hd");
+
+    let mut out = Vec::new();
+    state.draw(size, &mut out).unwrap();
+    assert!(out.is_empty());
+}
+
+#[test]
+fn only_the_differences() {
+    let size = Dimension { width: 20, height: 1 };
+    let mut state = TerminalState::new(size);
+    let mut out = Vec::new();
+    state.draw(size, &mut out).unwrap();
+
+    let red = CellColor::none().bg(Color::new(255, 0, 0));
+    state.put_text(Position { x: 1, y: 0 }, red.clone(), "ab");
+    state.put_text(Position { x: 5, y: 0 }, red.clone().fg(Color::gray(0)), "c");
+    state.put_text(Position { x: 6, y: 0 }, red.clone(), "          ");
+    state.put_text(Position { x: 16, y: 0 }, CellColor::none(), "d");
+    let mut out = Vec::new();
+    state.draw(size, &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "\x1b[1;2H\x1b[48;2;255;0;0mab\x1b[2C\x1b[38;2;0;0;0mc\x1b[10X\x1b[10C\x1b[0md",
+    );
+
+    // blanks up to the end of the line are erased
+    state.put_text(Position { x: 0, y: 0 }, red, " ".repeat(20));
+    let mut out = Vec::new();
+    state.draw(size, &mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "\x1b[1;1H\x1b[48;2;255;0;0m\x1b[K\x1b[0m");
+}
+
+/// Keeps every write separately
+#[derive(Default)]
+struct Writes(Vec<Vec<u8>>);
+
+impl Write for Writes {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.push(buf.to_vec());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn synchronized_frames() {
+    let size = Dimension { width: 4, height: 2 };
+    let mut state = TerminalState::new(size);
+    state.set_synchronized(true);
+    state.put_text(Position { x: 0, y: 1 }, CellColor::none().fg(Color::gray(1)), "abcd");
+
+    let mut out = Writes::default();
+    state.draw(size, &mut out).unwrap();
+    assert_eq!(out.0.len(), 1);
+    let frame = String::from_utf8(out.0.remove(0)).unwrap();
+    assert!(frame.starts_with("\x1b[?2026h\x1b[3J"));
+    assert!(frame.ends_with("\x1b[0m\x1b[?2026l"));
+
+    // no empty frames
+    state.draw(size, &mut out).unwrap();
+    assert!(out.0.is_empty());
+}
+
+#[test]
+fn mixins_write_into_the_base() {
+    let mut term = TerminalBase::new(Vec::new()).hide_cursor().unwrap().no_wrap().unwrap();
+    assert_eq!(term.get_ref().as_slice(), b"\x1b[?25l\x1b[?7l");
+    term.wrap_mode().unwrap();
+    assert_eq!(term.get_ref().as_slice(), b"\x1b[?25l\x1b[?7l\x1b[?7h");
+}
+
+fn screen(state: &mut TerminalState, vt: &mut entropic::vt::VirtualTerminal) -> String {
+    state.draw(vt.size(), vt).unwrap();
+    assert!(vt.unrecognized.is_empty());
+    vt.text()
+}
+
+#[test]
+fn wide_text() {
+    let size = Dimension { width: 8, height: 2 };
+    let mut state = TerminalState::new(size);
+    let mut vt = entropic::vt::VirtualTerminal::new(size);
+    state.put_text(Position { x: 0, y: 0 }, CellColor::none(), "a中b");
+    state.put_text(Position { x: 0, y: 1 }, CellColor::none(), "e\u{301}😀x");
+    assert_eq!(state.get(Position { x: 1, y: 0 }).width, CellWidth::Double);
+    assert_eq!(state.get(Position { x: 2, y: 0 }).width, CellWidth::Continuation);
+    assert_eq!(state.get(Position { x: 0, y: 1 }).marks, "\u{301}");
+    assert_eq!(text_width("a中b"), 4);
+    assert_eq!(screen(&mut state, &mut vt), "a中b\ne\u{301}😀x\n");
+
+    // the half that's left of a wide glyph becomes a blank
+    state.put_text(Position { x: 2, y: 0 }, CellColor::none(), "c");
+    state.put(Position { x: 1, y: 1 }, CharCell::new('f'));
+    assert_eq!(screen(&mut state, &mut vt), "a cb\ne\u{301}f x\n");
+
+    // and the narrow ones get replaced by a wide one
+    state.put_text(Position { x: 2, y: 0 }, CellColor::none(), "語");
+    assert_eq!(screen(&mut state, &mut vt), "a 語\ne\u{301}f x\n");
+
+    let mut fresh = entropic::vt::VirtualTerminal::new(size);
+    state.redraw(size, &mut fresh).unwrap();
+    assert_eq!(fresh.text(), vt.text());
+}
+
+#[test]
+fn text_is_clipped() {
+    let size = Dimension { width: 6, height: 2 };
+    let mut state = TerminalState::new(size);
+    let mut vt = entropic::vt::VirtualTerminal::new(size);
+    // past the end of the row and of the buffer
+    state.put_text(Position { x: 3, y: 0 }, CellColor::none(), "abcdef");
+    state.put_text(Position { x: 3, y: 1 }, CellColor::none(), "ab中");
+    state.put_text(Position { x: 0, y: 2 }, CellColor::none(), "nowhere");
+    state.put(Position { x: 9, y: 1 }, CharCell::new('x'));
+    assert_eq!(screen(&mut state, &mut vt), "   abc\n   ab\n");
+
+    // a wide glyph cut in half by the clip leaves a blank
+    let clip = (Position { x: 1, y: 0 }, Dimension { width: 3, height: 1 });
+    state.clear(size);
+    state.put_text_clipped(Position { x: 0, y: 0 }, clip, CellColor::none(), "中文字");
+    state.put_text_clipped(Position { x: 0, y: 1 }, clip, CellColor::none(), "outside");
+    assert_eq!(screen(&mut state, &mut vt), "  文\n\n");
+    assert_eq!(state.get(Position { x: 1, y: 0 }).char, ' ');
+    assert_eq!(state.get(Position { x: 4, y: 0 }).char, ' ');
+}
+
+#[test]
+fn attributes() {
+    let size = Dimension { width: 6, height: 1 };
+    let mut state = TerminalState::new(size);
+    let mut vt = entropic::vt::VirtualTerminal::new(size);
+    let mut out = Vec::new();
+    state.draw(size, &mut out).unwrap();
+
+    let curly = Attributes::default().underline(Underline::Curly).underline_color(Color::new(255, 0, 0));
+    state.put_text(Position { x: 0, y: 0 }, CellColor::none().attrs(Attributes::default().bold().italic()), "ab");
+    state.put_text(Position { x: 2, y: 0 }, CellColor::none().attrs(Attributes::default().bold().dim()), "c");
+    state.put_text(Position { x: 3, y: 0 }, CellColor::none().attrs(curly.clone()), "d");
+    state.put(Position { x: 4, y: 0 }, CharCell::new('e').attrs(Attributes::default().reverse()));
+    let mut out = Vec::new();
+    state.draw(size, &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out.clone()).unwrap(),
+        "\x1b[1;1H\x1b[1;3mab\x1b[2;23mc\x1b[22;4:3;58;2;255;0;0md\x1b[24;59;7me\x1b[0m",
+    );
+    vt.write_all(&out).unwrap();
+    assert!(vt.unrecognized.is_empty());
+    assert_eq!(vt.cell(Position { x: 2, y: 0 }).attrs, Attributes::default().bold().dim());
+    assert_eq!(vt.cell(Position { x: 3, y: 0 }).attrs, curly);
+
+    // the attributes alone make a cell change
+    state.put(Position { x: 4, y: 0 }, CharCell::new('e'));
+    let mut out = Vec::new();
+    state.draw(size, &mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "\x1b[1;5He");
+
+    // reversed spaces can't be erased
+    let reversed = CellColor::none().attrs(Attributes::default().reverse());
+    state.put_text(Position { x: 0, y: 0 }, reversed, " ".repeat(6));
+    let mut out = Vec::new();
+    state.draw(size, &mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "\x1b[1;1H\x1b[7m      \x1b[0m");
+
+    // on a mono terminal a light background is the reverse video, so the two cancel out
+    state.set_color_depth(ColorDepth::Mono);
+    state.put(Position { x: 0, y: 0 }, CharCell::new('x').bg(Color::gray(255)).attrs(Attributes::default().reverse()));
+    let mut out = Vec::new();
+    state.redraw(size, &mut out).unwrap();
+    assert!(String::from_utf8(out).unwrap().starts_with("\x1b[3J\x1b[1;1Hx\x1b[7m     "));
+}
@@ -0,0 +1,108 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::thread;
+use std::time::Duration;
+
+use entropic::gui::*;
+use entropic::input::*;
+use entropic::record::*;
+use entropic::state::*;
+
+/// Gives out one chunk per read, after a delay, like a terminal would
+struct Session(Vec<(u64, &'static [u8])>);
+
+impl Read for Session {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.0.is_empty() {
+            return Ok(0);
+        }
+        let (delay, chunk) = self.0.remove(0);
+        thread::sleep(Duration::from_millis(delay));
+        buf[..chunk.len()].copy_from_slice(chunk);
+        Ok(chunk.len())
+    }
+}
+
+fn picture() -> Picture {
+    let size = Dimension { width: 32, height: 32 };
+    Picture {
+        size,
+        layers: vec![Layer { name: "Layer".into(), pixels: vec![Pixel::default(); size.number()].into_boxed_slice() }],
+    }
+}
+
+fn run(events: crossbeam_channel::Receiver<io::Result<(Event, std::time::Instant)>>) -> Picture {
+    let mut gui = Gui::new(Dimension { width: 80, height: 24 }, picture());
+    for event in events {
+        let (event, at) = event.unwrap();
+        gui.handle(event, at).unwrap();
+    }
+    gui.state.picture
+}
+
+#[test]
+fn replay_reproduces_the_picture() {
+    let session = Session(vec![
+        // a stroke with the white primary color
+        (0, b"\x1b[<0;5;4M"),
+        (10, b"\x1b[<32;7;4M\x1b[<32;9;4M"),
+        (10, b"\x1b[<0;9;4m"),
+        // two clicks on the secondary swatch, too slow to be a double click
+        (10, b"\x1b[<0;2;1M"),
+        (10, b"\x1b[<0;2;1m"),
+        (500, b"\x1b[<0;2;1M"),
+        (10, b"\x1b[<0;2;1m"),
+        // now a quick one, opening the color picker and making it green
+        (10, b"\x1b[<0;2;1M"),
+        (10, b"\x1b[<0;2;1m"),
+        (10, b"\x1b[<0;80;6M"),
+        (10, b"\x1b[<0;80;6m"),
+        (10, b"\x1b"),
+        // swap the colors and paint with the green one
+        (10, b"x"),
+        (10, b"\x1b[<0;11;6M\x1b[<0;11;6m"),
+    ]);
+
+    let path = std::env::temp_dir().join(format!("entropic-replay-test-{}", std::process::id()));
+    let recorder = Recorder::new(session, File::create(&path).unwrap(), Dimension { width: 80, height: 24 }).unwrap();
+    let recorded = run(create_timed_event_receiver(recorder));
+
+    let white = Pixel { r: 255, g: 255, b: 255, a: 255 };
+    let green = Pixel { r: 0, g: 255, b: 0, a: 255 };
+    let size = recorded.size;
+    assert_eq!(recorded.layers[0].pixels[size.offset(Position { x: 1, y: 2 })], white);
+    assert_eq!(recorded.layers[0].pixels[size.offset(Position { x: 3, y: 2 })], white);
+    assert_eq!(recorded.layers[0].pixels[size.offset(Position { x: 4, y: 4 })], green);
+
+    let replayed = run(create_replay_receiver(Recording::new(File::open(&path).unwrap()).unwrap(), None));
+    let _ = std::fs::remove_file(&path);
+
+    assert!(recorded == replayed, "replayed picture differs from the recorded one");
+}
+
+#[test]
+fn replay_keeps_the_terminal_size() {
+    // a click below the 24th row, which only lands on the canvas after the resize
+    let session = Session(vec![(50, b"\x1b[<0;5;30M"), (10, b"\x1b[<0;5;30m")]);
+
+    let path = std::env::temp_dir().join(format!("entropic-replay-size-test-{}", std::process::id()));
+    let recorder = Recorder::new(session, File::create(&path).unwrap(), Dimension { width: 80, height: 24 }).unwrap();
+    recorder.sizes().record(Dimension { width: 80, height: 40 }).unwrap();
+    // the input has to be read for it to be recorded
+    create_timed_event_receiver(recorder).iter().for_each(drop);
+
+    let recording = Recording::new(File::open(&path).unwrap()).unwrap();
+    let chunks: Vec<Chunk> = recording.map(Result::unwrap).collect();
+    assert!(matches!(chunks[..], [
+        Chunk::Resize(_, Dimension { width: 80, height: 24 }),
+        Chunk::Resize(_, Dimension { width: 80, height: 40 }),
+        Chunk::Input(..),
+        Chunk::Input(..),
+    ]));
+
+    // replayed in the smaller terminal, the size from the recording takes over
+    let replayed = run(create_replay_receiver(Recording::new(File::open(&path).unwrap()).unwrap(), None));
+    let _ = std::fs::remove_file(&path);
+    let white = Pixel { r: 255, g: 255, b: 255, a: 255 };
+    assert_eq!(replayed.layers[0].pixels[replayed.size.offset(Position { x: 1, y: 28 })], white);
+}
@@ -0,0 +1,116 @@
+use entropic::draw::Color;
+use entropic::gui::*;
+use entropic::state::*;
+use entropic::vt::*;
+
+mod common;
+use common::*;
+
+const SCREEN: Dimension = Dimension { width: 104, height: 34 };
+
+fn gui() -> Gui {
+    let size = Dimension { width: 32, height: 32 };
+    Gui::new(SCREEN, blank(size, &["Background", "Layer 1"]))
+}
+
+fn painted(gui: &Gui, x: u16, y: u16) -> bool {
+    let size = gui.state.picture.size;
+    gui.state.picture.layers[1].pixels[size.offset(Position { x, y })].a != 0
+}
+
+fn screen(gui: &mut Gui) -> String {
+    let mut vt = VirtualTerminal::new(SCREEN);
+    gui.draw(&mut vt).unwrap();
+    vt.text()
+}
+
+#[test]
+fn strokes_capture_the_mouse() {
+    let mut gui = gui();
+    // from the canvas over the layers panel and back, letting go over the panel
+    run(&mut gui, b"\x1b[<0;5;4M\x1b[<32;75;3M\x1b[<32;9;4M\x1b[<32;75;3M\x1b[<0;75;3m");
+    assert!(painted(&gui, 1, 2));
+    assert!(painted(&gui, 3, 2));
+    assert_eq!(gui.state.current_layer, 1);
+
+    // and the other way around, the canvas doesn't get a stroke started elsewhere
+    run(&mut gui, b"\x1b[<0;75;3M\x1b[<32;13;4M\x1b[<0;13;4m");
+    assert_eq!(gui.state.current_layer, 0);
+    assert!(!painted(&gui, 5, 2));
+}
+
+#[test]
+fn bound_buttons_bubble() {
+    let mut gui = gui();
+    // the back button swaps the colors even over the canvas, which doesn't paint with it
+    run(&mut gui, b"\x1b[<128;5;4M\x1b[<128;5;4m");
+    assert_eq!(gui.state.primary, Color::gray(0));
+    assert!(!painted(&gui, 1, 2));
+}
+
+#[test]
+fn keyboard_focus() {
+    let mut gui = gui();
+    // nothing is focused yet, so the arrows do nothing
+    run(&mut gui, b"\x1b[B");
+    assert_eq!(gui.state.current_layer, 1);
+
+    run(&mut gui, b"\t");
+    run(&mut gui, b"\x1b[B");
+    assert_eq!(gui.state.current_layer, 0);
+    // the keys the panel doesn't want still get to the bindings
+    run(&mut gui, b"x");
+    assert_eq!(gui.state.primary, Color::gray(0));
+
+    // back around to the preview once it's on, the arrows scaling it
+    run(&mut gui, b"p");
+    run(&mut gui, b"\x1b[Z");
+    run(&mut gui, b"\x1b[A");
+    assert!(screen(&mut gui).contains(" Preview 2x"));
+    assert_eq!(gui.state.current_layer, 0);
+
+    // and nothing is focused after escape, or when the focused one goes away
+    run(&mut gui, b"\t");
+    run(&mut gui, b"\x1b");
+    run(&mut gui, b"\x1b[A");
+    assert_eq!(gui.state.current_layer, 0);
+    run(&mut gui, b"\x1b[Z");
+    run(&mut gui, b"p");
+    run(&mut gui, b"\x1b[A");
+    assert!(!screen(&mut gui).contains("Preview"));
+    run(&mut gui, b"p");
+    assert!(screen(&mut gui).contains(" Preview 2x"));
+}
+
+#[test]
+fn renaming_lets_the_bindings_through() {
+    let mut gui = gui();
+    run(&mut gui, b"\x1b[<0;5;4M\x1b[<0;5;4m");
+    assert!(painted(&gui, 1, 2));
+
+    // a double click on the layer starts renaming it, taking the typed keys
+    run(&mut gui, b"\x1b[<0;75;3M\x1b[<0;75;3m\x1b[<0;75;3M\x1b[<0;75;3m");
+    run(&mut gui, b"x");
+    assert_eq!(gui.state.primary, Color::gray(255));
+    // but not the ones bound to something else
+    run(&mut gui, b"\x1a");
+    assert!(!painted(&gui, 1, 2));
+    run(&mut gui, b"\r");
+    assert_eq!(gui.state.picture.layers[0].name, "Backgroundx");
+}
+
+#[test]
+fn renaming_a_layer_undone_away() {
+    let mut gui = gui();
+    run(&mut gui, b"n");
+    run(&mut gui, b"\r");
+    assert_eq!(gui.state.picture.layers.len(), 3);
+    run(&mut gui, b"\x1b[<0;75;2M\x1b[<0;75;2m\x1b[<0;75;2M\x1b[<0;75;2m");
+    assert!(screen(&mut gui).contains(" Layer 2_"));
+    // the undo takes the layer being renamed away, which calls the renaming off
+    run(&mut gui, b"\x1a");
+    assert_eq!(gui.state.picture.layers.len(), 2);
+    run(&mut gui, b"\r");
+    assert_eq!(gui.state.picture.layers[1].name, "Layer 1");
+    assert!(!screen(&mut gui).contains('_'));
+}
@@ -0,0 +1,93 @@
+use entropic::scale::*;
+use entropic::state::*;
+
+mod common;
+use common::*;
+
+fn scaled(art_in: &str, scaler: Scaler, factor: f64) -> String {
+    let (pixels, size) = fixture(art_in);
+    let (pixels, size) = scaler.scale(&pixels, size, factor).unwrap();
+    art(&pixels, size)
+}
+
+const DIAGONAL: &str = "#.. .#. ..#";
+
+#[test]
+fn nearest() {
+    let (a, b) = (Pixel { r: 1, g: 0, b: 0, a: 255 }, Pixel { r: 2, g: 0, b: 0, a: 255 });
+    let (pixels, size) = Scaler::Nearest.scale(&[a, b], Dimension { width: 2, height: 1 }, 2.0).unwrap();
+    assert_eq!(size, Dimension { width: 4, height: 2 });
+    assert!(pixels == [a, a, b, b, a, a, b, b]);
+
+    assert_eq!(scaled(DIAGONAL, Scaler::Nearest, 1.5), "\
+##...
+##...
+..#..
+...##
+...##");
+    assert_eq!(scaled("o#o# #o#o oooo", Scaler::Nearest, 0.5), "##\noo");
+    assert_eq!(scaled("#", Scaler::Nearest, 0.1), "#");
+}
+
+#[test]
+fn scale2x() {
+    assert_eq!(scaled(DIAGONAL, Scaler::Scale2x, 2.0), "\
+##....
+#.#...
+.###..
+..###.
+...#.#
+....##");
+    // twice over for 4x
+    assert_eq!(scaled(DIAGONAL, Scaler::Scale2x, 4.0), "\
+####........
+###.#.......
+##..##......
+#...###.....
+.######.....
+..#######...
+...#######..
+.....######.
+.....###...#
+......##..##
+.......#.###
+........####");
+    // the straight edges stay as they are
+    assert_eq!(scaled("oo# oo#", Scaler::Scale2x, 2.0), "oooo##\noooo##\noooo##\noooo##");
+}
+
+#[test]
+fn scale3x() {
+    assert_eq!(scaled(DIAGONAL, Scaler::Scale3x, 3.0), "\
+###......
+##.#.....
+#..#.....
+.#####...
+...###...
+...#####.
+.....#..#
+.....#.##
+......###");
+}
+
+#[test]
+fn factors() {
+    let (pixels, size) = fixture(DIAGONAL);
+    assert_eq!(Scaler::Scale2x.scale(&pixels, size, 3.0).err(), Some("scale2x only scales by 2, 4, 8 and so on".into()));
+    assert_eq!(Scaler::Scale3x.scale(&pixels, size, 1.0).err(), Some("scale3x only scales by 3, 9, 27 and so on".into()));
+    assert_eq!(Scaler::Nearest.scale(&pixels, size, 30000.0).err(), Some("3x3 is too big to be scaled by 30000".into()));
+
+    // all of the layers or none of them
+    let mut picture = Picture {
+        size,
+        layers: vec![Layer { name: "a".into(), pixels: pixels.clone().into_boxed_slice() }, Layer { name: "b".into(), pixels: pixels.into_boxed_slice() }],
+    };
+    assert!(picture.scale(1.5, Scaler::Scale2x).is_err());
+    assert_eq!(picture.size, size);
+    // fitting in the dimensions isn't enough, there's a budget for the pixels
+    assert_eq!(picture.scale(2000.0, Scaler::Nearest).err(), Some("3x3 scaled by 2000 is too big, a picture has at most 16777216 pixels".into()));
+    assert_eq!(picture.size, size);
+    picture.scale(2.0, Scaler::Scale2x).unwrap();
+    assert_eq!(picture.size, Dimension { width: 6, height: 6 });
+    assert!(picture.is_consistent() && picture.layers[0].pixels == picture.layers[1].pixels);
+}
@@ -0,0 +1,128 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use entropic::gui::*;
+use entropic::input::*;
+use entropic::state::*;
+use entropic::vt::*;
+
+mod common;
+use common::*;
+
+const SCREEN: Dimension = Dimension { width: 104, height: 34 };
+
+fn gui() -> Gui {
+    let size = Dimension { width: 32, height: 32 };
+    let mut picture = blank(size, &["Background", "Layer 1"]);
+    picture.layers[0].pixels.fill(Pixel { r: 0x3f, g: 0x3f, b: 0x3f, a: 0xff });
+    Gui::new(SCREEN, picture)
+}
+
+/// Feeds the events the way the main loop does, drawing after each one
+fn run_drawn(gui: &mut Gui, screen: &mut VirtualTerminal, input: &[u8]) {
+    let mut parser = EventParser::default();
+    let now = Instant::now();
+    for event in parser.parse(input).collect::<Vec<_>>() {
+        match gui.handle(event, now).unwrap() {
+            Flow::Redraw => {
+                gui.draw(screen).unwrap();
+                gui.redraw(screen).unwrap();
+            }
+            _ => gui.draw(screen).unwrap(),
+        }
+    }
+}
+
+/// Compares the screen with `tests/snapshots/<name>.txt`,
+/// run with `UPDATE_SNAPSHOTS=1` to write the new ones
+fn assert_snapshot(name: &str, screen: &VirtualTerminal) {
+    assert!(screen.unrecognized.is_empty(), "unrecognized output: {:?}", screen.unrecognized);
+    let actual = format!("{}---\n{}", screen.text(), screen.colors());
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "snapshots", &format!("{}.txt", name)].iter().collect();
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_else(|e| panic!("can't read {}: {}", path.display(), e));
+    assert!(expected == actual, "{} differs from the snapshot, got:\n{}", name, actual);
+}
+
+/// What the diffing got on the screen should match a full repaint of the same state
+fn assert_consistent(gui: &mut Gui, screen: &VirtualTerminal) {
+    let mut fresh = VirtualTerminal::new(SCREEN);
+    gui.redraw(&mut fresh).unwrap();
+    assert_eq!(screen.text(), fresh.text());
+    assert_eq!(screen.colors(), fresh.colors());
+}
+
+#[test]
+fn initial_screen() {
+    let mut gui = gui();
+    let mut screen = VirtualTerminal::new(SCREEN);
+    gui.draw(&mut screen).unwrap();
+    assert_snapshot("initial", &screen);
+}
+
+#[test]
+fn painting() {
+    let mut gui = gui();
+    let mut screen = VirtualTerminal::new(SCREEN);
+    gui.draw(&mut screen).unwrap();
+    run_drawn(&mut gui, &mut screen, b"\x1b[<0;5;4M\x1b[<32;7;5M\x1b[<32;11;6M\x1b[<0;11;6m\x1b[<2;21;10M\x1b[<2;21;10m");
+    assert_snapshot("painting", &screen);
+    assert_consistent(&mut gui, &screen);
+}
+
+#[test]
+fn color_picker() {
+    let mut gui = gui();
+    let mut screen = VirtualTerminal::new(SCREEN);
+    gui.draw(&mut screen).unwrap();
+    run_drawn(&mut gui, &mut screen, b"\x1b[<0;1;1M\x1b[<0;1;1m\x1b[<0;1;1M\x1b[<0;1;1m");
+    assert_snapshot("color_picker", &screen);
+    run_drawn(&mut gui, &mut screen, b"\x1b");
+    assert_consistent(&mut gui, &screen);
+}
+
+#[test]
+fn half_blocks() {
+    let mut gui = gui();
+    let mut screen = VirtualTerminal::new(SCREEN);
+    gui.draw(&mut screen).unwrap();
+    run_drawn(&mut gui, &mut screen, b"\x1b[<0;5;4M\x1b[<0;5;4mz");
+    assert_snapshot("half_blocks", &screen);
+    assert_consistent(&mut gui, &screen);
+}
+
+#[test]
+fn glyph_modes() {
+    let mut gui = gui();
+    let mut screen = VirtualTerminal::new(SCREEN);
+    gui.draw(&mut screen).unwrap();
+    // a diagonal line, then on to the quadrants and the sextants
+    run_drawn(&mut gui, &mut screen, b"\x1b[<0;3;2M\x1b[<32;5;3M\x1b[<32;7;4M\x1b[<32;9;5M\x1b[<0;9;5m");
+    // keys come one per chunk
+    run_drawn(&mut gui, &mut screen, b"z");
+    run_drawn(&mut gui, &mut screen, b"z");
+    assert_snapshot("quadrants", &screen);
+    assert_consistent(&mut gui, &screen);
+    run_drawn(&mut gui, &mut screen, b"z");
+    assert_snapshot("sextants", &screen);
+    assert_consistent(&mut gui, &screen);
+}
+
+#[test]
+fn brush_and_eraser() {
+    let mut gui = gui();
+    let mut screen = VirtualTerminal::new(SCREEN);
+    gui.draw(&mut screen).unwrap();
+    run_drawn(&mut gui, &mut screen, b"]");
+    run_drawn(&mut gui, &mut screen, b"]");
+    run_drawn(&mut gui, &mut screen, b"\x1b[<0;9;6M\x1b[<0;9;6m");
+    run_drawn(&mut gui, &mut screen, b"t");
+    run_drawn(&mut gui, &mut screen, b"[");
+    run_drawn(&mut gui, &mut screen, b"\x1b[<0;9;6M\x1b[<0;9;6m");
+    assert_snapshot("brush_and_eraser", &screen);
+    assert_consistent(&mut gui, &screen);
+}
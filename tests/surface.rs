@@ -0,0 +1,68 @@
+use entropic::draw::*;
+use entropic::state::*;
+use entropic::surface::*;
+use entropic::vt::VirtualTerminal;
+
+fn screen(state: &mut TerminalState) -> String {
+    let mut vt = VirtualTerminal::new(state.size());
+    state.draw(state.size(), &mut vt).unwrap();
+    vt.text()
+}
+
+#[test]
+fn clipped_to_the_surface() {
+    let mut state = TerminalState::new(Dimension { width: 10, height: 4 });
+    let mut surface = state.surface();
+    let mut panel = surface.sub(Position { x: 2, y: 1 }, Dimension { width: 4, height: 2 });
+    assert_eq!(panel.size(), Dimension { width: 4, height: 2 });
+    panel.text(Position { x: 0, y: 0 }, CellColor::none(), "abcdefgh");
+    panel.text(Position { x: 3, y: 1 }, CellColor::none(), "中");
+    panel.text(Position { x: 0, y: 2 }, CellColor::none(), "below");
+    panel.put(Position { x: 9, y: 0 }, CharCell::new('x'));
+    assert!(panel.get(Position { x: 4, y: 0 }).is_none());
+    assert_eq!(panel.get(Position { x: 3, y: 0 }).unwrap().char, 'd');
+
+    // nested ones are clipped to their parent too
+    let mut nested = panel.sub(Position { x: 1, y: 1 }, Dimension { width: 10, height: 10 });
+    assert_eq!(nested.size(), Dimension { width: 3, height: 1 });
+    assert_eq!(nested.origin(), Position { x: 3, y: 2 });
+    nested.put(Position { x: 0, y: 0 }, CharCell::new('1'));
+
+    assert_eq!(screen(&mut state), "\n  abcd\n   1\n\n");
+
+    // and so are the ones off the screen
+    let mut surface = state.surface();
+    let off = surface.sub(Position { x: 8, y: 3 }, Dimension { width: 5, height: 5 });
+    assert_eq!(off.size(), Dimension { width: 2, height: 1 });
+    let nothing = Surface::new(&mut state, Position { x: 20, y: 20 }, Dimension { width: 5, height: 5 });
+    assert_eq!(nothing.size(), Dimension { width: 0, height: 0 });
+}
+
+#[test]
+fn boxes_and_alignment() {
+    let mut state = TerminalState::new(Dimension { width: 9, height: 5 });
+    let mut surface = state.surface();
+    surface.fill(CharCell::new('.'));
+    let mut dialog = surface.sub(Position { x: 1, y: 0 }, Dimension { width: 7, height: 5 });
+    dialog.border(Border::Rounded, CellColor::none());
+    let mut inside = dialog.sub(Position { x: 1, y: 1 }, Dimension { width: 5, height: 3 });
+    inside.clear();
+    inside.aligned_text(0, Align::Left, CellColor::none(), "ab");
+    inside.aligned_text(1, Align::Center, CellColor::none(), "ab");
+    inside.aligned_text(2, Align::Right, CellColor::none(), "toolong");
+    assert_eq!(screen(&mut state), ".╭─────╮.\n.│ab   │.\n.│ ab  │.\n.│toolo│.\n.╰─────╯.\n");
+}
+
+#[test]
+fn overlays_have_to_fit() {
+    let mut state = TerminalState::new(Dimension { width: 10, height: 4 });
+    let mut surface = state.surface();
+    let mut panel = surface.sub(Position { x: 4, y: 1 }, Dimension { width: 4, height: 2 });
+    let image = |size| Overlay { pos: Position { x: 1, y: 0 }, size, bytes: b"\x1b_Gimage\x1b\\".to_vec(), clear: vec![] };
+    assert!(!panel.overlay(image(Dimension { width: 4, height: 1 })));
+    assert!(panel.overlay(image(Dimension { width: 3, height: 2 })));
+
+    let mut vt = VirtualTerminal::new(state.size());
+    state.draw(state.size(), &mut vt).unwrap();
+    assert_eq!(vt.graphics, vec![(Position { x: 5, y: 1 }, b"\x1b_Gimage\x1b\\".to_vec())]);
+}
@@ -0,0 +1,100 @@
+use entropic::command::parse;
+use entropic::gui::*;
+use entropic::state::*;
+use entropic::transform::*;
+
+mod common;
+use common::*;
+
+fn transformed(art_in: &str, transform: Transform) -> String {
+    let (pixels, size) = fixture(art_in);
+    let (pixels, size) = transform.apply(&pixels, size);
+    art(&pixels, size)
+}
+
+/// An L, which looks different whichever way it's flipped or turned
+const L: &str = "#.. #.. ###";
+
+#[test]
+fn flips_and_quarter_turns() {
+    assert_eq!(transformed(L, Transform::FlipHorizontal), "..#\n..#\n###");
+    assert_eq!(transformed(L, Transform::FlipVertical), "###\n#..\n#..");
+    assert_eq!(transformed(L, Transform::Rotate(90.0)), "###\n#..\n#..");
+    assert_eq!(transformed(L, Transform::Rotate(180.0)), "###\n..#\n..#");
+    assert_eq!(transformed(L, Transform::Rotate(-90.0)), "..#\n..#\n###");
+    assert_eq!(transformed(L, Transform::Rotate(720.0)), L.replace(' ', "\n"));
+    // the width and the height swap
+    assert_eq!(transformed("#.. ##o", Transform::Rotate(90.0)), "##\n#.\no.");
+}
+
+#[test]
+fn rotsprite() {
+    let bar = "........ ........ ........ oooooooo ######## ........ ........ ........";
+    assert_eq!(transformed(bar, Transform::Rotate(45.0)), "\
+........
+.#o.....
+.#oo....
+..##o...
+...##o..
+....##o.
+.....##.
+........");
+    assert_eq!(transformed(bar, Transform::Rotate(30.0)), "\
+........
+.o......
+#oo.....
+.##oo...
+...##oo.
+.....##o
+......#.
+........");
+
+    // wider than the picture scaled up 8x could be, and still turned
+    let (pixels, size) = fixture(&"#".repeat(8192));
+    let (turned, turned_size) = Transform::Rotate(1.0).apply(&pixels, size);
+    assert_eq!(turned_size, size);
+    assert_eq!(turned[4096], pixels[4096]);
+}
+
+#[test]
+fn shear() {
+    assert_eq!(transformed(L, Transform::Shear(1.0, 0.0)), "...\n#..\n.##");
+    assert_eq!(transformed("#### #### .... ....", Transform::Shear(0.0, 0.5)), "###.\n.###\n...#\n....");
+}
+
+#[test]
+fn commands() {
+    assert_eq!(parse("flip vertical"), Ok(Action::Transform(Transform::FlipVertical, Target::Layer)));
+    assert_eq!(parse("rotate -22.5 picture"), Ok(Action::Transform(Transform::Rotate(-22.5), Target::Picture)));
+    assert_eq!(parse("shear 0.5 0"), Ok(Action::Transform(Transform::Shear(0.5, 0.0), Target::Layer)));
+    assert_eq!(parse("flip diagonal"), Err("usage: flip horizontal|vertical [picture]".into()));
+    assert_eq!(parse("rotate right"), Err("not a number: right".into()));
+}
+
+#[test]
+fn keys_and_undo() {
+    let (pixels, size) = fixture("#.. ##o");
+    let mut gui = Gui::new(Dimension { width: 80, height: 30 }, Picture {
+        size,
+        layers: vec![
+            Layer { name: "Background".into(), pixels: pixels.clone().into_boxed_slice() },
+            Layer { name: "Layer 1".into(), pixels: pixels.into_boxed_slice() },
+        ],
+    });
+    let layer = |gui: &Gui, i: usize| art(&gui.state.picture.layers[i].pixels, gui.state.picture.size);
+
+    // the layer flips on its own, the whole picture with shift
+    type_in(&mut gui, "h");
+    assert_eq!((layer(&gui, 0).as_str(), layer(&gui, 1).as_str()), ("#..\n##o", "..#\no##"));
+    type_in(&mut gui, "R");
+    assert_eq!(gui.state.picture.size, Dimension { width: 2, height: 3 });
+    assert_eq!((layer(&gui, 0).as_str(), layer(&gui, 1).as_str()), ("##\n#.\no.", "o.\n#.\n##"));
+
+    // the angle is asked for
+    type_in(&mut gui, "a\x7f\x7f180\r");
+    assert_eq!(layer(&gui, 1), "##\n.#\n.o");
+
+    type_in(&mut gui, "\x1a\x1a\x1a");
+    assert_eq!(gui.state.picture.size, size);
+    assert_eq!(layer(&gui, 1), "#..\n##o");
+}